@@ -0,0 +1,417 @@
+//! Adams–Bashforth–Moulton methods of variable step and variable order used by `ODESolver::Adams`, in PECE mode (predict, evaluate, correct, evaluate), started with RK4 steps under error control.
+//!
+//! The RK4 steps at the start estimate their error with the embedded method of order 3 of weights (1/6, 1/3, 1/3, 0, 1/6), whose last stage is the differential at the new state, needed anyway for the history: the error is `step/6*(k4 - k5)`, without more evaluations.
+//!
+//! The coefficients for the unevenly spaced past times are the integrals of the Lagrange polynomials through them. The predictor of order k uses the last k differentials, the corrector (order k + 1) also the differential at the predicted state, and their difference estimates the local error of the order k. The corrected state is kept (local extrapolation) and the differences with the predictors of orders k - 1 and k + 1 choose the order of the next step.
+
+use crate::engine::{self, History, Schedule, Scalar, SolveStats, System, VectorSpace, Workspace};
+use crate::{collocation, lsoda, rosenbrock, next_tstop, ODEParam, ODESolver, Tolerance};
+
+pub(crate) const MAX_ORDER : usize = 12;
+// The RK4 steps at the start give the history for this order
+const START_ORDER : usize = 4;
+
+const SAFETY : f64 = 0.9;
+const MIN_FACTOR : f64 = 0.2;
+const MAX_FACTOR : f64 = 2.0;
+
+// Gauss–Legendre quadrature of 7 points on [-1, 1], exact for polynomials up to degree 13 (the corrector of order 13)
+const GAUSS : [(f64, f64); 7] = [
+    (0.0, 0.417_959_183_673_469_4),
+    (-0.405_845_151_377_397_2, 0.381_830_050_505_118_9),
+    (0.405_845_151_377_397_2, 0.381_830_050_505_118_9),
+    (-0.741_531_185_599_394_4, 0.279_705_391_489_276_7),
+    (0.741_531_185_599_394_4, 0.279_705_391_489_276_7),
+    (-0.949_107_912_342_758_5, 0.129_484_966_168_869_7),
+    (0.949_107_912_342_758_5, 0.129_484_966_168_869_7),
+];
+
+
+/// Past times, states and differentials of the multistep methods, kept in the `Workspace` between the steps, with the state of the method switching of `ODESolver::Lsoda` (see `engine::History`).
+pub(crate) struct Multistep<S> {
+    // the last one is the actual time
+    pub(crate) times : Vec<f64>,
+    pub(crate) states : Vec<S>,
+    pub(crate) dstates : Vec<S>,
+    // step to be tried next
+    pub(crate) step : f64,
+    pub(crate) order : usize,
+    pub(crate) stiff : bool,
+    pub(crate) switch_count : usize,
+    // rows of the jacobian of the BDF
+    pub(crate) jacobian : Vec<S>,
+    pub(crate) jacobian_age : usize,
+    // of the last step: estimate of the spectral radius of the jacobian (Adams) and ratio of the next step to the last one allowed by the error
+    pub(crate) spectral_radius : f64,
+    pub(crate) ratio : f64,
+    // past times relative to the actual time in units of the step, followed by 1 (the time of the step)
+    pub(crate) nodes : Vec<f64>,
+    pub(crate) weights : Vec<f64>,
+}
+
+impl<S> Multistep<S> {
+
+    pub(crate) fn new () -> Multistep<S> {
+        Multistep { times : Vec::new(), states : Vec::new(), dstates : Vec::new(), step : 0.0, order : 0, stiff : false, switch_count : 0,
+            jacobian : Vec::new(), jacobian_age : 0, spectral_radius : 0.0, ratio : 0.0, nodes : Vec::new(), weights : Vec::new() }
+    }
+
+    pub(crate) fn history<T : Scalar> (&self) -> History<T> where S : VectorSpace<T> {
+        let to_vec = |vectors : &Vec<S>| vectors.iter().map(|vector| vector.components().to_vec()).collect();
+        History {
+            step : self.step,
+            order : self.order,
+            times : self.times.clone(),
+            states : to_vec(&self.states),
+            dstates : to_vec(&self.dstates),
+            stiff : self.stiff,
+            switch_count : self.switch_count,
+            jacobian : to_vec(&self.jacobian),
+            jacobian_age : self.jacobian_age,
+        }
+    }
+
+    pub(crate) fn set_history<T : Scalar> (&mut self, history : &History<T>, like : &S) where S : VectorSpace<T> {
+        let size = like.components().len();
+        let sizes = |vectors : &Vec<Vec<T>>| vectors.iter().all(|vector| vector.len() == size);
+        // an empty history is valid, it starts the integration again; else the order has to be possible with the past times (one more for the BDF) and the step positive
+        let past = history.times.len();
+        let consistent = past == 0 || (history.order >= 1 && history.order <= past.min(MAX_ORDER)
+            && (!history.stiff || (history.order < past && history.order <= lsoda::MAX_ORDER_BDF))
+            && history.step.is_finite() && history.step > 0.0
+            && history.times.iter().all(|time| time.is_finite()) && history.times.windows(2).all(|times| times[0] < times[1]));
+        let valid = consistent && past <= MAX_ORDER + 1
+            && history.states.len() == history.times.len() && history.dstates.len() == history.times.len()
+            && (history.jacobian.is_empty() || history.jacobian.len() == size)
+            && sizes(&history.states) && sizes(&history.dstates) && sizes(&history.jacobian);
+        if !valid {
+            self.times.clear();
+            return;
+        }
+
+        let from_vec = |vectors : &Vec<Vec<T>>| vectors.iter().map(|vector| {
+            let mut state = like.zeros_like();
+            state.components_mut().copy_from_slice(vector);
+            state
+        }).collect();
+        self.times = history.times.clone();
+        self.states = from_vec(&history.states);
+        self.dstates = from_vec(&history.dstates);
+        self.step = history.step;
+        self.order = history.order;
+        self.stiff = history.stiff;
+        self.switch_count = history.switch_count;
+        self.jacobian = from_vec(&history.jacobian);
+        self.jacobian_age = history.jacobian_age;
+    }
+
+    // Starts a new history at `time`
+    pub(crate) fn restart<T : Scalar> (&mut self, time : f64, state : &S, dstate : &mut S, initial_step : f64) where S : VectorSpace<T> {
+        self.replace(time, state, dstate);
+        self.step = initial_step;
+        self.order = 1;
+        self.stiff = false;
+        self.switch_count = 0;
+        self.jacobian.clear();
+        self.spectral_radius = 0.0;
+    }
+
+    // Keeps only the state and the differential at `time`, as the one step solvers. `dstate` gets a buffer of the same size.
+    pub(crate) fn replace<T : Scalar> (&mut self, time : f64, state : &S, dstate : &mut S) where S : VectorSpace<T> {
+        if self.times.len() == 1 {
+            self.times[0] = time;
+            self.states[0].assign(state);
+            std::mem::swap(&mut self.dstates[0], dstate);
+        } else {
+            self.times.clear();
+            self.states.clear();
+            self.dstates.clear();
+            self.push(time, state, dstate);
+        }
+    }
+
+    // Adds the state and the differential at `time`, reusing the buffers of the oldest ones when the history is full. `dstate` gets a buffer of the same size.
+    pub(crate) fn push<T : Scalar> (&mut self, time : f64, state : &S, dstate : &mut S) where S : VectorSpace<T> {
+        let (mut state_buffer, dstate_buffer) = if self.times.len() > MAX_ORDER {
+            self.times.remove(0);
+            (self.states.remove(0), self.dstates.remove(0))
+        } else {
+            (state.zeros_like(), dstate.zeros_like())
+        };
+        state_buffer.assign(state);
+        self.times.push(time);
+        self.states.push(state_buffer);
+        self.dstates.push(std::mem::replace(dstate, dstate_buffer));
+    }
+}
+
+
+// Integrals over [0, 1] of the Lagrange polynomials of the `nodes`, the coefficients of the Adams methods in units of the step
+pub(crate) fn adams_weights (nodes : &[f64], weights : &mut Vec<f64>) {
+    weights.clear();
+    weights.resize(nodes.len(), 0.0);
+
+    for (x, w) in GAUSS {
+        let s = 0.5*(1.0 + x);
+        for (j, weight) in weights.iter_mut().enumerate() {
+            let mut lagrange = 1.0;
+            for (i, node) in nodes.iter().enumerate() {
+                if i != j {
+                    lagrange *= (s - node)/(nodes[j] - node);
+                }
+            }
+            *weight += 0.5*w*lagrange;
+        }
+    }
+}
+
+// `output = state + step*sum(weights[j]*dstates[j])`, with `last` after the `dstates` if given
+pub(crate) fn combine<T : Scalar, S : VectorSpace<T>> (output : &mut S, state : &S, step : f64, weights : &[f64], dstates : &[S], last : Option<&S>) {
+    output.assign(state);
+    for (weight, dstate) in weights.iter().zip(dstates.iter().chain(last)) {
+        output.axpy(step*weight, dstate);
+    }
+}
+
+// Root mean square of the difference of `a` and `b` relative to the tolerance of each component
+pub(crate) fn error_norm<T : Scalar, S : VectorSpace<T>> (tolerance : Tolerance, state : &S, a : &S, b : &S) -> f64 {
+    let mut sum = 0.0;
+    for ((y, a), b) in state.components().iter().zip(a.components()).zip(b.components()) {
+        let scale = tolerance.atol + tolerance.rtol*y.magnitude().max(a.magnitude());
+        let error = (*a - *b).magnitude()/scale;
+        sum += error*error;
+    }
+    (sum/state.components().len().max(1) as f64).sqrt()
+}
+
+// Ratio of the next step to the last one for the estimated `error` of order `order`
+pub(crate) fn step_ratio (error : f64, order : usize) -> f64 {
+    SAFETY*error.powf(-1.0/(order as f64 + 1.0))
+}
+
+// Ratio of the next step limited to the allowed change
+pub(crate) fn step_factor (error : f64, order : usize) -> f64 {
+    step_ratio(error, order).clamp(MIN_FACTOR, MAX_FACTOR)
+}
+
+// Root mean square of `vector` relative to the tolerance of each component of `state`
+fn scaled_norm<T : Scalar, S : VectorSpace<T>> (tolerance : Tolerance, state : &S, vector : &S) -> f64 {
+    let sum : f64 = state.components().iter().zip(vector.components())
+        .map(|(y, x)| (x.magnitude()/(tolerance.atol + tolerance.rtol*y.magnitude())).powi(2)).sum();
+    (sum/state.components().len().max(1) as f64).sqrt()
+}
+
+// Root mean square of the difference of `a` and `b`
+fn difference_norm<T : Scalar, S : VectorSpace<T>> (a : &S, b : &S) -> f64 {
+    let sum : f64 = a.components().iter().zip(b.components()).map(|(a, b)| (*a - *b).magnitude().powi(2)).sum();
+    (sum/a.components().len().max(1) as f64).sqrt()
+}
+
+
+// First step for the `tolerance`, of at most `max_step`, from the differential at the start of the history: the step of RK4 whose local error, estimated with the second derivative, is about the tolerance, as in Hairer, Nørsett and Wanner
+fn start_step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, max_step : f64, tolerance : Tolerance, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) -> f64 {
+    let Workspace { k1, k2, stage, multistep, .. } = workspace;
+    let dstate = &multistep.dstates[0];
+
+    let d0 = scaled_norm(tolerance, system.state(), system.state());
+    let d1 = scaled_norm(tolerance, system.state(), dstate);
+    let h0 = if d0 < 1.0e-5 || d1 < 1.0e-5 { 1.0e-6 } else { 0.01*d0/d1 }.min(max_step);
+
+    stage.assign_axpy(system.state(), h0, dstate);
+    system.dstate_at(time + h0, stage, k1);
+    stats.fevals += 1;
+    k2.assign(k1);
+    k2.axpy(-1.0, dstate);
+    let d2 = scaled_norm(tolerance, system.state(), k2)/h0;
+
+    let h1 = if d1.max(d2) <= 1.0e-15 { (1.0e-3*h0).max(1.0e-6) } else { (0.01/d1.max(d2)).powf(1.0/(START_ORDER as f64 + 1.0)) };
+    let step = (100.0*h0).min(h1).min(max_step);
+    if step.is_finite() && step > 0.0 { step } else { max_step }
+}
+
+
+// Makes one accepted RK4 step from `time`, of at most `goal - time`, with the error of the embedded method of order 3, and adds it to the history
+fn rk4_step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, goal : f64, tolerance : Tolerance, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) -> f64 {
+    loop {
+        let proposed = workspace.multistep.step;
+        let clipped = time + proposed >= goal;
+        let (step, new_time) = if clipped { (goal - time, goal) } else { (proposed, time + proposed) };
+
+        engine::rk4(system, time, step, workspace);
+        stats.fevals += 4;
+
+        let Workspace { k1, k4, stage, multistep, .. } = workspace;
+        system.dstate(new_time, k1);
+        stats.fevals += 1;
+        stage.assign(system.state());
+        stage.axpy(-step/6.0, k4);
+        stage.axpy(step/6.0, k1);
+        let error = error_norm(tolerance, system.state(), system.state(), stage);
+        let too_small = time + step == time;
+
+        // a step whose error is not finite (e.g. unstable at a stiff start) is also rejected
+        if (error > 1.0 || error.is_nan()) && !too_small {
+            let last = multistep.states.len() - 1;
+            system.update_state(&multistep.states[last]);
+            multistep.step = if error.is_finite() { step*step_factor(error, 3).min(1.0) } else { 0.5*step };
+            stats.rejected_steps += 1;
+            continue;
+        }
+
+        stats.accepted_steps += 1;
+        multistep.push(new_time, system.state(), k1);
+        multistep.order = multistep.times.len().min(START_ORDER);
+        multistep.spectral_radius = 0.0;
+        multistep.ratio = 1.0;
+        if error.is_finite() {
+            multistep.step = step*step_factor(error, 3);
+            if clipped {
+                multistep.step = multistep.step.max(proposed);
+            }
+        }
+        return new_time;
+    }
+}
+
+
+// Makes one accepted step from `time`, of at most `goal - time`, and returns the time reached. Without a history at `time` the integration starts again with RK4 steps under error control, the first one for the `tolerance` of at most `initial_step`.
+pub(crate) fn step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, goal : f64, initial_step : f64, tolerance : Tolerance, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) -> f64 {
+
+    if workspace.multistep.times.last() != Some(&time) {
+        let Workspace { k4, multistep, .. } = workspace;
+        system.dstate(time, k4);
+        stats.fevals += 1;
+        multistep.restart(time, system.state(), k4, initial_step);
+        workspace.multistep.step = start_step(system, time, initial_step, tolerance, workspace, stats);
+    }
+
+    if workspace.multistep.times.len() < START_ORDER {
+        return rk4_step(system, time, goal, tolerance, workspace, stats);
+    }
+
+    let Workspace { k1, k2, k3, k4, stage, multistep, .. } = workspace;
+    let order = multistep.order;
+
+    loop {
+        let proposed = multistep.step;
+        let clipped = time + proposed >= goal;
+        let (step, new_time) = if clipped { (goal - time, goal) } else { (proposed, time + proposed) };
+
+        let past = multistep.times.len();
+        multistep.nodes.clear();
+        multistep.nodes.extend(multistep.times.iter().map(|past_time| (past_time - time)/step));
+        multistep.nodes.push(1.0);
+        let nodes = &multistep.nodes;
+
+        // predict and evaluate
+        adams_weights(&nodes[past - order .. past], &mut multistep.weights);
+        combine(stage, system.state(), step, &multistep.weights, &multistep.dstates[past - order ..], None);
+        system.dstate_at(new_time, stage, k1);
+
+        // correct
+        adams_weights(&nodes[past - order ..], &mut multistep.weights);
+        combine(k2, system.state(), step, &multistep.weights, &multistep.dstates[past - order ..], Some(k1));
+        stats.fevals += 1;
+
+        let error = error_norm(tolerance, system.state(), k2, stage);
+        let too_small = time + step == time;
+
+        // a step whose error is not finite (e.g. unstable at a stiff start) is also rejected
+        if (error > 1.0 || error.is_nan()) && !too_small {
+            multistep.step = if error.is_finite() { step*step_factor(error, order).min(1.0) } else { 0.5*step };
+            stats.rejected_steps += 1;
+            continue;
+        }
+
+        // order of the next step, by the errors of the predictors of the neighbouring orders
+        let mut new_order = order;
+        let mut new_error = error;
+        if order > 1 {
+            adams_weights(&nodes[past - order + 1 .. past], &mut multistep.weights);
+            combine(k3, system.state(), step, &multistep.weights, &multistep.dstates[past - order + 1 ..], None);
+            let lower = error_norm(tolerance, system.state(), k2, k3);
+            if step_ratio(lower, order - 1) > step_ratio(new_error, new_order) {
+                new_order = order - 1;
+                new_error = lower;
+            }
+        }
+        if order < MAX_ORDER && past > order {
+            adams_weights(&nodes[past - order - 1 .. past], &mut multistep.weights);
+            combine(k3, system.state(), step, &multistep.weights, &multistep.dstates[past - order - 1 ..], None);
+            let higher = error_norm(tolerance, system.state(), k2, k3);
+            if step_ratio(higher, order + 1) > step_ratio(new_error, new_order) {
+                new_order = order + 1;
+                new_error = higher;
+            }
+        }
+
+        // evaluate
+        system.dstate_at(new_time, k2, k4);
+        stats.fevals += 1;
+        stats.accepted_steps += 1;
+        multistep.spectral_radius = difference_norm(k4, k1)/difference_norm(k2, stage);
+        system.update_state(k2);
+        multistep.push(new_time, system.state(), k4);
+
+        multistep.order = new_order;
+        multistep.ratio = step_ratio(new_error, new_order);
+        if error.is_finite() {
+            multistep.step = step*step_factor(new_error, new_order);
+            if clipped {
+                multistep.step = multistep.step.max(proposed);
+            }
+        }
+        return new_time;
+    }
+}
+
+
+// Makes the steps of the adaptive `odesolver` from `time` up to `goal`, where it lands exactly
+pub(crate) fn advance_to<T : Scalar, Sys : System<T>> (system : &mut Sys, mut time : f64, goal : f64, initial_step : f64, odesolver : ODESolver, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) -> f64 {
+    while time < goal {
+        time = match odesolver {
+            ODESolver::Lsoda(tolerance) => lsoda::step(system, time, goal, initial_step, tolerance, workspace, stats),
+            ODESolver::Adams(tolerance) => step(system, time, goal, initial_step, tolerance, workspace, stats),
+            ODESolver::Ros3p(_) | ODESolver::Rodas4(_) => rosenbrock::step(system, time, goal, initial_step, odesolver, workspace, stats),
+            ODESolver::Radau5(tolerance) => collocation::radau_step(system, time, goal, initial_step, tolerance, workspace, stats),
+            _ => unreachable!("{:?} is not an adaptive solver", odesolver),
+        };
+    }
+    time
+}
+
+
+// Makes the steps up to the next output time (`odeparam.step*odeparam.ratio_step_output` after the last output time on the grid of the `schedule`, as the one step solvers), landing at its `tstops`, where the solver starts again
+pub(crate) fn integrator<T : Scalar, Sys : System<T>> (system : &mut Sys, odeparam : ODEParam, odesolver : ODESolver, workspace : &mut Workspace<Sys::State>, schedule : &mut Schedule, stats : &mut SolveStats) -> ODEParam {
+    let mut time = odeparam.time;
+    let tend = odeparam.tend;
+
+    let mut output_time = schedule.grid_time;
+    for _i in 0 .. odeparam.ratio_step_output {
+        output_time += odeparam.step;
+    }
+
+    loop {
+        let tstop = next_tstop(schedule.tstops, time, tend);
+        if output_time - tstop >= 0.0 {
+            time = advance_to(system, time, tstop, odeparam.step, odesolver, workspace, stats);
+            // the system may change at a tstop, the solver starts again without the history before it
+            if tstop < tend {
+                workspace.multistep.times.clear();
+            }
+            if tstop == output_time {
+                schedule.grid_time = output_time;
+            }
+            if tstop == tend || tstop == output_time || schedule.output_tstops {
+                break;
+            }
+        } else {
+            time = advance_to(system, time, output_time, odeparam.step, odesolver, workspace, stats);
+            schedule.grid_time = output_time;
+            break;
+        }
+    }
+
+    ODEParam {
+        time, .. odeparam}
+}
@@ -0,0 +1,281 @@
+pub use super::*;
+use crate::engine::History;
+use crate::sink::OutputSink;
+use std::fs;
+use std::io::{self, Read};
+
+const MAGIC : &[u8] = b"ODECKPT\x03";
+// Format of the checkpoints with the history of `ODESolver::Adams` only: step, order, times and differentials, without the past states
+const MAGIC_V2 : &[u8] = b"ODECKPT\x02";
+// Format of the checkpoints without the history of the multistep solvers, which is always empty in them
+const MAGIC_V1 : &[u8] = b"ODECKPT\x01";
+
+/// Struct Checkpoint
+///
+/// Full state of an integration at an output time: the `odeparam` (with `time` at the checkpoint and the integration `step`), the `odesolver`, the `state` of the system, the solver `history` (see `engine::History`, empty for one step methods) and the number of `output_rows` given to the sink up to (and including) the checkpoint.
+///
+/// The values are stored with their exact bits, so resuming from a checkpoint gives the same output as the uninterrupted integration.
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    pub odeparam : ODEParam,
+    pub odesolver : ODESolver,
+    pub state : Vec<f64>,
+    pub history : History,
+    pub output_rows : u64,
+}
+
+
+fn solver_to_byte (odesolver : ODESolver) -> u8 {
+    match odesolver {
+        ODESolver::RK4 => 0,
+        ODESolver::Euler => 1,
+        ODESolver::Adams(_) => 2,
+        ODESolver::Lsoda(_) => 3,
+        ODESolver::Ros3p(_) => 4,
+        ODESolver::Rodas4(_) => 5,
+        ODESolver::Radau5(_) => 6,
+        ODESolver::GaussLegendre => 7,
+        ODESolver::BackwardEuler => 8,
+        ODESolver::Trapezoidal => 9,
+    }
+}
+
+fn byte_to_solver (byte : u8, reader : &mut Reader) -> Result<ODESolver, Box<dyn Error>> {
+    match byte {
+        0 => Ok(ODESolver::RK4),
+        1 => Ok(ODESolver::Euler),
+        2 => Ok(ODESolver::Adams(Tolerance { rtol : reader.f64()?, atol : reader.f64()? })),
+        3 => Ok(ODESolver::Lsoda(Tolerance { rtol : reader.f64()?, atol : reader.f64()? })),
+        4 => Ok(ODESolver::Ros3p(Tolerance { rtol : reader.f64()?, atol : reader.f64()? })),
+        5 => Ok(ODESolver::Rodas4(Tolerance { rtol : reader.f64()?, atol : reader.f64()? })),
+        6 => Ok(ODESolver::Radau5(Tolerance { rtol : reader.f64()?, atol : reader.f64()? })),
+        7 => Ok(ODESolver::GaussLegendre),
+        8 => Ok(ODESolver::BackwardEuler),
+        9 => Ok(ODESolver::Trapezoidal),
+        _ => Err(invalid(format!("Unknown solver {} in checkpoint", byte))),
+    }
+}
+
+fn invalid (message : String) -> Box<dyn Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+
+fn push_vector (bytes : &mut Vec<u8>, vector : &[f64]) {
+    bytes.extend_from_slice(&(vector.len() as u64).to_le_bytes());
+    for value in vector {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn push_vectors (bytes : &mut Vec<u8>, vectors : &[Vec<f64>]) {
+    bytes.extend_from_slice(&(vectors.len() as u64).to_le_bytes());
+    for vector in vectors {
+        push_vector(bytes, vector);
+    }
+}
+
+// Reads the binary checkpoint format
+struct Reader<'a> {
+    bytes : &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take (&mut self, n : usize) -> Result<&[u8], Box<dyn Error>> {
+        if self.bytes.len() < n {
+            return Err(invalid("Truncated checkpoint".to_string()));
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u64 (&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn f64 (&mut self) -> Result<f64, Box<dyn Error>> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn vector (&mut self) -> Result<Vec<f64>, Box<dyn Error>> {
+        let len = self.u64()? as usize;
+        (0..len).map(|_| self.f64()).collect()
+    }
+
+    fn vectors (&mut self) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+        let len = self.u64()? as usize;
+        (0..len).map(|_| self.vector()).collect()
+    }
+}
+
+
+impl Checkpoint {
+
+    /// Serializes the checkpoint in a binary format.
+    pub fn to_bytes (&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.odeparam.time.to_le_bytes());
+        bytes.extend_from_slice(&self.odeparam.tend.to_le_bytes());
+        bytes.extend_from_slice(&self.odeparam.step.to_le_bytes());
+        bytes.extend_from_slice(&(self.odeparam.ratio_step_output as u64).to_le_bytes());
+        bytes.push(solver_to_byte(self.odesolver));
+        if let ODESolver::Adams(tolerance) | ODESolver::Lsoda(tolerance) | ODESolver::Ros3p(tolerance) | ODESolver::Rodas4(tolerance) | ODESolver::Radau5(tolerance) = self.odesolver {
+            bytes.extend_from_slice(&tolerance.rtol.to_le_bytes());
+            bytes.extend_from_slice(&tolerance.atol.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.output_rows.to_le_bytes());
+        push_vector(&mut bytes, &self.state);
+        bytes.extend_from_slice(&self.history.step.to_le_bytes());
+        bytes.extend_from_slice(&(self.history.order as u64).to_le_bytes());
+        push_vector(&mut bytes, &self.history.times);
+        push_vectors(&mut bytes, &self.history.states);
+        push_vectors(&mut bytes, &self.history.dstates);
+        bytes.push(self.history.stiff as u8);
+        bytes.extend_from_slice(&(self.history.switch_count as u64).to_le_bytes());
+        push_vectors(&mut bytes, &self.history.jacobian);
+        bytes.extend_from_slice(&(self.history.jacobian_age as u64).to_le_bytes());
+        bytes
+    }
+
+    /// Deserializes a checkpoint written by `to_bytes`, also by older versions of the library.
+    ///
+    /// The checkpoints of the older versions have no history (version 1) or the history of `ODESolver::Adams` without the past states (version 2). Their history is dropped, so the adaptive solvers restart at the checkpoint time and the output after it is close to, but not exactly, the one of the uninterrupted integration.
+    pub fn from_bytes (bytes : &[u8]) -> Result<Checkpoint, Box<dyn Error>> {
+        let mut reader = Reader { bytes };
+        let magic = reader.take(MAGIC.len())?;
+        let version = if magic == MAGIC {
+            3
+        } else if magic == MAGIC_V2 {
+            2
+        } else if magic == MAGIC_V1 {
+            1
+        } else {
+            return Err(invalid("Not a checkpoint file".to_string()));
+        };
+        let odeparam = ODEParam {
+            time : reader.f64()?,
+            tend : reader.f64()?,
+            step : reader.f64()?,
+            ratio_step_output : reader.u64()? as usize,
+        };
+        let byte = reader.take(1)?[0];
+        let odesolver = byte_to_solver(byte, &mut reader)?;
+        let output_rows = reader.u64()?;
+        let state = reader.vector()?;
+        let history = match version {
+            1 => History::default(),
+            2 => {
+                // step, order, times and differentials, not enough to continue the multistep method
+                reader.f64()?;
+                reader.u64()?;
+                reader.vector()?;
+                reader.vectors()?;
+                History::default()
+            },
+            _ => {
+                let step = reader.f64()?;
+                let order = reader.u64()? as usize;
+                let times = reader.vector()?;
+                let states = reader.vectors()?;
+                let dstates = reader.vectors()?;
+                let stiff = reader.take(1)?[0] != 0;
+                let switch_count = reader.u64()? as usize;
+                let jacobian = reader.vectors()?;
+                let jacobian_age = reader.u64()? as usize;
+                History { step, order, times, states, dstates, stiff, switch_count, jacobian, jacobian_age }
+            },
+        };
+
+        Ok(Checkpoint { odeparam, odesolver, state, history, output_rows })
+    }
+
+    /// Saves the checkpoint to a given filename/filepath.
+    ///
+    /// It is first written to a temporary file which then replaces the given one, so a preemption while saving does not destroy the previous checkpoint.
+    pub fn to_file (&self, file_as_string : String) -> Result<(), Box<dyn Error>> {
+        let temporary = format!("{}.tmp", file_as_string);
+        fs::write(&temporary, self.to_bytes())?;
+        fs::rename(&temporary, &file_as_string)?;
+        Ok(())
+    }
+
+    /// Reads a checkpoint from a given filename/filepath.
+    pub fn from_file (file_as_string : String) -> Result<Checkpoint, Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        fs::File::open(file_as_string)?.read_to_end(&mut bytes)?;
+        Checkpoint::from_bytes(&bytes)
+    }
+}
+
+
+/// Sink which saves a `Checkpoint` to a file at regular intervals of output rows, forwarding the rows to another sink.
+///
+/// The fixed step solvers restart the integration from the time and state at each output row, so these are the full state of the integrator. The adaptive solvers also need their history (the next step and, for the multistep `ODESolver::Adams` and `ODESolver::Lsoda`, past states and differentials, order and the state of the method switching), which is saved in `Checkpoint.history` and given back to the solver by the sink of `CheckpointSink::resume`. To resume, the state of the system is set to `Checkpoint.state` and the same solver is called with `Checkpoint.odeparam`, `Checkpoint.odesolver` and a sink made with `CheckpointSink::resume`. The rows given to the inner sink are then the same as the rows after the checkpoint of the uninterrupted integration.
+pub struct CheckpointSink<'a, Sink : OutputSink + ?Sized> {
+    sink : &'a mut Sink,
+    file_as_string : String,
+    every : u64,
+    odeparam : ODEParam,
+    odesolver : ODESolver,
+    output_rows : u64,
+    skip_first : bool,
+    history : History,
+    resume : Option<History>,
+}
+
+impl<'a, Sink : OutputSink + ?Sized> CheckpointSink<'a, Sink> {
+
+    /// Creates a sink which saves a checkpoint to `file_as_string` every `every` output rows. `odeparam` and `odesolver` are the ones given to the solver.
+    pub fn new (sink : &'a mut Sink, file_as_string : String, every : u64, odeparam : ODEParam, odesolver : ODESolver) -> Self {
+        CheckpointSink { sink, file_as_string, every : every.max(1), odeparam, odesolver, output_rows : 0, skip_first : false, history : History::default(), resume : None }
+    }
+
+    /// Creates a sink to resume the integration from `checkpoint`. The first row given by the solver, at the checkpoint time, was already given to the inner sink before the checkpoint, so it is skipped.
+    pub fn resume (sink : &'a mut Sink, file_as_string : String, every : u64, checkpoint : &Checkpoint) -> Self {
+        CheckpointSink { sink, file_as_string, every : every.max(1), odeparam : checkpoint.odeparam.clone(), odesolver : checkpoint.odesolver, output_rows : checkpoint.output_rows, skip_first : true,
+            history : History::default(), resume : Some(checkpoint.history.clone()) }
+    }
+}
+
+impl<Sink : OutputSink + ?Sized> OutputSink for CheckpointSink<'_, Sink> {
+    fn push (&mut self, time : f64, state : &[f64]) -> Result<(), Box<dyn Error>> {
+        if self.skip_first {
+            self.skip_first = false;
+            return Ok(());
+        }
+
+        self.sink.push(time, state)?;
+        self.output_rows += 1;
+
+        if self.output_rows.is_multiple_of(self.every) {
+            let checkpoint = Checkpoint {
+                odeparam : ODEParam { time, .. self.odeparam.clone() },
+                odesolver : self.odesolver,
+                state : state.to_vec(),
+                history : self.history.clone(),
+                output_rows : self.output_rows,
+            };
+            checkpoint.to_file(self.file_as_string.clone())?;
+        }
+
+        Ok(())
+    }
+
+    fn finish (&mut self) -> Result<(), Box<dyn Error>> {
+        self.sink.finish()
+    }
+
+    fn needs_history (&self) -> bool {
+        true
+    }
+
+    fn push_history (&mut self, history : History) {
+        self.history = history;
+    }
+
+    fn resume_history (&mut self) -> Option<History> {
+        self.resume.take()
+    }
+}
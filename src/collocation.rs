@@ -0,0 +1,307 @@
+//! Implicit Runge–Kutta collocation methods of three stages: Radau IIA of order 5 used by `ODESolver::Radau5` (stiffly accurate and L-stable, with the step chosen by the error estimate of RADAU5 of Hairer and Wanner) and Gauss–Legendre of order 6 used by `ODESolver::GaussLegendre` (symplectic, with a fixed step).
+//!
+//! The stage increments `z` solve `z = h*(A⊗I)*f(t + c*h, y + z)` by simplified Newton iterations with one Jacobian J. In the variables `w = (T^-1⊗I)*z`, where `T^-1*A^-1*T` has the real eigenvalue `gamma` of `A^-1` and the block of its complex pair `alpha ± i*beta`, the linear system of each iteration splits into the real system of `gamma/h*I - J` and the complex one of `(alpha - i*beta)/h*I - J`, solved in its real form.
+
+use crate::adams::{error_norm, step_factor};
+use crate::engine::{Scalar, SolveStats, System, VectorSpace, Workspace};
+use crate::implicit::{self, factorize, factorize_complex, solve_complex_in_place, solve_in_place};
+use crate::linalg::LU;
+use crate::Tolerance;
+
+
+/// Coefficients of a collocation method and the decomposition of the inverse of its matrix A.
+struct Collocation {
+    c : [f64; 3],
+    gamma : f64,
+    alpha : f64,
+    beta : f64,
+    t : [[f64; 3]; 3],
+    t_inv : [[f64; 3]; 3],
+    // the new state is `y + sum(d[i]*z[i])`
+    d : [f64; 3],
+    newton_iterations : usize,
+}
+
+const RADAU5 : Collocation = Collocation {
+    c : [0.155_051_025_721_682_2, 0.644_948_974_278_317_8, 1.0],
+    gamma : 3.637_834_252_744_496,
+    alpha : 2.681_082_873_627_752,
+    beta : 3.050_430_199_247_410_5,
+    t : [
+        [0.094_438_762_488_975_24, -0.141_255_295_020_954_2, 0.030_029_194_105_147_424],
+        [0.250_213_122_965_333_3, 0.204_129_352_293_799_94, -0.382_942_112_757_261_9],
+        [1.0, 1.0, 0.0],
+    ],
+    t_inv : [
+        [4.178_718_591_551_904, 0.327_682_820_761_062_37, 0.523_376_445_499_449_5],
+        [-4.178_718_591_551_904, -0.327_682_820_761_062_37, 0.476_623_554_500_550_44],
+        [0.502_872_634_945_786_9, -2.571_926_949_855_605, 0.596_039_204_828_224_9],
+    ],
+    d : [0.0, 0.0, 1.0],
+    newton_iterations : 7,
+};
+
+// The error of Radau IIA is estimated as `(gamma/h*I - J)^-1*(f(t, y) + sum(e[i]*z[i])/h)`
+const RADAU_ERROR : [f64; 3] = [-10.048_809_399_827_416, 1.382_142_733_160_749, -1.0/3.0];
+
+const GAUSS6 : Collocation = Collocation {
+    c : [0.112_701_665_379_258_31, 0.5, 0.887_298_334_620_741_7],
+    gamma : 4.644_370_709_252_171,
+    alpha : 3.677_814_645_373_914_4,
+    beta : 3.508_761_919_567_443_3,
+    t : [
+        [0.072_151_852_055_200_17, -0.082_241_230_573_630_67, 0.060_120_738_619_308_5],
+        [0.118_832_578_741_277_81, 0.053_065_090_742_061_394, -0.316_205_051_132_291_6],
+        [1.0, 1.0, 0.0],
+    ],
+    t_inv : [
+        [5.991_698_084_937_801, 1.139_214_295_155_735_4, 0.432_312_113_783_858_36],
+        [-5.991_698_084_937_801, -1.139_214_295_155_735_4, 0.567_687_886_216_141_6],
+        [1.246_213_273_586_231_5, -2.925_559_646_192_313_7, 0.257_735_201_273_432_47],
+    ],
+    d : [5.0/3.0, -4.0/3.0, 5.0/3.0],
+    newton_iterations : 10,
+};
+
+// Tolerance of the Newton iterations relative to the tolerance of the step
+const NEWTON_TOLERANCE : f64 = 0.03;
+// The Newton iterations of the fixed step Gauss–Legendre method go down almost to the rounding errors, to keep it symplectic
+const GAUSS_TOLERANCE : Tolerance = Tolerance { rtol : 1.0e-12, atol : 1.0e-12 };
+// The Jacobian of Radau IIA is kept for the next step if the Newton iterations converged faster than this rate
+const JACOBIAN_RATE : f64 = 1.0e-3;
+
+
+// Factorized linear systems of the Newton iterations of `method` for a step
+struct Newton<'a, T> {
+    method : &'a Collocation,
+    step : f64,
+    real : LU<T>,
+    complex : LU<T>,
+}
+
+impl<'a, T : Scalar> Newton<'a, T> {
+
+    fn new<S : VectorSpace<T>> (method : &'a Collocation, step : f64, jacobian : &[S]) -> Option<Newton<'a, T>> {
+        Some(Newton {
+            method,
+            step,
+            real : factorize(method.gamma/step, jacobian)?,
+            complex : factorize_complex(method.alpha/step, method.beta/step, jacobian)?,
+        })
+    }
+
+    // Iterations from `z = 0` for the step from `time` and the actual state, leaving in `stages` the increments `z`, the variables `w` and the differentials at the stages, three of each. Returns the rate of convergence, or `None` if they diverge or do not converge.
+    fn solve<Sys : System<T>> (&self, system : &mut Sys, time : f64, tolerance : Tolerance, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) -> Option<f64> {
+        let Workspace { k1, k2, k3, stage, stages, .. } = workspace;
+        let (z, rest) = stages.split_at_mut(3);
+        let (w, f) = rest.split_at_mut(3);
+        let method = self.method;
+        let step = self.step;
+
+        for vector in z.iter_mut().chain(w.iter_mut()) {
+            vector.components_mut().fill(T::zero());
+        }
+
+        let mut previous = 0.0;
+        let mut rate = 0.0;
+        let mut factor = 1.0;
+        for iteration in 0 .. method.newton_iterations {
+            for i in 0 .. 3 {
+                stage.assign(system.state());
+                stage.axpy(1.0, &z[i]);
+                system.dstate_at(time + method.c[i]*step, stage, &mut f[i]);
+            }
+            stats.fevals += 3;
+
+            // right sides in the variables w, with the block of `T^-1*A^-1*T`
+            transform(k1, &method.t_inv[0], f);
+            k1.axpy(-method.gamma/step, &w[0]);
+            transform(k2, &method.t_inv[1], f);
+            k2.axpy(-method.alpha/step, &w[1]);
+            k2.axpy(-method.beta/step, &w[2]);
+            transform(k3, &method.t_inv[2], f);
+            k3.axpy(method.beta/step, &w[1]);
+            k3.axpy(-method.alpha/step, &w[2]);
+
+            solve_in_place(&self.real, k1);
+            solve_complex_in_place(&self.complex, k2, k3);
+
+            let mut sum = 0.0;
+            for delta in [&*k1, &*k2, &*k3] {
+                for (d, y) in delta.components().iter().zip(system.state().components()) {
+                    sum += (d.magnitude()/(tolerance.atol + tolerance.rtol*y.magnitude())).powi(2);
+                }
+            }
+            let norm = (sum/(3*k1.components().len()).max(1) as f64).sqrt();
+
+            w[0].axpy(1.0, k1);
+            w[1].axpy(1.0, k2);
+            w[2].axpy(1.0, k3);
+            for (z, row) in z.iter_mut().zip(&method.t) {
+                transform(z, row, w);
+            }
+
+            if !norm.is_finite() {
+                return None;
+            }
+            if iteration > 0 {
+                rate = norm/previous;
+                if rate >= 0.99 {
+                    return None;
+                }
+                factor = rate/(1.0 - rate);
+            }
+            if factor*norm <= NEWTON_TOLERANCE {
+                return Some(rate);
+            }
+            previous = norm;
+        }
+        None
+    }
+}
+
+// `output = sum(weights[j]*vectors[j])`
+fn transform<T : Scalar, S : VectorSpace<T>> (output : &mut S, weights : &[f64; 3], vectors : &[S]) {
+    output.components_mut().fill(T::zero());
+    for (weight, vector) in weights.iter().zip(vectors) {
+        output.axpy(*weight, vector);
+    }
+}
+
+
+// Makes one accepted step of Radau IIA from `time`, of at most `goal - time`, and returns the time reached. Without a history at `time` the integration starts again with a step of `initial_step`.
+pub(crate) fn radau_step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, goal : f64, initial_step : f64, tolerance : Tolerance, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) -> f64 {
+
+    // the first error estimate is improved after a rejection, and at the start
+    let mut rejected = false;
+    if workspace.multistep.times.last() != Some(&time) {
+        let Workspace { k4, multistep, .. } = workspace;
+        system.dstate(time, k4);
+        stats.fevals += 1;
+        multistep.restart(time, system.state(), k4, initial_step);
+        rejected = true;
+    }
+    let zeros = workspace.k1.zeros_like();
+    workspace.stages.resize(9, zeros);
+
+    loop {
+        if workspace.multistep.jacobian.is_empty() {
+            let Workspace { k1, stage, multistep, .. } = workspace;
+            let dstate = multistep.dstates.last().expect("history of Radau IIA");
+            stats.fevals += implicit::jacobian(system, time, dstate, &mut multistep.jacobian, stage, k1);
+            multistep.jacobian_age = 0;
+        }
+
+        let proposed = workspace.multistep.step;
+        let clipped = time + proposed >= goal;
+        let (step, new_time) = if clipped { (goal - time, goal) } else { (proposed, time + proposed) };
+        let too_small = time + step == time;
+
+        let converged = Newton::new(&RADAU5, step, &workspace.multistep.jacobian)
+            .and_then(|newton| newton.solve(system, time, tolerance, workspace, stats).map(|rate| (newton, rate)));
+        let (newton, rate) = match converged {
+            Some(converged) => converged,
+            None if !too_small => {
+                // with a new jacobian, else with a smaller step
+                stats.rejected_steps += 1;
+                rejected = true;
+                let multistep = &mut workspace.multistep;
+                if multistep.jacobian_age > 0 {
+                    multistep.jacobian.clear();
+                } else {
+                    multistep.step = 0.5*step;
+                }
+                continue;
+            },
+            None => {
+                // no step is possible, the state which is not finite stops the integration
+                let Workspace { k3, .. } = workspace;
+                k3.components_mut().fill(T::from_f64(f64::NAN));
+                system.update_state(k3);
+                return goal;
+            },
+        };
+
+        let Workspace { k1, k2, k3, k4, stage, stages, multistep, .. } = workspace;
+        k3.assign(system.state());
+        for (d, z) in RADAU5.d.iter().zip(stages.iter()) {
+            k3.axpy(*d, z);
+        }
+
+        let dstate = multistep.dstates.last().expect("history of Radau IIA");
+        k2.assign(dstate);
+        for (e, z) in RADAU_ERROR.iter().zip(stages.iter()) {
+            k2.axpy(e/step, z);
+        }
+        solve_in_place(&newton.real, k2);
+        stage.assign(k3);
+        stage.axpy(1.0, k2);
+        let mut error = error_norm(tolerance, system.state(), stage, k3);
+
+        if error > 1.0 && rejected {
+            stage.assign(system.state());
+            stage.axpy(1.0, k2);
+            system.dstate_at(time, stage, k1);
+            stats.fevals += 1;
+            k2.assign(k1);
+            for (e, z) in RADAU_ERROR.iter().zip(stages.iter()) {
+                k2.axpy(e/step, z);
+            }
+            solve_in_place(&newton.real, k2);
+            stage.assign(k3);
+            stage.axpy(1.0, k2);
+            error = error_norm(tolerance, system.state(), stage, k3);
+        }
+
+        // a step whose error is not finite (the estimate out of the domain of the system) is also rejected
+        if (error > 1.0 || error.is_nan()) && !too_small {
+            multistep.step = if error.is_finite() { step*step_factor(error, 3).min(1.0) } else { 0.5*step };
+            stats.rejected_steps += 1;
+            rejected = true;
+            continue;
+        }
+
+        system.dstate_at(new_time, k3, k4);
+        stats.fevals += 1;
+        stats.accepted_steps += 1;
+        system.update_state(k3);
+        multistep.replace(new_time, system.state(), k4);
+
+        multistep.jacobian_age += 1;
+        if rate > JACOBIAN_RATE {
+            multistep.jacobian.clear();
+        }
+        if error.is_finite() {
+            multistep.step = step*step_factor(error, 3);
+            if clipped {
+                multistep.step = multistep.step.max(proposed);
+            }
+        }
+        return new_time;
+    }
+}
+
+// Makes one step of Gauss–Legendre from `time`, with a new Jacobian. If the Newton iterations do not converge the state is set to NaN, which stops the integration.
+pub(crate) fn gauss_step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, step : f64, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) {
+    let zeros = workspace.k1.zeros_like();
+    workspace.stages.resize(9, zeros);
+
+    let Workspace { k1, k4, stage, jacobian, .. } = workspace;
+    system.dstate(time, k4);
+    stats.fevals += 1;
+    stats.fevals += implicit::jacobian(system, time, k4, jacobian, stage, k1);
+
+    let converged = Newton::new(&GAUSS6, step, &workspace.jacobian)
+        .and_then(|newton| newton.solve(system, time, GAUSS_TOLERANCE, workspace, stats));
+
+    let Workspace { k3, stages, .. } = workspace;
+    k3.assign(system.state());
+    if converged.is_some() {
+        for (d, z) in GAUSS6.d.iter().zip(stages.iter()) {
+            k3.axpy(*d, z);
+        }
+    } else {
+        k3.components_mut().fill(T::from_f64(f64::NAN));
+    }
+    system.update_state(k3);
+}
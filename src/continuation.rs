@@ -0,0 +1,323 @@
+pub use super::*;
+pub use crate::solver_vector::{State, DState, Data, data_to_file};
+use crate::linalg::{self, Matrix, LU};
+
+/// Struct ContinuationParam
+///
+/// Parameters of the continuation: the initial value of the parameter `lambda`, the value where the continuation stops `lambda_end`, the continuation step `ds` (a step in `lambda` for the natural continuation, a step in arclength for the pseudo-arclength continuation), the maximum number of points of the branch `max_points`, the tolerance `tol` and the maximum number of iterations `max_iter` of the Newton corrector.
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContinuationParam {
+    pub lambda : f64,
+    pub lambda_end : f64,
+    pub ds : f64,
+    pub max_points : usize,
+    pub tol : f64,
+    pub max_iter : usize,
+}
+
+/// Enum BifurcationKind
+///
+/// `Fold` when the determinant of the jacobian changes sign (a real eigenvalue crosses zero) and `Hopf` when a pair of complex eigenvalues crosses the imaginary axis.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum BifurcationKind {
+    Fold,
+    Hopf,
+}
+
+/// A bifurcation detected along the branch. `lambda` and `state` are linearly interpolated between the two points of the branch where the test function changed sign.
+#[derive(Clone,Debug)]
+pub struct BifurcationPoint {
+    pub kind : BifurcationKind,
+    pub lambda : f64,
+    pub state : State,
+}
+
+
+// Values used to detect bifurcations at a point of the branch
+struct TestFunctions {
+    determinant : f64,
+    // eigenvalues (real, imaginary) with positive imaginary part, one for each complex conjugate pair
+    oscillatory : Vec<(f64,f64)>,
+}
+
+
+fn test_functions (jac : &Matrix) -> TestFunctions {
+    let determinant = linalg::determinant(jac);
+    let eigenvalues = linalg::eigenvalues(jac).unwrap_or_default();
+    let oscillatory = eigenvalues.into_iter().filter(|(_, im)| *im > 1.0e-12).collect();
+
+    TestFunctions { determinant, oscillatory }
+}
+
+
+// The test functions `g1` and `g2` have opposite signs, the zero is linearly interpolated
+fn interpolate (lambda1 : f64, state1 : &State, g1 : f64, lambda2 : f64, state2 : &State, g2 : f64) -> (f64, State) {
+    let theta = g1/(g1 - g2);
+    let lambda = lambda1 + theta*(lambda2 - lambda1);
+    let state = state1.iter().zip(state2)
+        .map(|(x1,x2)| x1 + theta*(x2 - x1)).collect();
+    (lambda, state)
+}
+
+
+// Real parts of the complex conjugate pairs at both points, each pair matched with the nearest pair of the other point. Pairs born or ended by the collision of two real eigenvalues are left unmatched.
+fn matched_pairs (pairs1 : &[(f64,f64)], pairs2 : &[(f64,f64)]) -> Vec<(f64,f64)> {
+    let distance = |a : &(f64,f64), b : &(f64,f64)| (a.0 - b.0).hypot(a.1 - b.1);
+    let mut used = vec!(false; pairs2.len());
+    let mut matched = Vec::new();
+    for pair1 in pairs1 {
+        let nearest = (0 .. pairs2.len()).filter(|j| !used[*j])
+            .min_by(|i,j| distance(pair1, &pairs2[*i]).total_cmp(&distance(pair1, &pairs2[*j])));
+        if let Some(j) = nearest {
+            used[j] = true;
+            matched.push((pair1.0, pairs2[j].0));
+        }
+    }
+    matched
+}
+
+
+fn detect_bifurcations (lambda1 : f64, state1 : &State, test1 : &TestFunctions, lambda2 : f64, state2 : &State, test2 : &TestFunctions, bifurcations : &mut Vec<BifurcationPoint>) {
+
+    if test1.determinant*test2.determinant < 0.0 {
+        let (lambda, state) = interpolate(lambda1, state1, test1.determinant, lambda2, state2, test2.determinant);
+        bifurcations.push(BifurcationPoint { kind : BifurcationKind::Fold, lambda, state });
+    }
+
+    // a complex conjugate pair whose real part changes sign
+    for (g1, g2) in matched_pairs(&test1.oscillatory, &test2.oscillatory) {
+        if g1*g2 < 0.0 {
+            let (lambda, state) = interpolate(lambda1, state1, g1, lambda2, state2, g2);
+            bifurcations.push(BifurcationPoint { kind : BifurcationKind::Hopf, lambda, state });
+        }
+    }
+}
+
+
+fn datum (lambda : f64, state : &State) -> Vec<f64> {
+    let mut datum = vec!(lambda);
+    datum.extend_from_slice(state);
+    datum
+}
+
+
+fn jacobian_state<Func : FnMut (&State, f64) -> DState> (function : &mut Func, state : &State, lambda : f64) -> Matrix {
+    let fx = function(state, lambda);
+    linalg::jacobian_fd(&mut |x : &[f64]| function(&x.to_vec(), lambda), state, &fx)
+}
+
+
+fn norm (x : &[f64]) -> f64 {
+    x.iter().map(|v| v*v).sum::<f64>().sqrt()
+}
+
+
+// Newton iterations on f(x, lambda) = 0 with lambda fixed
+fn newton<Func : FnMut (&State, f64) -> DState> (function : &mut Func, state : State, lambda : f64, param : &ContinuationParam) -> Option<State> {
+    let mut x = state;
+
+    for _i in 0 .. param.max_iter {
+        let fx = function(&x, lambda);
+        let jac = linalg::jacobian_fd(&mut |xs : &[f64]| function(&xs.to_vec(), lambda), &x, &fx);
+        let dx = LU::new(&jac)?.solve(&fx);
+        for (xi,dxi) in x.iter_mut().zip(&dx) {
+            *xi -= dxi;
+        }
+        if norm(&dx) < param.tol*(1.0 + norm(&x)) {
+            return Some(x);
+        }
+    }
+
+    None
+}
+
+
+/// Natural-parameter continuation of the equilibria of `function(state, lambda) = 0`. Returns a tuple with (Data, Vec<BifurcationPoint>).
+///
+/// `lambda` is increased (or decreased) by `ds` until `lambda_end`, and at each value the equilibrium is found by Newton iterations starting from the previous point. The continuation stops before `lambda_end` if the Newton iterations do not converge, which usually happens at a fold. The rows of `Data` are `[lambda, x1, x2, ...]` so the branch can be exported with `data_to_file`.
+///
+///# Inputs
+///
+///`function: Closure FnMut (&State, f64) -> DState`. Right hand side of the ODE system as a function of the state and the parameter.
+///
+///`state: State`. A guess of the equilibrium at the initial value of the parameter.
+///
+///`param: ContinuationParam`. A value of type ContinuationParam.
+pub fn natural_continuation<Func : FnMut (&State, f64) -> DState> (mut function : Func, state : State, param : ContinuationParam) -> (Data, Vec<BifurcationPoint>) {
+
+    let mut data : Data = Vec::new();
+    let mut bifurcations = Vec::new();
+
+    let direction = if param.lambda_end >= param.lambda { 1.0 } else { -1.0 };
+    let ds = direction*param.ds.abs();
+
+    let mut lambda = param.lambda;
+    let mut state = match newton(&mut function, state, lambda, &param) {
+        Some(x) => x,
+        None => return (data, bifurcations),
+    };
+    let mut test = test_functions(&jacobian_state(&mut function, &state, lambda));
+    data.push(datum(lambda, &state));
+
+    while data.len() < param.max_points && direction*(param.lambda_end - lambda) > 1.0e-12 {
+        let new_lambda = if direction*(lambda + ds - param.lambda_end) > 0.0 { param.lambda_end } else { lambda + ds };
+
+        let new_state = match newton(&mut function, state.clone(), new_lambda, &param) {
+            Some(x) => x,
+            None => break,
+        };
+        let new_test = test_functions(&jacobian_state(&mut function, &new_state, new_lambda));
+
+        detect_bifurcations(lambda, &state, &test, new_lambda, &new_state, &new_test, &mut bifurcations);
+        data.push(datum(new_lambda, &new_state));
+
+        lambda = new_lambda;
+        state = new_state;
+        test = new_test;
+    }
+
+    (data, bifurcations)
+}
+
+
+// jacobian of f(x, lambda) with respect to the state (n columns) and the parameter (last column)
+fn jacobian_extended<Func : FnMut (&State, f64) -> DState> (function : &mut Func, y : &[f64]) -> (Matrix, DState) {
+    let n = y.len() - 1;
+    let mut eval = |y : &[f64]| function(&y[..n].to_vec(), y[n]);
+    let fy = eval(y);
+    let jac = linalg::jacobian_fd(&mut eval, y, &fy);
+    (jac, fy)
+}
+
+
+// unit tangent of the branch at y, oriented by the previous tangent (or by `direction` in lambda)
+fn tangent (jac : &Matrix, previous : Option<&[f64]>, direction : f64) -> Option<Vec<f64>> {
+    let n = jac.len();
+    let mut a = jac.clone();
+    let mut b = vec!(0.0; n+1);
+    match previous {
+        Some(t) => a.push(t.to_vec()),
+        None => {
+            let mut row = vec!(0.0; n+1);
+            row[n] = 1.0;
+            a.push(row);
+        },
+    }
+    b[n] = 1.0;
+
+    let mut t = LU::new(&a)?.solve(&b);
+    let tnorm = norm(&t);
+    let orientation = match previous {
+        Some(_) => 1.0,
+        None => direction,
+    };
+    for ti in t.iter_mut() {
+        *ti *= orientation/tnorm;
+    }
+    Some(t)
+}
+
+
+/// Pseudo-arclength continuation of the equilibria of `function(state, lambda) = 0`. Returns a tuple with (Data, Vec<BifurcationPoint>).
+///
+/// The branch is parametrized by its arclength, so it can be followed around folds. At each point a predictor step of size `ds` is made along the tangent of the branch and corrected by Newton iterations on the extended system `[f(x, lambda) = 0, t·(y - y_pred) = 0]`. The continuation stops when `lambda` leaves the interval between its initial value and `lambda_end` (the last point is then found at the end of the interval), after `max_points` points or if the corrector does not converge. The rows of `Data` are `[lambda, x1, x2, ...]` so the branch can be exported with `data_to_file`.
+///
+///# Inputs
+///
+///`function: Closure FnMut (&State, f64) -> DState`. Right hand side of the ODE system as a function of the state and the parameter.
+///
+///`state: State`. A guess of the equilibrium at the initial value of the parameter.
+///
+///`param: ContinuationParam`. A value of type ContinuationParam.
+pub fn arclength_continuation<Func : FnMut (&State, f64) -> DState> (mut function : Func, state : State, param : ContinuationParam) -> (Data, Vec<BifurcationPoint>) {
+
+    let mut data : Data = Vec::new();
+    let mut bifurcations = Vec::new();
+
+    let n = state.len();
+    let direction = if param.lambda_end >= param.lambda { 1.0 } else { -1.0 };
+    let lambda_min = param.lambda.min(param.lambda_end);
+    let lambda_max = param.lambda.max(param.lambda_end);
+
+    let state = match newton(&mut function, state, param.lambda, &param) {
+        Some(x) => x,
+        None => return (data, bifurcations),
+    };
+    let mut y = state;
+    y.push(param.lambda);
+
+    let (jac, _) = jacobian_extended(&mut function, &y);
+    let mut t = match tangent(&jac, None, direction) {
+        Some(t) => t,
+        None => return (data, bifurcations),
+    };
+    let mut test = test_functions(&state_part(&jac));
+    data.push(datum(y[n], &y[..n].to_vec()));
+
+    while data.len() < param.max_points {
+        let predictor : Vec<f64> = y.iter().zip(&t).map(|(yi,ti)| yi + param.ds.abs()*ti).collect();
+
+        // Newton corrector on the extended system
+        let mut new_y = predictor.clone();
+        let mut converged = false;
+        for _i in 0 .. param.max_iter {
+            let (mut jac, mut g) = jacobian_extended(&mut function, &new_y);
+            jac.push(t.clone());
+            g.push(t.iter().zip(new_y.iter().zip(&predictor)).map(|(ti,(yi,pi))| ti*(yi - pi)).sum());
+            let dy = match LU::new(&jac) {
+                Some(lu) => lu.solve(&g),
+                None => break,
+            };
+            for (yi,dyi) in new_y.iter_mut().zip(&dy) {
+                *yi -= dyi;
+            }
+            if norm(&dy) < param.tol*(1.0 + norm(&new_y)) {
+                converged = true;
+                break;
+            }
+        }
+        if !converged {
+            break;
+        }
+
+        let (jac, _) = jacobian_extended(&mut function, &new_y);
+        let new_t = match tangent(&jac, Some(&t), direction) {
+            Some(t) => t,
+            None => break,
+        };
+        let state = y[..n].to_vec();
+
+        // the last point is moved back to the end of the interval of the parameter, or dropped if it is not found there
+        if new_y[n] < lambda_min || new_y[n] > lambda_max {
+            let lambda_last = if new_y[n] > lambda_max { lambda_max } else { lambda_min };
+            let theta = (lambda_last - y[n])/(new_y[n] - y[n]);
+            let guess = state.iter().zip(&new_y).map(|(x1,x2)| x1 + theta*(x2 - x1)).collect();
+            if let Some(last_state) = newton(&mut function, guess, lambda_last, &param) {
+                let last_test = test_functions(&jacobian_state(&mut function, &last_state, lambda_last));
+                detect_bifurcations(y[n], &state, &test, lambda_last, &last_state, &last_test, &mut bifurcations);
+                data.push(datum(lambda_last, &last_state));
+            }
+            break;
+        }
+
+        let new_test = test_functions(&state_part(&jac));
+        let new_state = new_y[..n].to_vec();
+
+        detect_bifurcations(y[n], &state, &test, new_y[n], &new_state, &new_test, &mut bifurcations);
+        data.push(datum(new_y[n], &new_state));
+
+        y = new_y;
+        t = new_t;
+        test = new_test;
+    }
+
+    (data, bifurcations)
+}
+
+
+// removes the column of the derivatives with respect to the parameter
+fn state_part (jac : &Matrix) -> Matrix {
+    let n = jac.len();
+    jac.iter().map(|row| row[..n].to_vec()).collect()
+}
@@ -0,0 +1,121 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Enum FloatFormat
+///
+/// How the values are written: `Scientific` (`1.23450000e2`), `Fixed` (`123.45000000`), both with `CsvFormat.precision` decimal places, or `Shortest`, the shortest representation that reads back to the same value (`123.45`).
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FloatFormat {
+    Scientific,
+    Fixed,
+    Shortest,
+}
+
+/// Struct CsvFormat
+///
+/// Formatting of the CSV files: the `delimiter` between columns (which can not be a character of the values, a double quote or a line break, see `validate_delimiter`), the `precision` and `float_format` of the values and the `names` of the columns (e.g. `["t", "x", "v"]`), written as a header when given. `CsvFormat::default()` gives a comma separated file with values in scientific notation with 8 decimal places and no header.
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CsvFormat {
+    pub delimiter : char,
+    pub precision : usize,
+    pub float_format : FloatFormat,
+    pub names : Option<Vec<String>>,
+}
+
+impl Default for CsvFormat {
+    fn default () -> Self {
+        CsvFormat { delimiter : ',', precision : 8, float_format : FloatFormat::Scientific, names : None }
+    }
+}
+
+impl CsvFormat {
+
+    /// Formats a single value.
+    pub fn format_value (&self, value : f64) -> String {
+        match self.float_format {
+            FloatFormat::Scientific => format!("{:.*e}", self.precision, value),
+            FloatFormat::Fixed => format!("{:.*}", self.precision, value),
+            FloatFormat::Shortest => format!("{}", value),
+        }
+    }
+
+    /// Quotes a field following RFC 4180: fields with the delimiter, double quotes or line breaks are enclosed in double quotes, and the double quotes inside them are doubled.
+    pub fn quote (&self, field : &str) -> String {
+        if field.contains(self.delimiter) || field.contains('"') || field.contains('\r') || field.contains('\n') {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+
+    /// Formats a row of values, without the line ending.
+    pub fn format_row (&self, row : &[f64]) -> String {
+        let fields : Vec<String> = row.iter().map(|value| self.quote(&self.format_value(*value))).collect();
+        fields.join(&self.delimiter.to_string())
+    }
+
+    /// Formats the header with the names of the columns, without the line ending. Returns `None` if no names were given.
+    pub fn format_header (&self) -> Option<String> {
+        self.names.as_ref().map(|names| {
+            let fields : Vec<String> = names.iter().map(|name| self.quote(name)).collect();
+            fields.join(&self.delimiter.to_string())
+        })
+    }
+
+    /// Checks that the delimiter can not be confused with the values or the quoting: double quotes, line breaks, digits, `.`, `e`, `-` and `+` are rejected.
+    pub fn validate_delimiter (&self) -> Result<(), io::Error> {
+        if matches!(self.delimiter, '"' | '\n' | '\r' | '.' | 'e' | '-' | '+') || self.delimiter.is_ascii_digit() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid CSV delimiter {:?}", self.delimiter)));
+        }
+        Ok(())
+    }
+
+    /// Checks that the number of names matches the width of a row.
+    pub fn validate (&self, width : usize) -> Result<(), io::Error> {
+        match &self.names {
+            Some(names) if names.len() != width => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("{} column names given for rows with {} columns", names.len(), width))),
+            _ => Ok(()),
+        }
+    }
+}
+
+
+/// Writes the data as CSV to any writer, with CRLF line endings as in RFC 4180.
+///
+/// Works with the `Data` of all the modules: any collection of rows that can be seen as `&[f64]` (`Vec<f64>` or `[f64;M]`). The delimiter is validated, and the names of the columns, if given, against the width of every row.
+pub fn write_csv<W : Write, Row : AsRef<[f64]>> (writer : &mut W, data : &[Row], format : &CsvFormat) -> Result<(), Box<dyn Error>> {
+    format.validate_delimiter()?;
+    for row in data {
+        format.validate(row.as_ref().len())?;
+    }
+
+    if let Some(header) = format.format_header() {
+        write!(writer, "{}\r\n", header)?;
+    }
+    for row in data {
+        write!(writer, "{}\r\n", format.format_row(row.as_ref()))?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+
+/// Saves the data as CSV to a given filename/filepath.
+///
+/// The first column of data is the times, the second onwards are the values of the state at that particular time. See `CsvFormat` for the available options.
+pub fn data_to_csv<Row : AsRef<[f64]>> (data : &[Row], file_as_string : String, format : &CsvFormat) -> Result<(), Box<dyn Error>> {
+    let file = match File::create(file_as_string) {
+        Ok(f) => f,
+        Err(erro) => {
+            eprintln!("Not possible to create/find the file for exporting the data. Error: {}", erro);
+            return Err(Box::new(erro) as Box<dyn Error>);},
+    };
+
+    let mut writer = BufWriter::new(file);
+    write_csv(&mut writer, data, format)
+}
@@ -0,0 +1,296 @@
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use crate::engine::Scalar;
+
+/// Struct Dual
+///
+/// Dual number `value + derivative*ε`, with `ε² = 0`. Evaluating a function on `Dual { value : x, derivative : 1.0 }` gives its value and its exact derivative at `x` (forward mode automatic differentiation).
+///
+/// It implements `Scalar`, so it can also be integrated by the solvers (e.g. `solver_vector::solve_ode_generic`), giving the derivative of the solution with respect to the initial state or a parameter.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct Dual {
+    pub value : f64,
+    pub derivative : f64,
+}
+
+impl Dual {
+
+    /// Dual number with the given value and derivative.
+    pub fn new (value : f64, derivative : f64) -> Dual {
+        Dual { value, derivative }
+    }
+
+    /// Constant, the derivative is 0.
+    pub fn constant (value : f64) -> Dual {
+        Dual { value, derivative : 0.0 }
+    }
+
+    /// Variable with respect to which the derivative is taken, the derivative is 1.
+    pub fn variable (value : f64) -> Dual {
+        Dual { value, derivative : 1.0 }
+    }
+
+    // Applies a function with value `value` and derivative `derivative` at self.value (chain rule)
+    fn chain (self, value : f64, derivative : f64) -> Dual {
+        Dual { value, derivative : derivative*self.derivative }
+    }
+}
+
+impl From<f64> for Dual {
+    fn from (value : f64) -> Dual {
+        Dual::constant(value)
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+    fn add (self, other : Dual) -> Dual {
+        Dual { value : self.value + other.value, derivative : self.derivative + other.derivative }
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+    fn sub (self, other : Dual) -> Dual {
+        Dual { value : self.value - other.value, derivative : self.derivative - other.derivative }
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+    fn mul (self, other : Dual) -> Dual {
+        Dual { value : self.value*other.value, derivative : self.derivative*other.value + self.value*other.derivative }
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+    fn div (self, other : Dual) -> Dual {
+        Dual { value : self.value/other.value,
+            derivative : (self.derivative*other.value - self.value*other.derivative)/(other.value*other.value) }
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+    fn neg (self) -> Dual {
+        Dual { value : -self.value, derivative : -self.derivative }
+    }
+}
+
+// Operations with a constant f64 on either side
+macro_rules! constant_ops {
+    ($($trait:ident $method:ident),*) => {
+        $(
+            impl $trait<f64> for Dual {
+                type Output = Dual;
+                fn $method (self, other : f64) -> Dual {
+                    $trait::$method(self, Dual::constant(other))
+                }
+            }
+
+            impl $trait<Dual> for f64 {
+                type Output = Dual;
+                fn $method (self, other : Dual) -> Dual {
+                    $trait::$method(Dual::constant(self), other)
+                }
+            }
+        )*
+    };
+}
+
+constant_ops!(Add add, Sub sub, Mul mul, Div div);
+
+macro_rules! assign_ops {
+    ($($trait:ident $method:ident $op:tt),*) => {
+        $(
+            impl<Other : Into<Dual>> $trait<Other> for Dual {
+                fn $method (&mut self, other : Other) {
+                    *self = *self $op other.into();
+                }
+            }
+        )*
+    };
+}
+
+assign_ops!(AddAssign add_assign +, SubAssign sub_assign -, MulAssign mul_assign *, DivAssign div_assign /);
+
+impl Scalar for Dual {
+    fn from_f64 (value : f64) -> Dual {
+        Dual::constant(value)
+    }
+
+    fn scale (self, factor : f64) -> Dual {
+        Dual { value : factor*self.value, derivative : factor*self.derivative }
+    }
+
+    fn is_finite (self) -> bool {
+        self.value.is_finite() && self.derivative.is_finite()
+    }
+
+    fn magnitude (self) -> f64 {
+        self.value.abs()
+    }
+}
+
+
+/// Trait of the real scalars with the elementary functions, implemented for `f64`, `f32` and `Dual`.
+///
+/// Functions written for any `T : Real` can be evaluated with `f64` and differentiated exactly with `Dual`, see `jacobian` and `solver_vector_trait::GenericODESystem`.
+pub trait Real : Scalar {
+
+    /// Value as `f64` (without the derivative for `Dual`), e.g. for comparisons.
+    fn value (self) -> f64;
+
+    fn sqrt (self) -> Self;
+    fn exp (self) -> Self;
+    fn ln (self) -> Self;
+    fn sin (self) -> Self;
+    fn cos (self) -> Self;
+    fn tan (self) -> Self;
+    fn sinh (self) -> Self;
+    fn cosh (self) -> Self;
+    fn tanh (self) -> Self;
+    fn asin (self) -> Self;
+    fn acos (self) -> Self;
+    fn atan (self) -> Self;
+    fn abs (self) -> Self;
+    fn powi (self, n : i32) -> Self;
+    fn powf (self, n : f64) -> Self;
+}
+
+macro_rules! float_real {
+    ($float:ident) => {
+        #[allow(clippy::unnecessary_cast)]
+        impl Real for $float {
+            fn value (self) -> f64 { self as f64 }
+            fn sqrt (self) -> Self { $float::sqrt(self) }
+            fn exp (self) -> Self { $float::exp(self) }
+            fn ln (self) -> Self { $float::ln(self) }
+            fn sin (self) -> Self { $float::sin(self) }
+            fn cos (self) -> Self { $float::cos(self) }
+            fn tan (self) -> Self { $float::tan(self) }
+            fn sinh (self) -> Self { $float::sinh(self) }
+            fn cosh (self) -> Self { $float::cosh(self) }
+            fn tanh (self) -> Self { $float::tanh(self) }
+            fn asin (self) -> Self { $float::asin(self) }
+            fn acos (self) -> Self { $float::acos(self) }
+            fn atan (self) -> Self { $float::atan(self) }
+            fn abs (self) -> Self { $float::abs(self) }
+            fn powi (self, n : i32) -> Self { $float::powi(self, n) }
+            fn powf (self, n : f64) -> Self { $float::powf(self, n as $float) }
+        }
+    };
+}
+
+float_real!(f64);
+float_real!(f32);
+
+impl Real for Dual {
+    fn value (self) -> f64 {
+        self.value
+    }
+
+    fn sqrt (self) -> Dual {
+        let value = self.value.sqrt();
+        self.chain(value, 0.5/value)
+    }
+
+    fn exp (self) -> Dual {
+        let value = self.value.exp();
+        self.chain(value, value)
+    }
+
+    fn ln (self) -> Dual {
+        self.chain(self.value.ln(), 1.0/self.value)
+    }
+
+    fn sin (self) -> Dual {
+        self.chain(self.value.sin(), self.value.cos())
+    }
+
+    fn cos (self) -> Dual {
+        self.chain(self.value.cos(), -self.value.sin())
+    }
+
+    fn tan (self) -> Dual {
+        let value = self.value.tan();
+        self.chain(value, 1.0 + value*value)
+    }
+
+    fn sinh (self) -> Dual {
+        self.chain(self.value.sinh(), self.value.cosh())
+    }
+
+    fn cosh (self) -> Dual {
+        self.chain(self.value.cosh(), self.value.sinh())
+    }
+
+    fn tanh (self) -> Dual {
+        let value = self.value.tanh();
+        self.chain(value, 1.0 - value*value)
+    }
+
+    fn asin (self) -> Dual {
+        self.chain(self.value.asin(), 1.0/(1.0 - self.value*self.value).sqrt())
+    }
+
+    fn acos (self) -> Dual {
+        self.chain(self.value.acos(), -1.0/(1.0 - self.value*self.value).sqrt())
+    }
+
+    fn atan (self) -> Dual {
+        self.chain(self.value.atan(), 1.0/(1.0 + self.value*self.value))
+    }
+
+    fn abs (self) -> Dual {
+        self.chain(self.value.abs(), self.value.signum())
+    }
+
+    fn powi (self, n : i32) -> Dual {
+        if n == 0 {
+            return Dual::constant(1.0);
+        }
+        self.chain(self.value.powi(n), n as f64*self.value.powi(n - 1))
+    }
+
+    fn powf (self, n : f64) -> Dual {
+        self.chain(self.value.powf(n), n*self.value.powf(n - 1.0))
+    }
+}
+
+
+/// Derivative of a function of one variable at `x`, exact up to rounding.
+pub fn derivative<Func : FnMut (Dual) -> Dual> (mut function : Func, x : f64) -> f64 {
+    function(Dual::variable(x)).derivative
+}
+
+
+/// Jacobian of a function at `point`, exact up to rounding: `jacobian[i][j]` is the derivative of the output `i` with respect to the input `j`.
+///
+/// The function is evaluated once for each input, with the derivative 1 in that input.
+///
+///# Inputs
+///
+///`function : FnMut (&[Dual]) -> Vec<Dual>`. The function to differentiate, usually written for any `T : Real` and called with `Dual`.
+///
+///`point : &[f64]`. The point where the jacobian is computed.
+pub fn jacobian<Func : FnMut (&[Dual]) -> Vec<Dual>> (mut function : Func, point : &[f64]) -> Vec<Vec<f64>> {
+    let mut duals : Vec<Dual> = point.iter().map(|x| Dual::constant(*x)).collect();
+    let mut jacobian : Vec<Vec<f64>> = Vec::new();
+
+    for j in 0 .. point.len() {
+        duals[j].derivative = 1.0;
+        let output = function(&duals);
+        duals[j].derivative = 0.0;
+
+        if jacobian.is_empty() {
+            jacobian = vec!(vec!(0.0; point.len()); output.len());
+        }
+        for (row, value) in jacobian.iter_mut().zip(&output) {
+            row[j] = value.derivative;
+        }
+    }
+
+    jacobian
+}
@@ -0,0 +1,559 @@
+pub use super::*;
+use crate::adams::Multistep;
+use crate::collocation;
+use crate::theta;
+use crate::sink::OutputSink;
+pub use crate::result::{SolveStats, Termination};
+use crate::result::non_finite;
+use std::fmt::Debug;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+use std::time::Instant;
+
+/// Trait of the scalars of the states: `f64` (the default), `f32`, `Complex<f64>` and `Complex<f32>` (feature `complex`) or a type of your own, e.g. dual numbers for forward mode differentiation.
+///
+/// The time and the step are always `f64`, the solvers multiply the components by them with `scale`.
+pub trait Scalar : Copy + PartialEq + Debug + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self> + AddAssign + 'static {
+
+    /// Scalar with the value of a real number.
+    fn from_f64 (value : f64) -> Self;
+
+    /// Scalar 0.
+    fn zero () -> Self {
+        Self::from_f64(0.0)
+    }
+
+    /// Product by a real number.
+    fn scale (self, factor : f64) -> Self {
+        Self::from_f64(factor)*self
+    }
+
+    /// True if the scalar is finite (not infinite nor NaN).
+    fn is_finite (self) -> bool;
+
+    /// Absolute value (modulus of complex numbers), used by the adaptive solvers to measure the error.
+    fn magnitude (self) -> f64;
+}
+
+impl Scalar for f64 {
+    fn from_f64 (value : f64) -> Self {
+        value
+    }
+
+    fn scale (self, factor : f64) -> Self {
+        factor*self
+    }
+
+    fn is_finite (self) -> bool {
+        f64::is_finite(self)
+    }
+
+    fn magnitude (self) -> f64 {
+        self.abs()
+    }
+}
+
+impl Scalar for f32 {
+    fn from_f64 (value : f64) -> Self {
+        value as f32
+    }
+
+    fn scale (self, factor : f64) -> Self {
+        (factor as f32)*self
+    }
+
+    fn is_finite (self) -> bool {
+        f32::is_finite(self)
+    }
+
+    fn magnitude (self) -> f64 {
+        self.abs() as f64
+    }
+}
+
+#[cfg(feature = "complex")]
+macro_rules! complex_scalar {
+    ($real:ty) => {
+        impl Scalar for num_complex::Complex<$real> {
+            fn from_f64 (value : f64) -> Self {
+                num_complex::Complex::new(value as $real, 0.0)
+            }
+
+            fn scale (self, factor : f64) -> Self {
+                self*(factor as $real)
+            }
+
+            fn is_finite (self) -> bool {
+                self.re.is_finite() && self.im.is_finite()
+            }
+
+            #[allow(clippy::unnecessary_cast)]
+            fn magnitude (self) -> f64 {
+                self.norm() as f64
+            }
+        }
+    };
+}
+
+#[cfg(feature = "complex")]
+complex_scalar!(f64);
+#[cfg(feature = "complex")]
+complex_scalar!(f32);
+
+
+/// Trait of the states integrated by the solvers: a vector of components of a `Scalar` type (`f64` by default), which are combined linearly by the solvers.
+///
+/// It is implemented for `[T;N]` and `Vec<T>`, for `nalgebra::SVector<T,N>` and `nalgebra::DVector<T>` with the feature `nalgebra` and for `ndarray::Array1<T>` with the feature `ndarray`. Other types (e.g. a struct with named fields stored in an array) only need to give their components.
+pub trait VectorSpace<T : Scalar = f64> : Clone {
+
+    /// Components of the state.
+    fn components (&self) -> &[T];
+
+    /// Mutable components of the state.
+    fn components_mut (&mut self) -> &mut [T];
+
+    /// State of the same size with all the components 0.
+    fn zeros_like (&self) -> Self {
+        let mut zeros = self.clone();
+        zeros.components_mut().iter_mut().for_each(|x| *x = T::zero());
+        zeros
+    }
+
+    /// Copies the components of `other`, which has the same size.
+    fn assign (&mut self, other : &Self) {
+        self.components_mut().copy_from_slice(other.components());
+    }
+
+    /// Adds `factor*other` to the state.
+    fn axpy (&mut self, factor : f64, other : &Self) {
+        for (x, y) in self.components_mut().iter_mut().zip(other.components()) {
+            *x += y.scale(factor);
+        }
+    }
+
+    /// Sets the state to `base + factor*other`.
+    fn assign_axpy (&mut self, base : &Self, factor : f64, other : &Self) {
+        for ((x, b), y) in self.components_mut().iter_mut().zip(base.components()).zip(other.components()) {
+            *x = *b + y.scale(factor);
+        }
+    }
+
+    /// True if all the components are finite.
+    fn is_finite (&self) -> bool {
+        self.components().iter().all(|x| x.is_finite())
+    }
+}
+
+impl<T : Scalar, const N : usize> VectorSpace<T> for [T;N] {
+    fn components (&self) -> &[T] {
+        self
+    }
+
+    fn components_mut (&mut self) -> &mut [T] {
+        self
+    }
+
+    fn zeros_like (&self) -> Self {
+        [T::zero(); N]
+    }
+}
+
+impl<T : Scalar> VectorSpace<T> for Vec<T> {
+    fn components (&self) -> &[T] {
+        self
+    }
+
+    fn components_mut (&mut self) -> &mut [T] {
+        self
+    }
+
+    fn zeros_like (&self) -> Self {
+        vec!(T::zero(); self.len())
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T : Scalar, const N : usize> VectorSpace<T> for nalgebra::SVector<T, N> {
+    fn components (&self) -> &[T] {
+        self.as_slice()
+    }
+
+    fn components_mut (&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+
+    fn zeros_like (&self) -> Self {
+        Self::from_element(T::zero())
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<T : Scalar> VectorSpace<T> for nalgebra::DVector<T> {
+    fn components (&self) -> &[T] {
+        self.as_slice()
+    }
+
+    fn components_mut (&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+
+    fn zeros_like (&self) -> Self {
+        Self::from_element(self.len(), T::zero())
+    }
+}
+
+/// The arrays have to be contiguous in the standard order, as the ones created by `Array1::from_vec`, `arr1` or `Array1::zeros`; other layouts panic.
+#[cfg(feature = "ndarray")]
+impl<T : Scalar> VectorSpace<T> for ndarray::Array1<T> {
+    fn components (&self) -> &[T] {
+        self.as_slice().expect("Array1 state which is not contiguous in the standard order")
+    }
+
+    fn components_mut (&mut self) -> &mut [T] {
+        self.as_slice_mut().expect("Array1 state which is not contiguous in the standard order")
+    }
+
+    fn zeros_like (&self) -> Self {
+        Self::from_elem(self.len(), T::zero())
+    }
+}
+
+
+/// Trait of the systems integrated by the engine: a state and the differential at any state.
+///
+/// The modules `solver_trait`, `solver_vector` and `solver_vector_trait` adapt their systems (and system functions) to it. The scalar `T` of the state is `f64` by default.
+pub trait System<T : Scalar = f64> {
+    type State : VectorSpace<T>;
+
+    /// Actual state of the system.
+    fn state (&self) -> &Self::State;
+
+    /// Writes in `dstate` the differential of the system at the actual state.
+    fn dstate (&mut self, time : f64, dstate : &mut Self::State) {
+        let state = self.state().clone();
+        self.dstate_at(time, &state, dstate);
+    }
+
+    /// Writes in `dstate` the differential of the system at a given `state`, used at the intermediate stages of a step.
+    fn dstate_at (&mut self, time : f64, state : &Self::State, dstate : &mut Self::State);
+
+    /// Updates the state of the system.
+    fn update_state (&mut self, state : &Self::State);
+
+    /// Writes in the rows of `jacobian` (one for each component of the differential) the Jacobian of the differential at a given `state`, used by the implicit solvers. Returns false if the system does not give it, the default, and then the solvers compute it by finite differences.
+    fn jacobian_at (&mut self, _time : f64, _state : &Self::State, _jacobian : &mut [Self::State]) -> bool {
+        false
+    }
+}
+
+
+/// Struct History
+///
+/// History of a multistep solver (`ODESolver::Adams` and `ODESolver::Lsoda`): the `step` to be tried next, the `order`, the past `times` (the last one is the actual time) with the `states` and the differentials `dstates` at them. For `ODESolver::Lsoda` also if the `stiff` method (BDF) is used, the number of consecutive steps which passed the test for switching the method and the `jacobian` of the BDF (by rows, empty if not computed) with its age in steps. For the adaptive one step solvers (`ODESolver::Ros3p`, `ODESolver::Rodas4` and `ODESolver::Radau5`) it only has the `step`, the actual time, state and differential and for `ODESolver::Radau5` the Jacobian kept for the next step, and it is empty for the fixed step solvers.
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct History<T = f64> {
+    pub step : f64,
+    pub order : usize,
+    pub times : Vec<f64>,
+    pub states : Vec<Vec<T>>,
+    pub dstates : Vec<Vec<T>>,
+    pub stiff : bool,
+    pub switch_count : usize,
+    pub jacobian : Vec<Vec<T>>,
+    pub jacobian_age : usize,
+}
+
+impl<T> Default for History<T> {
+    fn default () -> Self {
+        History { step : 0.0, order : 0, times : Vec::new(), states : Vec::new(), dstates : Vec::new(), stiff : false, switch_count : 0, jacobian : Vec::new(), jacobian_age : 0 }
+    }
+}
+
+
+/// Struct Workspace
+///
+/// Stage buffers of the solvers, allocated once for the whole integration, and the history of the multistep solvers.
+pub struct Workspace<S> {
+    pub(crate) k1 : S,
+    pub(crate) k2 : S,
+    pub(crate) k3 : S,
+    pub(crate) k4 : S,
+    pub(crate) stage : S,
+    // stages of the implicit solvers, allocated at their first step
+    pub(crate) stages : Vec<S>,
+    // rows of the jacobian of the fixed step implicit solvers, allocated at their first step and apart from the history
+    pub(crate) jacobian : Vec<S>,
+    pub(crate) multistep : Multistep<S>,
+}
+
+impl<S> Workspace<S> {
+
+    /// Creates the buffers for states of the size of `state`.
+    pub fn new<T : Scalar> (state : &S) -> Workspace<S> where S : VectorSpace<T> {
+        Workspace { k1 : state.zeros_like(), k2 : state.zeros_like(), k3 : state.zeros_like(), k4 : state.zeros_like(), stage : state.zeros_like(), stages : Vec::new(), jacobian : Vec::new(), multistep : Multistep::new() }
+    }
+
+    /// History of the multistep solver at the actual time.
+    pub fn history<T : Scalar> (&self) -> History<T> where S : VectorSpace<T> {
+        self.multistep.history()
+    }
+
+    /// Replaces the history of the multistep solver, to resume an integration. It is only used if its last time is the time of the next step, and it is ignored (the solver starts again) if it is not consistent: sizes of the states and differentials other than the size of the state, an order not possible with the past times or a step which is not finite and positive.
+    pub fn set_history<T : Scalar> (&mut self, history : &History<T>) where S : VectorSpace<T> {
+        self.multistep.set_history(history, &self.stage);
+    }
+}
+
+
+/// Makes one `step` of `odesolver` from `time`, updating the state of the system.
+///
+/// The adaptive solvers (see `ODESolver::is_adaptive`) make as many steps as needed to advance by `step`, continuing from the history in `workspace` (starting with a step of `step` if there is none).
+pub fn step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, step : f64, odesolver : ODESolver, workspace : &mut Workspace<Sys::State>) {
+    counted_step(system, time, step, odesolver, workspace, &mut SolveStats::default());
+}
+
+// `step`, counting the evaluations and the steps made in `stats`
+pub(crate) fn counted_step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, step : f64, odesolver : ODESolver, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) {
+    if odesolver.is_adaptive() {
+        adams::advance_to(system, time, time + step, step, odesolver, workspace, stats);
+    } else {
+        fixed_step(system, time, step, odesolver, workspace, stats);
+    }
+}
+
+
+// Makes one step of a fixed step `odesolver`, counted in `stats`
+fn fixed_step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, step : f64, odesolver : ODESolver, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) {
+    match odesolver {
+        ODESolver::RK4 => rk4(system, time, step, workspace),
+        ODESolver::Euler => euler(system, time, step, workspace),
+        ODESolver::GaussLegendre => collocation::gauss_step(system, time, step, workspace, stats),
+        ODESolver::BackwardEuler | ODESolver::Trapezoidal => theta::step(system, time, step, odesolver, workspace, stats),
+        _ => unreachable!("{:?} is not a fixed step solver", odesolver),
+    }
+    if let ODESolver::RK4 | ODESolver::Euler = odesolver {
+        stats.fevals += odesolver.stages();
+    }
+    stats.accepted_steps += 1;
+}
+
+
+pub(crate) fn rk4<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, step : f64, workspace : &mut Workspace<Sys::State>) {
+    let Workspace { k1, k2, k3, k4, stage, .. } = workspace;
+
+    system.dstate(time, k1);
+
+    let time2 = time + 0.5*step;
+    stage.assign_axpy(system.state(), 0.5*step, k1);
+    system.dstate_at(time2, stage, k2);
+
+    let time3 = time2;
+    stage.assign_axpy(system.state(), 0.5*step, k2);
+    system.dstate_at(time3, stage, k3);
+
+    let time4 = time + step;
+    stage.assign_axpy(system.state(), step, k3);
+    system.dstate_at(time4, stage, k4);
+
+    let stages = k1.components().iter().zip(k2.components()).zip(k3.components()).zip(k4.components());
+    for ((x, state), (((ka, kb), kc), kd)) in stage.components_mut().iter_mut().zip(system.state().components()).zip(stages) {
+        *x = *state + (*ka + kb.scale(2.0) + kc.scale(2.0) + *kd).scale(step*(1.0/6.0));
+    }
+    system.update_state(stage);
+}
+
+fn euler<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, step : f64, workspace : &mut Workspace<Sys::State>) {
+    let Workspace { k1, stage, .. } = workspace;
+
+    system.dstate(time, k1);
+    stage.assign_axpy(system.state(), step, k1);
+    system.update_state(stage);
+}
+
+
+// Times where the integrator has to stop: the sorted `tstops`, given to the output if `output_tstops`, and the grid of the fixed steps (`odeparam.step` from the initial time) and of the output times (every `ratio_step_output` steps), with the last point of the grid reached and the number of steps of the grid made since the last output time. A tstop between two points of the grid does not shift it, the next step ends at the next point of the grid.
+pub(crate) struct Schedule<'a> {
+    pub(crate) tstops : &'a [f64],
+    pub(crate) output_tstops : bool,
+    pub(crate) grid_time : f64,
+    pub(crate) grid_steps : usize,
+}
+
+
+// Makes the steps up to the next output time, landing at the `tstops`
+fn integrator<T : Scalar, Sys : System<T>> (system : &mut Sys, odeparam : ODEParam, odesolver : ODESolver, workspace : &mut Workspace<Sys::State>, schedule : &mut Schedule, stats : &mut SolveStats) -> ODEParam
+{
+    let mut time = odeparam.time;
+    let tend = odeparam.tend;
+    let step = odeparam.step;
+    let ratio_step_output = odeparam.ratio_step_output;
+
+    if odesolver.is_adaptive() {
+        return adams::integrator(system, odeparam, odesolver, workspace, schedule, stats);
+    }
+
+    while schedule.grid_steps < ratio_step_output {
+        let grid_time = schedule.grid_time + step;
+        let tstop = next_tstop(schedule.tstops, time, tend);
+        if grid_time - tstop > 0.0  {
+            let new_step = tstop - time;
+            fixed_step(system, time, new_step, odesolver, workspace, stats);
+            time = tstop;
+            if tstop == tend || schedule.output_tstops {
+                return ODEParam {
+                    time, .. odeparam};
+            }
+        } else {
+            // the full step on the grid, or what is left of it after a tstop
+            let new_step = if time == schedule.grid_time { step } else { grid_time - time };
+            fixed_step(system, time, new_step, odesolver, workspace, stats);
+            time = grid_time;
+            schedule.grid_time = grid_time;
+            schedule.grid_steps += 1;
+        }
+    }
+    schedule.grid_steps = 0;
+
+    ODEParam {
+        time, .. odeparam}
+}
+
+
+/// Integration engine used by all the solvers: integrates `system` from `odeparam.time` to `odeparam.tend`, giving the output to `sink` and filling `stats`. The state of the system is updated and the ODEParam at the end is returned, or the error of the sink.
+///
+/// The integrator lands exactly at each time of `tstops`, and if `output_tstops` is true the points at the `tstops` are also given to the sink. If the state stops being finite the integration is stopped with `Termination::Error` in `stats`.
+///
+///# Inputs
+///
+///`system : &mut Sys`. Any type which implements `System`.
+///
+///`odeparam : ODEParam`. A value of ODEParam.
+///
+///`odesolver : ODESolver`. A choice of an ODE solver.
+///
+///`tstops : &[f64]`. Times where the integrator has to land, in any order.
+///
+///`output_tstops : bool`. If the points at the `tstops` are given to the sink.
+///
+///`sink : &mut Sink`. Any `OutputSink`.
+///
+///`stats : &mut SolveStats`. The statistics of the integration.
+///
+///With the adaptive solvers the history of the solver is given to the sinks which need it (see `OutputSink::needs_history`), and the integration continues from the history given by `OutputSink::resume_history`, if any.
+pub fn integrate<T : Scalar, Sys : System<T>, Sink : OutputSink<T> + ?Sized> (system : &mut Sys, odeparam : ODEParam, odesolver : ODESolver, tstops : &[f64], output_tstops : bool, sink : &mut Sink, stats : &mut SolveStats) -> Result<ODEParam, Box<dyn Error>> {
+
+    let start = Instant::now();
+    let tstops = sorted_tstops(tstops);
+    let tend = odeparam.tend;
+
+    let mut workspace = Workspace::new(system.state());
+    if let Some(history) = sink.resume_history() {
+        workspace.set_history(&history);
+    }
+
+    output(sink, odeparam.time, system.state(), &workspace)?;
+
+    let mut schedule = Schedule { tstops : &tstops, output_tstops, grid_time : odeparam.time, grid_steps : 0 };
+    let mut new_param = odeparam;
+
+    loop {
+        new_param = integrator(system, new_param, odesolver, &mut workspace, &mut schedule, stats);
+
+        let new_time = new_param.time;
+
+        output(sink, new_time, system.state(), &workspace)?;
+
+        if !system.state().is_finite() {
+            stats.termination = Termination::Error(non_finite(new_time));
+            break;
+        }
+
+        if (new_time - tend).abs() < 1.0e-10 {
+            break;
+        }
+    }
+
+    sink.finish()?;
+
+    stats.wall_time = start.elapsed();
+
+    Ok(new_param)
+}
+
+
+// Gives an output row to the sink, with the history of the solver if the sink needs it
+fn output<T : Scalar, S : VectorSpace<T>, Sink : OutputSink<T> + ?Sized> (sink : &mut Sink, time : f64, state : &S, workspace : &Workspace<S>) -> Result<(), Box<dyn Error>> {
+    if sink.needs_history() {
+        sink.push_history(workspace.history());
+    }
+    sink.push(time, state.components())
+}
+
+
+/// Similar to `integrate` but without output, the system only evolves up to `odeparam.tend`. Returns the ODEParam at the end.
+pub fn advance<T : Scalar, Sys : System<T>> (system : &mut Sys, odeparam : ODEParam, odesolver : ODESolver) -> ODEParam {
+    let mut time = odeparam.time;
+    let tend = odeparam.tend;
+    let step = odeparam.step;
+    let mut workspace = Workspace::new(system.state());
+
+    if (time - tend).abs() < 1.0e-10 {
+        return odeparam;
+    }
+
+    if odesolver.is_adaptive() {
+        let time = adams::advance_to(system, time, tend, step, odesolver, &mut workspace, &mut SolveStats::default());
+        return ODEParam {
+            time, .. odeparam};
+    }
+
+    loop {
+        if (time + step) - tend > 0.0  {
+            self::step(system, time, tend - time, odesolver, &mut workspace);
+            time = tend;
+            break;
+        }
+        self::step(system, time, step, odesolver, &mut workspace);
+        time += step;
+    }
+
+    ODEParam {
+        time, .. odeparam}
+}
+
+
+// Writes rows `[time, state...]` as text, used by the `data_to_file` of the modules
+pub(crate) fn rows_to_file<Row : AsRef<[f64]>> (data : &[Row], file_as_string : String, header : Option<String>) -> Result<(), Box<dyn Error>> {
+    let file = File::create(file_as_string);
+
+    let mut file = match file {
+        Ok(f) => f,
+        Err(erro) => {
+            eprintln!("Not possible to create/find the file for exporting the data. Error: {}", erro);
+            return Err(Box::new(erro) as Box<dyn Error>);},
+    };
+
+    if let Some(headerstring) = header {
+        match writeln!(file,"{}", headerstring){
+            Ok(_) => (),
+            Err(erro) => {
+            eprintln!("Error in writing to file. Error: {}", erro);
+            return Err(Box::new(erro) as Box<dyn Error>);},
+        }
+    }
+
+    for datum in data {
+        let mut string = String::new();
+        for value in datum.as_ref() {
+             string += &format!("{:10.8e}\t",value).to_string();
+        }
+        match writeln!(file,"{}", string) {
+            Ok(_) => (),
+            Err(erro) => {
+            eprintln!("Error in writing to file. Error: {}", erro);
+            return Err(Box::new(erro) as Box<dyn Error>);},
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+pub use super::*;
+pub use crate::solver_vector_trait::{ODESystem, State, DState, Data};
+use std::thread;
+
+/// Struct EnsembleStatistics
+///
+/// Statistics of an ensemble of trajectories at each output time. `mean` has the same layout as a `Data` (first column is the time, the others are the mean of each state). `quantiles` has one `Data` for each requested quantile, in the same order as requested. `incomplete` has the indexes of the trajectories which stopped before the others (a failed integration), which are left out of the statistics.
+#[derive(Clone,Debug)]
+pub struct EnsembleStatistics {
+    pub mean : Data,
+    pub quantiles : Vec<(f64, Data)>,
+    pub incomplete : Vec<usize>,
+}
+
+
+// Number of worker threads, 0 means the available parallelism of the machine
+fn worker_threads (threads : usize, members : usize) -> usize {
+    let threads = if threads == 0 {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        threads
+    };
+    threads.min(members).max(1)
+}
+
+
+// Runs `task` for every member index across `threads` threads. The result of each member only depends on its index, so the output does not depend on the number of threads.
+pub(crate) fn run_parallel<T : Send, Task : Fn (usize) -> T + Sync> (members : usize, threads : usize, task : Task) -> Vec<T> {
+    let threads = worker_threads(threads, members);
+    let task = &task;
+
+    let mut results : Vec<Option<T>> = (0..members).map(|_| None).collect();
+    thread::scope(|scope| {
+        let handles : Vec<_> = (0..threads).map(|worker| {
+            scope.spawn(move || {
+                (worker..members).step_by(threads)
+                    .map(|member| (member, task(member)))
+                    .collect::<Vec<(usize,T)>>()
+            })
+        }).collect();
+
+        for handle in handles {
+            for (member, result) in handle.join().expect("Ensemble worker thread panicked") {
+                results[member] = Some(result);
+            }
+        }
+    });
+
+    results.into_iter().map(|r| r.expect("Ensemble member not computed")).collect()
+}
+
+
+/// Solves an ensemble of systems in parallel, returns a vector with the `Data` of each member.
+///
+/// Each member is a clone of `sist` modified by `generator`, which receives the index of the member and a mutable reference to its system (to perturb the initial state or the parameters). Each member only depends on its index, so the results are the same for any number of threads.
+///
+///# Inputs
+///
+///`sist : Sist`. The base system, any type which implements `ODESystem`, `Clone` and `Send`.
+///
+///`generator : Fn (usize, &mut Sist)`. Modifies the system of the given member.
+///
+///`members : usize`. Number of members of the ensemble.
+///
+///`odeparam : ODEParam`. An value of ODEParam.
+///
+///`odesolver : ODESolver`. A choice of an ODE solver.
+///
+///`threads : usize`. Number of worker threads, if 0 the available parallelism of the machine is used.
+pub fn solve_ensemble<Sist, Gen> (sist : Sist, generator : Gen, members : usize, odeparam : ODEParam, odesolver : ODESolver, threads : usize) -> Vec<Data>
+where Sist : ODESystem + Clone + Send + Sync,
+      Gen : Fn (usize, &mut Sist) + Sync,
+{
+    run_parallel(members, threads, |member| {
+        let mut member_sist = sist.clone();
+        generator(member, &mut member_sist);
+        solver_vector_trait::solve_ode_sist_mut(&mut member_sist, odeparam.clone(), odesolver).data()
+    })
+}
+
+
+/// Similar to `solve_ensemble` but using closures as in `solver_vector`. Returns a vector with the `Data` of each member.
+///
+/// `generator` receives the index of the member and returns its system function (a closure which can capture perturbed parameters) and its initial state.
+///
+///# Inputs
+///
+///`generator : Fn (usize) -> (SysFunc, State)`. Builds the system function and the initial state of the given member.
+///
+///`members : usize`. Number of members of the ensemble.
+///
+///`odeparam : ODEParam`. An value of ODEParam.
+///
+///`odesolver : ODESolver`. A choice of an ODE solver.
+///
+///`threads : usize`. Number of worker threads, if 0 the available parallelism of the machine is used.
+pub fn solve_ensemble_vector<SysFunc, Gen> (generator : Gen, members : usize, odeparam : ODEParam, odesolver : ODESolver, threads : usize) -> Vec<Data>
+where SysFunc : FnMut (f64, &State) -> DState,
+      Gen : Fn (usize) -> (SysFunc, State) + Sync,
+{
+    run_parallel(members, threads, |member| {
+        let (system_function, state) = generator(member);
+        solver_vector::solve_ode(system_function, odeparam.clone(), state, odesolver).data()
+    })
+}
+
+
+// Quantile q of sorted values, linearly interpolated
+pub(crate) fn quantile (sorted : &[f64], q : f64) -> f64 {
+    let position = q.clamp(0.0, 1.0)*((sorted.len() - 1) as f64);
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    let theta = position - below as f64;
+    sorted[below] + theta*(sorted[above] - sorted[below])
+}
+
+
+// Indexes of the trajectories which stopped before the others: shorter, or ending with a state which is not finite (the last output of a failed integration)
+fn incomplete (trajectories : &[Data]) -> Vec<usize> {
+    let rows = trajectories.iter().map(|d| d.len()).max().unwrap_or(0);
+    (0..trajectories.len()).filter(|&member| {
+        let data = &trajectories[member];
+        data.len() < rows || data.last().is_some_and(|datum| datum.iter().any(|x| !x.is_finite()))
+    }).collect()
+}
+
+
+/// Computes the mean and the requested quantiles (values between 0 and 1) of an ensemble at each output time.
+///
+/// All the trajectories must have the same output times, which is the case for members solved with the same `ODEParam`. The first column of each returned `Data` is the time. The trajectories which stopped before the others or end with a state which is not finite, because their integration failed, are left out of the statistics and their indexes are in `EnsembleStatistics.incomplete`.
+pub fn ensemble_statistics (trajectories : &[Data], quantiles : &[f64]) -> EnsembleStatistics {
+    let mut mean : Data = Vec::new();
+    let mut quantile_data : Vec<(f64, Data)> = quantiles.iter().map(|q| (*q, Vec::new())).collect();
+
+    let incomplete = incomplete(trajectories);
+    let trajectories : Vec<&Data> = (0..trajectories.len()).filter(|member| !incomplete.contains(member)).map(|member| &trajectories[member]).collect();
+    let rows = trajectories.first().map_or(0, |d| d.len());
+    let members = trajectories.len() as f64;
+
+    for row in 0..rows {
+        let time = trajectories[0][row][0];
+        let columns = trajectories[0][row].len();
+
+        let mut mean_datum = vec!(time);
+        let mut quantile_datums : Vec<Vec<f64>> = quantiles.iter().map(|_| vec!(time)).collect();
+
+        for column in 1..columns {
+            let mut values : Vec<f64> = trajectories.iter().map(|d| d[row][column]).collect();
+            mean_datum.push(values.iter().sum::<f64>()/members);
+
+            values.sort_by(|a,b| a.total_cmp(b));
+            for (datum, q) in quantile_datums.iter_mut().zip(quantiles) {
+                datum.push(quantile(&values, *q));
+            }
+        }
+
+        mean.push(mean_datum);
+        for ((_, data), datum) in quantile_data.iter_mut().zip(quantile_datums) {
+            data.push(datum);
+        }
+    }
+
+    EnsembleStatistics { mean, quantiles : quantile_data, incomplete }
+}
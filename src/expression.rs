@@ -0,0 +1,879 @@
+use std::error::Error;
+use std::io;
+
+use crate::solver_vector::{State, DState};
+
+/// Enum BinaryOp
+///
+/// Arithmetic operators of the expressions.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+/// Enum Function
+///
+/// Standard functions of the expressions. `ln` and `log` are the natural logarithm, `log10` the logarithm in base 10. `atan2`, `pow`, `min` and `max` have two arguments, the others have one.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Ln,
+    Log10,
+    Sqrt,
+    Abs,
+    Atan2,
+    Pow,
+    Min,
+    Max,
+}
+
+impl Function {
+
+    /// Function with the given name, if there is one.
+    pub fn from_name (name : &str) -> Option<Function> {
+        let function = match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "asin" => Function::Asin,
+            "acos" => Function::Acos,
+            "atan" => Function::Atan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "tanh" => Function::Tanh,
+            "exp" => Function::Exp,
+            "ln" | "log" => Function::Ln,
+            "log10" => Function::Log10,
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            "atan2" => Function::Atan2,
+            "pow" => Function::Pow,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            _ => return None,
+        };
+        Some(function)
+    }
+
+    /// Name of the function, as written in the expressions.
+    pub fn name (&self) -> &'static str {
+        match self {
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Tan => "tan",
+            Function::Asin => "asin",
+            Function::Acos => "acos",
+            Function::Atan => "atan",
+            Function::Sinh => "sinh",
+            Function::Cosh => "cosh",
+            Function::Tanh => "tanh",
+            Function::Exp => "exp",
+            Function::Ln => "ln",
+            Function::Log10 => "log10",
+            Function::Sqrt => "sqrt",
+            Function::Abs => "abs",
+            Function::Atan2 => "atan2",
+            Function::Pow => "pow",
+            Function::Min => "min",
+            Function::Max => "max",
+        }
+    }
+
+    /// Number of arguments of the function.
+    pub fn arity (&self) -> usize {
+        match self {
+            Function::Atan2 | Function::Pow | Function::Min | Function::Max => 2,
+            _ => 1,
+        }
+    }
+
+    /// Applies the function to the arguments, which must have the arity of the function.
+    pub fn apply (&self, args : &[f64]) -> f64 {
+        match self {
+            Function::Sin => args[0].sin(),
+            Function::Cos => args[0].cos(),
+            Function::Tan => args[0].tan(),
+            Function::Asin => args[0].asin(),
+            Function::Acos => args[0].acos(),
+            Function::Atan => args[0].atan(),
+            Function::Sinh => args[0].sinh(),
+            Function::Cosh => args[0].cosh(),
+            Function::Tanh => args[0].tanh(),
+            Function::Exp => args[0].exp(),
+            Function::Ln => args[0].ln(),
+            Function::Log10 => args[0].log10(),
+            Function::Sqrt => args[0].sqrt(),
+            Function::Abs => args[0].abs(),
+            Function::Atan2 => args[0].atan2(args[1]),
+            Function::Pow => args[0].powf(args[1]),
+            Function::Min => args[0].min(args[1]),
+            Function::Max => args[0].max(args[1]),
+        }
+    }
+}
+
+
+/// Enum Expr
+///
+/// Parsed mathematical expression. The variables are kept by name, `pi` and `e` are parsed as numbers.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Variable(String),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+
+fn invalid (message : String) -> Box<dyn Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+
+#[derive(Clone,Debug,PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+// Tokens with their positions (in characters) in the text
+fn tokenize (text : &str) -> Result<Vec<(usize, Token)>, Box<dyn Error>> {
+    let chars : Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let token = if c.is_ascii_digit() || c == '.' {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // exponent of numbers as 1.5e-3
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let number : String = chars[start..i].iter().collect();
+            Token::Number(number.parse().map_err(|_| invalid(format!("Invalid number '{}' at position {}", number, start)))?)
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Token::Name(chars[start..i].iter().collect())
+        } else {
+            i += 1;
+            match c {
+                '+' | '-' | '/' | '^' => Token::Op(c),
+                // `**` is also accepted for powers
+                '*' if i < chars.len() && chars[i] == '*' => { i += 1; Token::Op('^') },
+                '*' => Token::Op('*'),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                _ => return Err(invalid(format!("Unexpected character '{}' at position {}", c, start))),
+            }
+        };
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+
+// Recursive descent parser, from the lowest to the highest precedence:
+// sum (+ -), product (* /), unary (-), power (^, right associative), primary
+struct Parser {
+    tokens : Vec<(usize, Token)>,
+    position : usize,
+    end : usize,
+}
+
+impl Parser {
+    fn peek (&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn here (&self) -> usize {
+        self.tokens.get(self.position).map(|(start, _)| *start).unwrap_or(self.end)
+    }
+
+    fn next (&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect (&mut self, expected : Token, description : &str) -> Result<(), Box<dyn Error>> {
+        let here = self.here();
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(invalid(format!("Expected {} at position {}", description, here))),
+        }
+    }
+
+    fn sum (&mut self) -> Result<Expr, Box<dyn Error>> {
+        let mut expr = self.product()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+            self.position += 1;
+            let op = if op == '+' { BinaryOp::Add } else { BinaryOp::Sub };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product (&mut self) -> Result<Expr, Box<dyn Error>> {
+        let mut expr = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+            self.position += 1;
+            let op = if op == '*' { BinaryOp::Mul } else { BinaryOp::Div };
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary (&mut self) -> Result<Expr, Box<dyn Error>> {
+        match self.peek() {
+            Some(Token::Op('-')) => {
+                self.position += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            },
+            Some(Token::Op('+')) => {
+                self.position += 1;
+                self.unary()
+            },
+            _ => self.power(),
+        }
+    }
+
+    fn power (&mut self) -> Result<Expr, Box<dyn Error>> {
+        let base = self.primary()?;
+        if let Some(Token::Op('^')) = self.peek() {
+            self.position += 1;
+            // the exponent can have a sign, as in 2^-x
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary(BinaryOp::Pow, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn primary (&mut self) -> Result<Expr, Box<dyn Error>> {
+        let here = self.here();
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::LParen) => {
+                let expr = self.sum()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(expr)
+            },
+            Some(Token::Name(name)) => {
+                if let Some(Token::LParen) = self.peek() {
+                    let function = Function::from_name(&name)
+                        .ok_or_else(|| invalid(format!("Unknown function '{}' at position {}", name, here)))?;
+                    self.position += 1;
+                    let mut args = vec!(self.sum()?);
+                    while let Some(Token::Comma) = self.peek() {
+                        self.position += 1;
+                        args.push(self.sum()?);
+                    }
+                    self.expect(Token::RParen, "')'")?;
+                    if args.len() != function.arity() {
+                        return Err(invalid(format!("Function '{}' at position {} takes {} argument(s), {} given", name, here, function.arity(), args.len())));
+                    }
+                    return Ok(Expr::Call(function, args));
+                }
+                match name.as_str() {
+                    "pi" => Ok(Expr::Number(std::f64::consts::PI)),
+                    "e" => Ok(Expr::Number(std::f64::consts::E)),
+                    _ => Ok(Expr::Variable(name)),
+                }
+            },
+            Some(_) => Err(invalid(format!("Unexpected token at position {}", here))),
+            None => Err(invalid("Unexpected end of the expression".to_string())),
+        }
+    }
+}
+
+
+/// Names which can not be used as variables: the constants and the functions.
+pub fn is_reserved (name : &str) -> bool {
+    name == "pi" || name == "e" || Function::from_name(name).is_some()
+}
+
+
+impl Expr {
+
+    /// Parses an expression with numbers (as `2`, `1.5e-3`), variables, the operators `+ - * / ^` (`**` is also accepted for powers), parentheses, the constants `pi` and `e` and the functions of `Function`.
+    pub fn parse (text : &str) -> Result<Expr, Box<dyn Error>> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, position : 0, end : text.chars().count() };
+        let expr = parser.sum()?;
+        if parser.position < parser.tokens.len() {
+            return Err(invalid(format!("Unexpected token at position {}", parser.here())));
+        }
+        Ok(expr)
+    }
+
+    /// Evaluates the expression, the values of the variables are given by `lookup`. Returns an error if a variable is unknown.
+    pub fn eval<Lookup : Fn (&str) -> Option<f64>> (&self, lookup : &Lookup) -> Result<f64, Box<dyn Error>> {
+        let value = match self {
+            Expr::Number(value) => *value,
+            Expr::Variable(name) => lookup(name).ok_or_else(|| invalid(format!("Unknown variable '{}'", name)))?,
+            Expr::Neg(expr) => -expr.eval(lookup)?,
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.eval(lookup)?, right.eval(lookup)?);
+                match op {
+                    BinaryOp::Add => left + right,
+                    BinaryOp::Sub => left - right,
+                    BinaryOp::Mul => left * right,
+                    BinaryOp::Div => left / right,
+                    BinaryOp::Pow => left.powf(right),
+                }
+            },
+            Expr::Call(function, args) => {
+                let args = args.iter().map(|arg| arg.eval(lookup)).collect::<Result<Vec<f64>,_>>()?;
+                function.apply(&args)
+            },
+        };
+        Ok(value)
+    }
+
+    /// Names of the variables of the expression, without repetitions, in the order they appear.
+    pub fn variables (&self) -> Vec<String> {
+        let mut variables = Vec::new();
+        self.collect_variables(&mut variables);
+        variables
+    }
+
+    fn collect_variables (&self, variables : &mut Vec<String>) {
+        match self {
+            Expr::Number(_) => (),
+            Expr::Variable(name) => {
+                if !variables.contains(name) {
+                    variables.push(name.clone());
+                }
+            },
+            Expr::Neg(expr) => expr.collect_variables(variables),
+            Expr::Binary(_, left, right) => {
+                left.collect_variables(variables);
+                right.collect_variables(variables);
+            },
+            Expr::Call(_, args) => {
+                for arg in args {
+                    arg.collect_variables(variables);
+                }
+            },
+        }
+    }
+}
+
+
+// Constructors which simplify the result, used by `derivative` so that the derivatives do not grow with terms as `0*x` or `x*1`
+fn number (expr : &Expr) -> Option<f64> {
+    match expr {
+        Expr::Number(value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn neg (a : Expr) -> Expr {
+    match a {
+        Expr::Number(value) => Expr::Number(-value),
+        Expr::Neg(a) => *a,
+        a => Expr::Neg(Box::new(a)),
+    }
+}
+
+fn add (a : Expr, b : Expr) -> Expr {
+    match (number(&a), number(&b)) {
+        (Some(x), Some(y)) => Expr::Number(x + y),
+        (Some(0.0), _) => b,
+        (_, Some(0.0)) => a,
+        _ => match b {
+            Expr::Neg(b) => Expr::Binary(BinaryOp::Sub, Box::new(a), b),
+            b => Expr::Binary(BinaryOp::Add, Box::new(a), Box::new(b)),
+        },
+    }
+}
+
+fn sub (a : Expr, b : Expr) -> Expr {
+    match (number(&a), number(&b)) {
+        (Some(x), Some(y)) => Expr::Number(x - y),
+        (Some(0.0), _) => neg(b),
+        (_, Some(0.0)) => a,
+        _ => match b {
+            Expr::Neg(b) => Expr::Binary(BinaryOp::Add, Box::new(a), b),
+            b => Expr::Binary(BinaryOp::Sub, Box::new(a), Box::new(b)),
+        },
+    }
+}
+
+fn mul (a : Expr, b : Expr) -> Expr {
+    match (number(&a), number(&b)) {
+        (Some(x), Some(y)) => Expr::Number(x * y),
+        (Some(0.0), _) | (_, Some(0.0)) => Expr::Number(0.0),
+        (Some(1.0), _) => b,
+        (_, Some(1.0)) => a,
+        (Some(-1.0), _) => neg(b),
+        (_, Some(-1.0)) => neg(a),
+        _ => Expr::Binary(BinaryOp::Mul, Box::new(a), Box::new(b)),
+    }
+}
+
+fn div (a : Expr, b : Expr) -> Expr {
+    match (number(&a), number(&b)) {
+        (Some(x), Some(y)) if y != 0.0 => Expr::Number(x / y),
+        (Some(0.0), _) => Expr::Number(0.0),
+        (_, Some(1.0)) => a,
+        _ => Expr::Binary(BinaryOp::Div, Box::new(a), Box::new(b)),
+    }
+}
+
+fn pow (a : Expr, b : Expr) -> Expr {
+    match (number(&a), number(&b)) {
+        (Some(x), Some(y)) => Expr::Number(x.powf(y)),
+        (_, Some(0.0)) => Expr::Number(1.0),
+        (_, Some(1.0)) => a,
+        _ => Expr::Binary(BinaryOp::Pow, Box::new(a), Box::new(b)),
+    }
+}
+
+fn call (function : Function, args : Vec<Expr>) -> Expr {
+    match args.iter().map(number).collect::<Option<Vec<f64>>>() {
+        Some(values) => Expr::Number(function.apply(&values)),
+        None => Expr::Call(function, args),
+    }
+}
+
+
+impl Expr {
+
+    /// True if the expression depends on the variable.
+    pub fn depends_on (&self, variable : &str) -> bool {
+        match self {
+            Expr::Number(_) => false,
+            Expr::Variable(name) => name == variable,
+            Expr::Neg(expr) => expr.depends_on(variable),
+            Expr::Binary(_, left, right) => left.depends_on(variable) || right.depends_on(variable),
+            Expr::Call(_, args) => args.iter().any(|arg| arg.depends_on(variable)),
+        }
+    }
+
+    /// Symbolic derivative of the expression with respect to the variable, simplified.
+    ///
+    /// `abs`, `min` and `max` are differentiated as `abs(x) = x*sign(x)`, so their derivatives are not defined (NaN) where the derivative is discontinuous.
+    pub fn derivative (&self, variable : &str) -> Expr {
+        if !self.depends_on(variable) {
+            return Expr::Number(0.0);
+        }
+
+        match self {
+            Expr::Number(_) => Expr::Number(0.0),
+            Expr::Variable(_) => Expr::Number(1.0),
+            Expr::Neg(a) => neg(a.derivative(variable)),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.as_ref().clone(), b.as_ref().clone());
+                let (da, db) = (a.derivative(variable), b.derivative(variable));
+                match op {
+                    BinaryOp::Add => add(da, db),
+                    BinaryOp::Sub => sub(da, db),
+                    BinaryOp::Mul => add(mul(da, b.clone()), mul(a, db)),
+                    BinaryOp::Div => div(sub(mul(da, b.clone()), mul(a, db)), pow(b, Expr::Number(2.0))),
+                    BinaryOp::Pow => power_derivative(a, b, da, db),
+                }
+            },
+            Expr::Call(function, args) => {
+                let a = args[0].clone();
+                let da = a.derivative(variable);
+                let sign = |x : Expr| div(x.clone(), call(Function::Abs, vec!(x)));
+                match function {
+                    Function::Sin => mul(call(Function::Cos, vec!(a)), da),
+                    Function::Cos => neg(mul(call(Function::Sin, vec!(a)), da)),
+                    Function::Tan => div(da, pow(call(Function::Cos, vec!(a)), Expr::Number(2.0))),
+                    Function::Asin => div(da, call(Function::Sqrt, vec!(sub(Expr::Number(1.0), pow(a, Expr::Number(2.0)))))),
+                    Function::Acos => neg(div(da, call(Function::Sqrt, vec!(sub(Expr::Number(1.0), pow(a, Expr::Number(2.0))))))),
+                    Function::Atan => div(da, add(Expr::Number(1.0), pow(a, Expr::Number(2.0)))),
+                    Function::Sinh => mul(call(Function::Cosh, vec!(a)), da),
+                    Function::Cosh => mul(call(Function::Sinh, vec!(a)), da),
+                    Function::Tanh => mul(sub(Expr::Number(1.0), pow(call(Function::Tanh, vec!(a)), Expr::Number(2.0))), da),
+                    Function::Exp => mul(call(Function::Exp, vec!(a)), da),
+                    Function::Ln => div(da, a),
+                    Function::Log10 => div(da, mul(a, Expr::Number(std::f64::consts::LN_10))),
+                    Function::Sqrt => div(da, mul(Expr::Number(2.0), call(Function::Sqrt, vec!(a)))),
+                    Function::Abs => mul(sign(a), da),
+                    Function::Atan2 => {
+                        // atan2(y, x)
+                        let (y, dy, x) = (a, da, args[1].clone());
+                        let dx = x.derivative(variable);
+                        div(sub(mul(x.clone(), dy), mul(y.clone(), dx)), add(pow(x, Expr::Number(2.0)), pow(y, Expr::Number(2.0))))
+                    },
+                    Function::Pow => {
+                        let b = args[1].clone();
+                        let db = b.derivative(variable);
+                        power_derivative(a, b, da, db)
+                    },
+                    Function::Min | Function::Max => {
+                        // min(a,b) = (a + b - |a - b|)/2, max(a,b) = (a + b + |a - b|)/2
+                        let b = args[1].clone();
+                        let db = b.derivative(variable);
+                        let dabs = mul(sign(sub(a, b)), sub(da.clone(), db.clone()));
+                        let dsum = add(da, db);
+                        let d = if *function == Function::Min { sub(dsum, dabs) } else { add(dsum, dabs) };
+                        div(d, Expr::Number(2.0))
+                    },
+                }
+            },
+        }
+    }
+}
+
+fn power_derivative (a : Expr, b : Expr, da : Expr, db : Expr) -> Expr {
+    if let Some(exponent) = number(&b) {
+        // d(a^n) = n*a^(n-1)*da
+        return mul(mul(b, pow(a, Expr::Number(exponent - 1.0))), da);
+    }
+    if number(&a).is_some() {
+        // d(c^b) = c^b*ln(c)*db
+        return mul(mul(pow(a.clone(), b), call(Function::Ln, vec!(a))), db);
+    }
+    // d(a^b) = a^b*(db*ln(a) + b*da/a)
+    mul(pow(a.clone(), b.clone()), add(mul(db, call(Function::Ln, vec!(a.clone()))), div(mul(b, da), a)))
+}
+
+
+/// Symbolic Jacobian of the expressions with respect to the variables: the element `[i][j]` is the derivative of `expressions[i]` with respect to `variables[j]`.
+pub fn jacobian (expressions : &[Expr], variables : &[&str]) -> Vec<Vec<Expr>> {
+    expressions.iter().map(|expr| variables.iter().map(|variable| expr.derivative(variable)).collect()).collect()
+}
+
+
+// Precedence of the expressions when written: sums, products, negation, powers and atoms
+fn precedence (expr : &Expr) -> u8 {
+    match expr {
+        Expr::Binary(BinaryOp::Add | BinaryOp::Sub, _, _) => 1,
+        Expr::Binary(BinaryOp::Mul | BinaryOp::Div, _, _) => 2,
+        Expr::Neg(_) => 3,
+        Expr::Number(value) if *value < 0.0 => 3,
+        Expr::Binary(BinaryOp::Pow, _, _) => 4,
+        _ => 5,
+    }
+}
+
+fn write_operand (f : &mut std::fmt::Formatter, expr : &Expr, parentheses : bool) -> std::fmt::Result {
+    if parentheses {
+        write!(f, "({})", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+/// Writes the expression in the syntax of `Expr::parse`, with the parentheses needed to read it back.
+impl std::fmt::Display for Expr {
+    fn fmt (&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Expr::Number(value) => write!(f, "{}", value),
+            Expr::Variable(name) => write!(f, "{}", name),
+            Expr::Neg(expr) => {
+                write!(f, "-")?;
+                write_operand(f, expr, precedence(expr) < 3)
+            },
+            Expr::Binary(op, left, right) => {
+                let (symbol, level) = match op {
+                    BinaryOp::Add => ("+", 1),
+                    BinaryOp::Sub => ("-", 1),
+                    BinaryOp::Mul => ("*", 2),
+                    BinaryOp::Div => ("/", 2),
+                    BinaryOp::Pow => ("^", 4),
+                };
+                if *op == BinaryOp::Pow {
+                    // the base is an atom, the exponent can have a sign
+                    write_operand(f, left, precedence(left) <= 4)?;
+                    write!(f, "^")?;
+                    return write_operand(f, right, precedence(right) < 3);
+                }
+                write_operand(f, left, precedence(left) < level)?;
+                write!(f, " {} ", symbol)?;
+                // a - (b + c), a/(b*c)
+                let right_level = precedence(right);
+                let parentheses = right_level < level || (right_level == level && matches!(op, BinaryOp::Sub | BinaryOp::Div))
+                    || (level == 2 && right_level == 3);
+                write_operand(f, right, parentheses)
+            },
+            Expr::Call(function, args) => {
+                write!(f, "{}(", function.name())?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            },
+        }
+    }
+}
+
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum Instruction {
+    Constant(f64),
+    Load(usize),
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Powi(i32),
+    Call(Function),
+}
+
+/// Struct Program
+///
+/// Compiled form of an expression: a sequence of stack instructions with the variables resolved to indexes, so the evaluation does not look up names or follow pointers. The constant subexpressions are folded and integer powers use `powi`.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Program {
+    instructions : Vec<Instruction>,
+    stack_size : usize,
+}
+
+impl Expr {
+
+    /// Compiles the expression, the value of each variable is given in `Program::eval` at its index in `variables`. Returns an error if a variable of the expression is not in `variables`.
+    pub fn compile (&self, variables : &[&str]) -> Result<Program, Box<dyn Error>> {
+        let mut instructions = Vec::new();
+        self.fold().emit(variables, &mut instructions)?;
+
+        // maximum size of the stack during the evaluation
+        let mut size : isize = 0;
+        let mut stack_size = 0;
+        for instruction in &instructions {
+            size += match instruction {
+                Instruction::Constant(_) | Instruction::Load(_) => 1,
+                Instruction::Neg | Instruction::Powi(_) => 0,
+                Instruction::Call(function) => 1 - function.arity() as isize,
+                _ => -1,
+            };
+            stack_size = stack_size.max(size as usize);
+        }
+
+        Ok(Program { instructions, stack_size })
+    }
+
+    // Expression with the constant subexpressions evaluated
+    fn fold (&self) -> Expr {
+        match self {
+            Expr::Number(_) | Expr::Variable(_) => self.clone(),
+            Expr::Neg(a) => match a.fold() {
+                Expr::Number(value) => Expr::Number(-value),
+                a => Expr::Neg(Box::new(a)),
+            },
+            Expr::Binary(op, a, b) => match (op, a.fold(), b.fold()) {
+                (BinaryOp::Add, Expr::Number(x), Expr::Number(y)) => Expr::Number(x + y),
+                (BinaryOp::Sub, Expr::Number(x), Expr::Number(y)) => Expr::Number(x - y),
+                (BinaryOp::Mul, Expr::Number(x), Expr::Number(y)) => Expr::Number(x * y),
+                (BinaryOp::Div, Expr::Number(x), Expr::Number(y)) => Expr::Number(x / y),
+                (BinaryOp::Pow, Expr::Number(x), Expr::Number(y)) => Expr::Number(x.powf(y)),
+                (op, a, b) => Expr::Binary(*op, Box::new(a), Box::new(b)),
+            },
+            Expr::Call(function, args) => call(*function, args.iter().map(|arg| arg.fold()).collect()),
+        }
+    }
+
+    fn emit (&self, variables : &[&str], instructions : &mut Vec<Instruction>) -> Result<(), Box<dyn Error>> {
+        match self {
+            Expr::Number(value) => instructions.push(Instruction::Constant(*value)),
+            Expr::Variable(name) => {
+                let index = variables.iter().position(|variable| variable == name)
+                    .ok_or_else(|| invalid(format!("Unknown variable '{}'", name)))?;
+                instructions.push(Instruction::Load(index));
+            },
+            Expr::Neg(a) => {
+                a.emit(variables, instructions)?;
+                instructions.push(Instruction::Neg);
+            },
+            Expr::Binary(BinaryOp::Pow, a, b) if number(b).is_some_and(|y| y.fract() == 0.0 && y.abs() <= i32::MAX as f64) => {
+                a.emit(variables, instructions)?;
+                instructions.push(Instruction::Powi(number(b).unwrap_or(1.0) as i32));
+            },
+            Expr::Binary(op, a, b) => {
+                a.emit(variables, instructions)?;
+                b.emit(variables, instructions)?;
+                instructions.push(match op {
+                    BinaryOp::Add => Instruction::Add,
+                    BinaryOp::Sub => Instruction::Sub,
+                    BinaryOp::Mul => Instruction::Mul,
+                    BinaryOp::Div => Instruction::Div,
+                    BinaryOp::Pow => Instruction::Pow,
+                });
+            },
+            Expr::Call(function, args) => {
+                for arg in args {
+                    arg.emit(variables, instructions)?;
+                }
+                instructions.push(Instruction::Call(*function));
+            },
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+
+    /// Evaluates the program with the `values` of the variables, in the order given to `Expr::compile`. `stack` is a working buffer, which can be reused between evaluations to avoid allocations.
+    pub fn eval (&self, values : &[f64], stack : &mut Vec<f64>) -> f64 {
+        stack.clear();
+        stack.reserve(self.stack_size);
+
+        for instruction in &self.instructions {
+            match *instruction {
+                Instruction::Constant(value) => stack.push(value),
+                Instruction::Load(index) => stack.push(values[index]),
+                Instruction::Neg => {
+                    let a = stack.pop().unwrap_or(f64::NAN);
+                    stack.push(-a);
+                },
+                Instruction::Powi(n) => {
+                    let a = stack.pop().unwrap_or(f64::NAN);
+                    stack.push(a.powi(n));
+                },
+                Instruction::Call(function) => {
+                    let arity = function.arity();
+                    let value = function.apply(&stack[stack.len() - arity ..]);
+                    stack.truncate(stack.len() - arity);
+                    stack.push(value);
+                },
+                instruction => {
+                    let b = stack.pop().unwrap_or(f64::NAN);
+                    let a = stack.pop().unwrap_or(f64::NAN);
+                    stack.push(match instruction {
+                        Instruction::Add => a + b,
+                        Instruction::Sub => a - b,
+                        Instruction::Mul => a * b,
+                        Instruction::Div => a / b,
+                        _ => a.powf(b),
+                    });
+                },
+            }
+        }
+
+        stack.pop().unwrap_or(f64::NAN)
+    }
+}
+
+
+/// Struct ExprSystem
+///
+/// System of ODEs given by the expressions of the derivatives of the states, compiled to `Program`s. It gives the system function used by `solver_vector::solve_ode` and the exact Jacobian, from the symbolic derivatives of the expressions.
+///
+/// The variables of the expressions are the time `t`, the states and the parameters.
+#[derive(Clone,Debug)]
+pub struct ExprSystem {
+    derivatives : Vec<Program>,
+    jacobian : Vec<Vec<Program>>,
+    params : Vec<String>,
+    // values of the variables: the time, the states and the parameters
+    values : Vec<f64>,
+    stack : Vec<f64>,
+}
+
+impl ExprSystem {
+
+    /// Compiles the system, `derivatives[i]` is the expression of the derivative of `states[i]`. Returns an error if the sizes do not match or if an expression has an unknown variable.
+    pub fn new (derivatives : &[Expr], states : &[&str], params : &[(&str, f64)]) -> Result<ExprSystem, Box<dyn Error>> {
+        if derivatives.len() != states.len() {
+            return Err(invalid(format!("{} expressions given for {} states", derivatives.len(), states.len())));
+        }
+
+        let mut variables = vec!("t");
+        variables.extend_from_slice(states);
+        variables.extend(params.iter().map(|(name, _)| *name));
+
+        let compiled = derivatives.iter().map(|expr| expr.compile(&variables)).collect::<Result<Vec<_>,_>>()?;
+        let jacobian = jacobian(derivatives, states).iter()
+            .map(|row| row.iter().map(|expr| expr.compile(&variables)).collect::<Result<Vec<_>,_>>())
+            .collect::<Result<Vec<_>,_>>()?;
+
+        let mut values = vec!(0.0; 1 + states.len());
+        values.extend(params.iter().map(|(_, value)| *value));
+
+        Ok(ExprSystem {
+            derivatives : compiled,
+            jacobian,
+            params : params.iter().map(|(name, _)| name.to_string()).collect(),
+            values,
+            stack : Vec::new(),
+        })
+    }
+
+    /// Changes the value of a parameter.
+    pub fn set_param (&mut self, name : &str, value : f64) -> Result<(), Box<dyn Error>> {
+        let index = self.params.iter().position(|param| param == name)
+            .ok_or_else(|| invalid(format!("No parameter named '{}'", name)))?;
+        let states = self.derivatives.len();
+        self.values[1 + states + index] = value;
+        Ok(())
+    }
+
+    fn load (&mut self, time : f64, state : &[f64]) {
+        self.values[0] = time;
+        self.values[1 .. 1 + state.len()].copy_from_slice(state);
+    }
+
+    /// Derivative of the state at the given time.
+    pub fn dstate (&mut self, time : f64, state : &[f64]) -> Vec<f64> {
+        self.load(time, state);
+        let ExprSystem { derivatives, values, stack, .. } = self;
+        derivatives.iter().map(|program| program.eval(values, stack)).collect()
+    }
+
+    /// Jacobian of the derivative with respect to the state, the element `[i][j]` is the derivative of `dstate[i]` with respect to `state[j]`.
+    pub fn jacobian (&mut self, time : f64, state : &[f64]) -> Vec<Vec<f64>> {
+        self.load(time, state);
+        let ExprSystem { jacobian, values, stack, .. } = self;
+        jacobian.iter().map(|row| row.iter().map(|program| program.eval(values, stack)).collect()).collect()
+    }
+
+    /// System function, as used by `solver_vector::solve_ode`.
+    pub fn system_function (&self) -> impl FnMut (f64, &State) -> DState + Clone {
+        let mut system = self.clone();
+        move |time : f64, state : &State| -> DState { system.dstate(time, state) }
+    }
+
+    /// Function which gives the Jacobian at a time and state, as `jacobian`.
+    pub fn jacobian_function (&self) -> impl FnMut (f64, &State) -> Vec<Vec<f64>> + Clone {
+        let mut system = self.clone();
+        move |time : f64, state : &State| -> Vec<Vec<f64>> { system.jacobian(time, state) }
+    }
+}
@@ -0,0 +1,182 @@
+pub use super::*;
+pub use crate::solver_vector_trait::{ODESystem, State, DState, Data, data_to_file};
+pub use crate::result::{SolveResult, SolveStats, Termination};
+use crate::result::non_finite;
+use crate::solver_vector_trait::step_sist_mut;
+use crate::engine::Workspace;
+use std::time::Instant;
+
+///Trait used to caracterize a hybrid system: the continuous flow is given by `ODESystem::dstate` and the discrete jumps by guards and reset maps.
+///
+///A jump is triggered when a guard function crosses zero from positive to zero or negative values (e.g. the height of a bouncing ball reaching the floor). Then the reset map of the triggered guard is applied to the system, which can change the state and any other field of the data type (e.g. the mode of a switching power converter).
+pub trait HybridSystem : ODESystem {
+
+    ///Returns the values of the guard functions at the actual state.
+    fn guards (&self, time : f64) -> Vec<f64>;
+
+    ///Applies the reset map of the triggered `guard` (index in the vector returned by `guards`).
+    fn reset (&mut self, guard : usize, time : f64);
+
+    ///Returns true if the integration has to stop when the `guard` is triggered, instead of applying its reset map. By default no guard is terminal.
+    fn terminal (&self, _guard : usize) -> bool {
+        false
+    }
+}
+
+/// A discrete jump of a hybrid system, at `time` triggered by the guard of index `guard`.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Event {
+    pub time : f64,
+    pub guard : usize,
+}
+
+
+// Index of the first guard which crossed zero from positive values
+fn triggered (before : &[f64], after : &[f64]) -> Option<usize> {
+    before.iter().zip(after).position(|(b,a)| *b > 0.0 && *a <= 0.0)
+}
+
+
+// A state which is not finite ends the integration, as in `engine::integrate`
+fn finite<Sist : ODESystem> (sist : &Sist) -> bool {
+    sist.state().iter().all(|x| x.is_finite())
+}
+
+
+fn push<Sist : ODESystem> (times : &mut Vec<f64>, states : &mut Vec<State>, time : f64, sist : &Sist) {
+    times.push(time);
+    states.push(sist.state().clone());
+}
+
+
+/// Main function for solving hybrid systems, returns a tuple with (SolveResult, Vec<Event>) where the Sist and ODEParam of the `SolveResult` have updated values.
+///
+/// The guards are checked after each step. When a guard is triggered the step is bisected until the event time is found within `event_tol`, the integration is stopped at the event time, the reset map is applied and the integration restarts from the new state. Both the pre-jump and the post-jump points are added to the output at the event time. The step after the jump ends at the next time of the grid of `ODEParam.step`, so the other output times are not shifted by the events. If the guard is `terminal` the integration ends at the event time, with `Termination::Event`.
+///
+/// The buffers and the history of the solver are kept from one step to the next and only restarted after the jumps, so the adaptive solvers continue with their step size and order between the events. In the statistics, the step over the event is counted as rejected, and the steps of the bisection are counted in the evaluations of the system. If the state stops being finite, in a step or in the bisection, it is added to the output and the integration ends with `Termination::Error`.
+///
+///# Inputs
+///
+///`sist : Sist`. Any type which implements `HybridSystem` and `Clone`
+///
+///`odeparam : ODEParam`. An value of ODEParam.
+///
+///`odesolver : ODESolver`. A choice of an ODE solver.
+///
+///`event_tol : f64`. Tolerance of the event time.
+pub fn solve_hybrid<Sist : HybridSystem + Clone> (sist : Sist, odeparam : ODEParam, odesolver : ODESolver, event_tol : f64) -> (SolveResult<Sist>, Vec<Event>) {
+
+    let start = Instant::now();
+    let tend = odeparam.tend;
+    let step = odeparam.step;
+    let ratio_step_output = odeparam.ratio_step_output;
+
+    let mut times : Vec<f64> = Vec::new();
+    let mut states : Vec<State> = Vec::new();
+    let mut stats = SolveStats::default();
+    let mut events : Vec<Event> = Vec::new();
+    let mut sist = sist;
+    let mut time = odeparam.time;
+    // last point of the grid of `odeparam.step` reached and the steps of the grid since the last output time, kept over the jumps
+    let mut grid_time = time;
+    let mut steps = 0;
+    let mut workspace = Workspace::new(sist.state());
+
+    push(&mut times, &mut states, time, &sist);
+
+    while (time - tend).abs() >= 1.0e-10 {
+        let grid_next = if (grid_time + step) - tend > 0.0 { tend } else { grid_time + step };
+        // the full step on the grid, or what is left of it after a jump
+        let new_step = grid_next - time;
+        let guards = sist.guards(time);
+        let previous = sist.clone();
+        let mut step_stats = SolveStats::default();
+
+        step_sist_mut(&mut sist, time, new_step, odesolver, &mut workspace, &mut step_stats);
+        stats.fevals += step_stats.fevals;
+        if !finite(&sist) {
+            time += new_step;
+            push(&mut times, &mut states, time, &sist);
+            stats.termination = Termination::Error(non_finite(time));
+            break;
+        }
+
+        if triggered(&guards, &sist.guards(time + new_step)).is_some() {
+            stats.rejected_steps += 1;
+
+            // bisection of the step, `upper` always has a triggered guard
+            let mut lower = 0.0;
+            let mut upper = new_step;
+            let mut upper_sist = sist;
+            let mut failed = None;
+            while upper - lower > event_tol {
+                let middle = 0.5*(lower + upper);
+                let mut middle_sist = previous.clone();
+                // the bisection is rare enough to restart the solver at each try
+                let mut middle_workspace = Workspace::new(previous.state());
+                let mut middle_stats = SolveStats::default();
+                step_sist_mut(&mut middle_sist, time, middle, odesolver, &mut middle_workspace, &mut middle_stats);
+                stats.fevals += middle_stats.fevals;
+                if !finite(&middle_sist) {
+                    failed = Some((middle_sist, time + middle));
+                    break;
+                }
+                if triggered(&guards, &middle_sist.guards(time + middle)).is_some() {
+                    upper = middle;
+                    upper_sist = middle_sist;
+                } else {
+                    lower = middle;
+                }
+            }
+
+            if let Some((failed_sist, failed_time)) = failed {
+                sist = failed_sist;
+                time = failed_time;
+                push(&mut times, &mut states, time, &sist);
+                stats.termination = Termination::Error(non_finite(time));
+                break;
+            }
+
+            sist = upper_sist;
+            time += upper;
+            stats.accepted_steps += 1;
+            if time == grid_next {
+                grid_time = grid_next;
+                steps += 1;
+            }
+            let guard = triggered(&guards, &sist.guards(time)).unwrap_or(0);
+
+            push(&mut times, &mut states, time, &sist);
+            events.push(Event { time, guard });
+            if sist.terminal(guard) {
+                stats.termination = Termination::Event;
+                break;
+            }
+            sist.reset(guard, time);
+            push(&mut times, &mut states, time, &sist);
+            // the history of the solver is not valid after the jump
+            workspace = Workspace::new(sist.state());
+            if steps == ratio_step_output {
+                steps = 0;
+            }
+            continue;
+        }
+
+        time = grid_next;
+        grid_time = grid_next;
+        stats.accepted_steps += step_stats.accepted_steps;
+        stats.rejected_steps += step_stats.rejected_steps;
+        steps += 1;
+        if steps == ratio_step_output || (time - tend).abs() < 1.0e-10 {
+            push(&mut times, &mut states, time, &sist);
+            steps = 0;
+        }
+    }
+
+    stats.wall_time = start.elapsed();
+
+    let new_param = ODEParam {
+        time, .. odeparam};
+
+    (SolveResult { times, states, system : sist, odeparam : new_param, stats }, events)
+}
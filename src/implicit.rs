@@ -0,0 +1,69 @@
+//! Helpers of the solvers which solve linear systems with the Jacobian of the system (the BDF of `ODESolver::Lsoda`, the Rosenbrock and the collocation methods): the Jacobian given by the system or by finite differences, and the factorization of the matrices `c*I - J`, real and complex.
+
+use crate::engine::{Scalar, System, VectorSpace};
+use crate::linalg::LU;
+
+
+/// Jacobian of the system at `time` and its actual state, with the rows stored as states in `jacobian`: the one given by `System::jacobian_at`, else by forward differences, `dstate` being the differential at the actual state. `perturbed` and `dperturbed` are buffers. Returns the number of evaluations of the system.
+pub(crate) fn jacobian<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, dstate : &Sys::State, jacobian : &mut Vec<Sys::State>, perturbed : &mut Sys::State, dperturbed : &mut Sys::State) -> u64 {
+    let size = dstate.components().len();
+    jacobian.resize_with(size, || dstate.zeros_like());
+    perturbed.assign(system.state());
+
+    if system.jacobian_at(time, perturbed, jacobian) {
+        return 0;
+    }
+
+    for j in 0 .. size {
+        let x = system.state().components()[j];
+        let h = f64::EPSILON.sqrt()*x.magnitude().max(1.0);
+        perturbed.components_mut()[j] = x + T::from_f64(h);
+        system.dstate_at(time, perturbed, dperturbed);
+        perturbed.components_mut()[j] = x;
+
+        for (i, row) in jacobian.iter_mut().enumerate() {
+            row.components_mut()[j] = (dperturbed.components()[i] - dstate.components()[i]).scale(1.0/h);
+        }
+    }
+    size as u64
+}
+
+/// LU factorization of `c*I - J`, where `jacobian` has the rows of J, or `None` if it is singular.
+pub(crate) fn factorize<T : Scalar, S : VectorSpace<T>> (c : f64, jacobian : &[S]) -> Option<LU<T>> {
+    let matrix : Vec<Vec<T>> = jacobian.iter().enumerate().map(|(i, row)| {
+        row.components().iter().enumerate().map(|(j, x)| if i == j { T::from_f64(c) - *x } else { -*x }).collect()
+    }).collect();
+    LU::new(&matrix)
+}
+
+/// Solves the linear system of `lu` for the right side `b`, where the solution is written.
+pub(crate) fn solve_in_place<T : Scalar, S : VectorSpace<T>> (lu : &LU<T>, b : &mut S) {
+    let solution = lu.solve(b.components());
+    b.components_mut().copy_from_slice(&solution);
+}
+
+/// LU factorization of the real form of the complex system `(alpha - i*beta)*I - J`, the matrix `[[alpha*I - J, beta*I], [-beta*I, alpha*I - J]]` of size twice the size of J, or `None` if it is singular.
+pub(crate) fn factorize_complex<T : Scalar, S : VectorSpace<T>> (alpha : f64, beta : f64, jacobian : &[S]) -> Option<LU<T>> {
+    let size = jacobian.len();
+    let mut matrix = vec!(vec!(T::zero(); 2*size); 2*size);
+    for (i, row) in jacobian.iter().enumerate() {
+        for (j, x) in row.components().iter().enumerate() {
+            matrix[i][j] = -*x;
+            matrix[size + i][size + j] = -*x;
+        }
+        matrix[i][i] += T::from_f64(alpha);
+        matrix[size + i][size + i] += T::from_f64(alpha);
+        matrix[i][size + i] = T::from_f64(beta);
+        matrix[size + i][i] = T::from_f64(-beta);
+    }
+    LU::new(&matrix)
+}
+
+/// Solves the system of `factorize_complex` for the right sides `a` (first half) and `b` (second half), where the solution is written.
+pub(crate) fn solve_complex_in_place<T : Scalar, S : VectorSpace<T>> (lu : &LU<T>, a : &mut S, b : &mut S) {
+    let size = a.components().len();
+    let rhs : Vec<T> = a.components().iter().chain(b.components()).copied().collect();
+    let solution = lu.solve(&rhs);
+    a.components_mut().copy_from_slice(&solution[.. size]);
+    b.components_mut().copy_from_slice(&solution[size ..]);
+}
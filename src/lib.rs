@@ -12,7 +12,8 @@ use std::io::Write;
 /// Struct ODEParam
 ///
 /// It has all the parameters needed for the ODE solver: the actual `time`, the time for ending the simulation `tend`, the integration `step` and `relStepOut` which gives a ratio between the step time and the output time. If `ratio_step_output` is 1, at every step, output data will be added to the container, if it is 3, at every 3 steps, output data will be added to the container.
-#[derive(Clone)]
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ODEParam {
     pub time : f64,
     pub tend : f64,
@@ -20,13 +21,124 @@ pub struct ODEParam {
     pub ratio_step_output: usize,
 }
 
+/// Struct Tolerance
+///
+/// Tolerances of the adaptive solvers: the local error of each component of the state in a step is kept below `atol + rtol*|state|`.
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tolerance {
+    pub rtol : f64,
+    pub atol : f64,
+}
+
+/// `rtol` of 1e-6 and `atol` of 1e-9.
+impl Default for Tolerance {
+    fn default () -> Self {
+        Tolerance { rtol : 1.0e-6, atol : 1.0e-9 }
+    }
+}
+
 /// Enum ODESolver
 ///
 /// Contains the available solvers in the library
-#[derive(Clone,Copy)]
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ODESolver {
     RK4,
     Euler,
+    /// Adams–Bashforth–Moulton predictor–corrector (PECE) of variable step and variable order (1 to 12), started with RK4 steps under error control, the first one chosen for the `Tolerance` and of at most `ODEParam.step`. The steps are chosen by the solver for the given `Tolerance`, landing exactly at the output times (every `step*ratio_step_output`). It evaluates the system twice per step, for smooth non-stiff problems with expensive differentials.
+    Adams(Tolerance),
+    /// Automatic switching between `Adams` and the BDF (backward differentiation formulas, order 1 to 5) for stiff problems, as in LSODA: it starts with `Adams`, switches to BDF when the stability of `Adams` limits the step much below the step allowed by the `Tolerance`, and back when the problem is no longer stiff. The BDF solve the implicit equations by Newton iterations with a finite difference Jacobian.
+    Lsoda(Tolerance),
+    /// Rosenbrock method ROS3P (Lang and Verwer) of order 3 with an embedded method of order 2 for the steps of the `Tolerance`, started with a step of `ODEParam.step` and landing exactly at the output times. It is linearly implicit: each step needs only one Jacobian of the system (given by the system or by finite differences) and the solution of linear systems, with no Newton iterations. For stiff problems at moderate tolerances.
+    Ros3p(Tolerance),
+    /// Rosenbrock method Rodas4 (Hairer and Wanner) of order 4 with an embedded method of order 3, as `Ros3p`. It is stiffly accurate, for stiff problems at tighter tolerances.
+    Rodas4(Tolerance),
+    /// Radau IIA implicit Runge–Kutta method of order 5 (three stages), stiffly accurate and L-stable, with the steps of the `Tolerance` as RADAU5 of Hairer and Wanner. The stages are solved by simplified Newton iterations with the Jacobian of the system (given by the system or by finite differences), kept while the iterations converge fast. For very stiff problems and tight tolerances.
+    Radau5(Tolerance),
+    /// Gauss–Legendre implicit Runge–Kutta method of order 6 (three stages) with the fixed step `ODEParam.step`. It is symplectic and conserves the quadratic invariants, for long-term integration of conservative (Hamiltonian) systems. The stages are solved by simplified Newton iterations almost to the rounding errors, with a new Jacobian at each step.
+    GaussLegendre,
+    /// Implicit (backward) Euler method of order 1 with the fixed step `ODEParam.step`. It is L-stable, so the steps are limited by the accuracy and not by the stiffness, as for the diffusion of method-of-lines models where `Euler` needs tiny steps. The new state is solved by simplified Newton iterations with a new Jacobian (given by the system or by finite differences) at each step.
+    BackwardEuler,
+    /// Trapezoidal rule (Crank–Nicolson) of order 2 with the fixed step `ODEParam.step`, solved as `BackwardEuler`. It is A-stable but not L-stable: the very stiff components are not damped but oscillate.
+    Trapezoidal,
+}
+
+impl ODESolver {
+
+    /// Number of evaluations of the system function in each step, without the ones for the Jacobian. For `Radau5`, `GaussLegendre`, `BackwardEuler` and `Trapezoidal` in each Newton iteration.
+    pub fn stages (&self) -> u64 {
+        match self {
+            ODESolver::RK4 => 4,
+            ODESolver::Euler | ODESolver::BackwardEuler | ODESolver::Trapezoidal => 1,
+            ODESolver::Adams(_) | ODESolver::Lsoda(_) => 2,
+            ODESolver::Ros3p(_) => 4,
+            ODESolver::Rodas4(_) => 7,
+            ODESolver::Radau5(_) | ODESolver::GaussLegendre => 3,
+        }
+    }
+
+    /// If the solver chooses its steps for a `Tolerance`, then `ODEParam.step` is only the first step.
+    pub fn is_adaptive (&self) -> bool {
+        !matches!(self, ODESolver::RK4 | ODESolver::Euler | ODESolver::GaussLegendre | ODESolver::BackwardEuler | ODESolver::Trapezoidal)
+    }
+
+    /// `Tolerance` of the adaptive solvers, `None` for the fixed step ones.
+    pub fn tolerance (&self) -> Option<Tolerance> {
+        match self {
+            ODESolver::Adams(tolerance) | ODESolver::Lsoda(tolerance) | ODESolver::Ros3p(tolerance) | ODESolver::Rodas4(tolerance) | ODESolver::Radau5(tolerance) => Some(*tolerance),
+            _ => None,
+        }
+    }
+
+    /// The same solver with the given `tolerance`. The fixed step solvers, which have no tolerance, are returned unchanged.
+    pub fn with_tolerance (self, tolerance : Tolerance) -> ODESolver {
+        match self {
+            ODESolver::Adams(_) => ODESolver::Adams(tolerance),
+            ODESolver::Lsoda(_) => ODESolver::Lsoda(tolerance),
+            ODESolver::Ros3p(_) => ODESolver::Ros3p(tolerance),
+            ODESolver::Rodas4(_) => ODESolver::Rodas4(tolerance),
+            ODESolver::Radau5(_) => ODESolver::Radau5(tolerance),
+            _ => self,
+        }
+    }
+}
+
+/// Parses the name of a solver (`RK4`, `Euler`, `Adams`, `LSODA`, `ROS3P`, `Rodas4`, `Radau5`, `GaussLegendre`, `BackwardEuler`, `Trapezoidal`), ignoring the case. The adaptive solvers have the default `Tolerance`, which can be changed with `ODESolver::with_tolerance`.
+impl std::str::FromStr for ODESolver {
+    type Err = Box<dyn Error>;
+
+    fn from_str (name : &str) -> Result<ODESolver, Box<dyn Error>> {
+        match name.to_lowercase().as_str() {
+            "rk4" => Ok(ODESolver::RK4),
+            "euler" => Ok(ODESolver::Euler),
+            "adams" => Ok(ODESolver::Adams(Tolerance::default())),
+            "lsoda" => Ok(ODESolver::Lsoda(Tolerance::default())),
+            "ros3p" => Ok(ODESolver::Ros3p(Tolerance::default())),
+            "rodas4" => Ok(ODESolver::Rodas4(Tolerance::default())),
+            "radau5" => Ok(ODESolver::Radau5(Tolerance::default())),
+            "gausslegendre" => Ok(ODESolver::GaussLegendre),
+            "backwardeuler" => Ok(ODESolver::BackwardEuler),
+            "trapezoidal" => Ok(ODESolver::Trapezoidal),
+            _ => Err(format!("Unknown solver '{}'", name).into()),
+        }
+    }
+}
+
+// Sorted copy of the times where the integrator must land exactly
+pub(crate) fn sorted_tstops (tstops : &[f64]) -> Vec<f64> {
+    let mut tstops = tstops.to_vec();
+    tstops.sort_by(|a,b| a.total_cmp(b));
+    tstops
+}
+
+// Next time where the integrator must land: the first of the sorted `tstops` after `time`, or `tend`
+pub(crate) fn next_tstop (tstops : &[f64], time : f64, tend : f64) -> f64 {
+    let i = tstops.partition_point(|t| *t <= time + 1.0e-10);
+    match tstops.get(i) {
+        Some(t) if *t < tend => *t,
+        _ => tend,
+    }
 }
 
 /// odesolver using traits and known size at compile time
@@ -37,8 +149,6 @@ pub enum ODESolver {
 ///use odesolver::solver_trait as ST;
 ///
 ///const ORDER:usize = 2;
-///const ORDER_T:usize = ORDER + 1;
-/// // it is always ORDER + 1, so that the exporting Data has an extra field for the time.
 ///
 ///fn main() {
 ///    let initial_state = [3.0,2.0];
@@ -53,10 +163,10 @@ pub enum ODESolver {
 ///    
 ///    let sist = FSist1 { state : initial_state };
 ///
-///    let file_trait = "./test_trait.txt".to_string();
-///    let(data,_,_) = ST::solve_ode::<ORDER,ORDER_T,_>(sist, odeparam, ST::ODESolver::RK4);
+///    let file_trait = std::env::temp_dir().join("odesolver_test_trait.txt").to_str().unwrap().to_string();
+///    let result = ST::solve_ode::<ORDER,_>(sist, odeparam, ST::ODESolver::RK4);
 ///    
-///    ST::data_to_file(&data, file_trait, None).unwrap();
+///    ST::data_to_file(&result.data(), file_trait, None).unwrap();
 ///}
 ///
 ///
@@ -70,8 +180,12 @@ pub enum ODESolver {
 ///        return &self.state;
 ///    }
 ///    
-///    fn dstate (&self, _time : f64) -> ST::DState<ORDER>{
-///        let state = self.state;
+///    fn dstate (&self, time : f64) -> ST::DState<ORDER>{
+///        self.dstate_at(time, &self.state)
+///    }
+///
+///    // Used by the solvers at the intermediate stages, without cloning the system
+///    fn dstate_at (&self, _time : f64, state : &ST::State<ORDER>) -> ST::DState<ORDER>{
 ///        let mut dstate  = [0.0; ORDER];
 ///        
 ///        dstate[0] = -0.5*state[0];
@@ -110,10 +224,10 @@ pub mod solver_trait;
 ///                          ,ratio_step_output
 ///                          };
 ///
-///    let file_vec = "./test_vec.txt".to_string();
-///    let (data,_) = SV::solve_ode(system_function, odeparam, initial_state, SV::ODESolver::RK4); 
+///    let file_vec = std::env::temp_dir().join("odesolver_test_vec.txt").to_str().unwrap().to_string();
+///    let result = SV::solve_ode(system_function, odeparam, initial_state, SV::ODESolver::RK4); 
 ///    
-///    SV::data_to_file(&data, file_vec, None).unwrap();
+///    SV::data_to_file(&result.data(), file_vec, None).unwrap();
 ///}
 ///
 ///fn system_function (_time: f64, state: &SV::State) -> SV::DState {
@@ -124,6 +238,363 @@ pub mod solver_trait;
 ///    dstate
 ///}
 ///```
+///
+///# Example with an in place system function
+///
+///The system function can write the differential in a given buffer instead of returning a new vector. The stage buffers of the solvers are reused and the output is stored in one contiguous `FlatData`, so the integration does not allocate memory at each step.
+///
+///```
+///use odesolver::solver_vector as SV;
+///
+///fn main() {
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 400.0
+///                          ,step : 0.0001
+///                          ,ratio_step_output : 100
+///                          };
+///
+///    let system_function = |_time: f64, state: &[f64], dstate: &mut [f64]| {
+///        dstate[0] = -0.5*state[0];
+///        dstate[1] = -0.00001*state[1];
+///    };
+///    let result = SV::solve_ode_in_place(system_function, odeparam.clone(), vec!(3.0,2.0), SV::ODESolver::RK4);
+///
+///    assert_eq!(result.data.stride, 3);
+///    assert!((result.data.time(1) - 0.01).abs() < 1.0e-12);
+///    assert_eq!(result.data.row(1), &[result.data.time(1), result.component(0)[1], result.component(1)[1]]);
+///    assert_eq!(result.data.state(result.len() - 1), &result.system[..]);
+///
+///    // same results as the closures which return the differential
+///    let allocating = SV::solve_ode(|_time: f64, state: &SV::State| vec!(-0.5*state[0], -0.00001*state[1]), odeparam, vec!(3.0,2.0), SV::ODESolver::RK4);
+///    assert_eq!(result.data.to_data(), allocating.data());
+///
+///    let file_flat = std::env::temp_dir().join("odesolver_test_flat.txt").to_str().unwrap().to_string();
+///    SV::data_to_file(&result.data.to_data(), file_flat, None).unwrap();
+///}
+///```
+///
+///# Example with tstops
+///
+///`solve_ode_tstops` lands exactly at given times, e.g. where the system function is not smooth, without changing the step elsewhere: the output times stay at `time + k*step*ratio_step_output`.
+///
+///```
+///use odesolver::solver_vector as SV;
+///
+///fn main() {
+///    // the differential has a kink at t = 0.25, x(1) = 0.25^2/2 + 0.75^2/2
+///    let kink = |time: f64, _state: &SV::State| -> SV::DState { vec!((time - 0.25).abs()) };
+///    let exact = 0.3125;
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 1.0
+///                          ,step : 0.1
+///                          ,ratio_step_output : 2
+///                          };
+///    let on_grid = |times: &[f64]| times.iter().enumerate().all(|(k, time)| (time - 0.2*k as f64).abs() < 1.0e-12);
+///
+///    // RK4 is exact for the polynomial pieces, but only if it lands on the kink
+///    let plain = SV::solve_ode(kink, odeparam.clone(), vec!(0.0), SV::ODESolver::RK4);
+///    let stopped = SV::solve_ode_tstops(kink, odeparam.clone(), vec!(0.0), SV::ODESolver::RK4, &[0.25], false);
+///    assert!((plain.system[0] - exact).abs() > 1.0e-4);
+///    assert!((stopped.system[0] - exact).abs() < 1.0e-14);
+///    assert_eq!(stopped.times.len(), 6);
+///    assert!(on_grid(&stopped.times));
+///    assert_eq!(stopped.stats.accepted_steps, 11);
+///
+///    // with the point at the tstop in the output, the following output times are not shifted
+///    let output = SV::solve_ode_tstops(kink, odeparam.clone(), vec!(0.0), SV::ODESolver::RK4, &[0.25], true);
+///    assert_eq!(output.times.len(), 7);
+///    assert_eq!(output.times[2], 0.25);
+///    assert!((output.states[2][0] - 0.25*0.25/2.0).abs() < 1.0e-15);
+///    let mut times = output.times.clone();
+///    times.remove(2);
+///    assert!(on_grid(&times));
+///    assert_eq!(output.states.last(), stopped.states.last());
+///
+///    // the adaptive solvers land on the tstop and start again from it
+///    let tolerance = SV::Tolerance { rtol : 1.0e-10, atol : 1.0e-12 };
+///    for odesolver in [SV::ODESolver::Adams(tolerance), SV::ODESolver::Rodas4(tolerance)] {
+///        for output_tstops in [false, true] {
+///            let result = SV::solve_ode_tstops(kink, odeparam.clone(), vec!(0.0), odesolver, &[0.25], output_tstops);
+///            assert!((result.system[0] - exact).abs() < 1.0e-9);
+///            assert_eq!(result.times.len(), if output_tstops { 7 } else { 6 });
+///            assert_eq!(result.times.contains(&0.25), output_tstops);
+///            let times : Vec<f64> = result.times.iter().copied().filter(|time| *time != 0.25).collect();
+///            assert!(on_grid(&times));
+///        }
+///    }
+///}
+///```
+///
+///# Example with other types of state
+///
+///With `solve_ode_generic` the state and its differential can be of any type which implements `VectorSpace`: arrays, `nalgebra::SVector`/`DVector` (feature `nalgebra`) or `ndarray::Array1` (feature `ndarray`), so the system function can use their operations directly.
+///
+///```
+///use odesolver::solver_vector as SV;
+///
+///fn main() {
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 1.0
+///                          ,step : 0.001
+///                          ,ratio_step_output : 100
+///                          };
+///
+///    let rotation = |_time: f64, state: &[f64;2]| [-2.0*state[1], 2.0*state[0]];
+///    let result = SV::solve_ode_generic(rotation, odeparam.clone(), [1.0, 0.0], SV::ODESolver::RK4);
+///    assert!((result.system[0] - 2.0_f64.cos()).abs() < 1.0e-12);
+///    assert_eq!(result.states[10], result.system.to_vec());
+///
+///    #[cfg(feature = "nalgebra")]
+///    {
+///        use nalgebra::{matrix, vector, DVector};
+///
+///        let matrix = matrix![0.0, -2.0; 2.0, 0.0];
+///        let result = SV::solve_ode_generic(|_time: f64, state: &nalgebra::Vector2<f64>| matrix*state, odeparam.clone(), vector![1.0, 0.0], SV::ODESolver::RK4);
+///        assert!((result.system[0] - 2.0_f64.cos()).abs() < 1.0e-12);
+///
+///        let result = SV::solve_ode_generic(|_time: f64, state: &DVector<f64>| -state, odeparam.clone(), DVector::from_element(3, 1.0), SV::ODESolver::RK4);
+///        assert!((result.system[2] - (-1.0_f64).exp()).abs() < 1.0e-12);
+///    }
+///
+///    #[cfg(feature = "ndarray")]
+///    {
+///        use ndarray::{arr1, arr2, Array1};
+///
+///        let matrix = arr2(&[[0.0, -2.0], [2.0, 0.0]]);
+///        let result = SV::solve_ode_generic(|_time: f64, state: &Array1<f64>| matrix.dot(state), odeparam, arr1(&[1.0, 0.0]), SV::ODESolver::RK4);
+///        assert!((result.system[0] - 2.0_f64.cos()).abs() < 1.0e-12);
+///        assert_eq!(result.component(1).len(), 11);
+///    }
+///}
+///```
+///
+///# Example with other scalars
+///
+///The components of the state are `f64` by default, but `solve_ode_generic` accepts any `Scalar`: `f32`, `Complex<f64>` (feature `complex`) or a type of your own.
+///
+///```
+///use odesolver::solver_vector as SV;
+///
+///fn main() {
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 1.0
+///                          ,step : 0.01
+///                          ,ratio_step_output : 10
+///                          };
+///
+///    let decay = |_time: f64, state: &SV::State<f32>| -> SV::DState<f32> { vec!(-state[0]) };
+///    let result = SV::solve_ode_generic(decay, odeparam.clone(), vec!(1.0_f32), SV::ODESolver::RK4);
+///    assert!((result.system[0] - (-1.0_f32).exp()).abs() < 1.0e-6);
+///    assert_eq!(result.states[10], result.system);
+///
+///    #[cfg(feature = "complex")]
+///    {
+///        use num_complex::Complex;
+///
+///        // Schrodinger equation of a two level system, i d(psi)/dt = H psi with H = [[0, 1], [1, 0]]
+///        let i = Complex::new(0.0, 1.0);
+///        let schrodinger = |_time: f64, psi: &[Complex<f64>;2]| [-i*psi[1], -i*psi[0]];
+///        let result = SV::solve_ode_generic(schrodinger, odeparam, [Complex::new(1.0, 0.0), Complex::new(0.0, 0.0)], SV::ODESolver::RK4);
+///        let [up, down] = result.system;
+///        assert!((up.norm_sqr() - 1.0_f64.cos().powi(2)).abs() < 1.0e-9);
+///        assert!((up.norm_sqr() + down.norm_sqr() - 1.0).abs() < 1.0e-9);
+///    }
+///}
+///```
+///
+///# Example with an adaptive multistep solver
+///
+///`ODESolver::Adams` chooses its steps and order for a `Tolerance`, evaluating the system twice per step, so for smooth problems with expensive differentials it needs far fewer evaluations than RK4. `odeparam.step` only bounds the first step, the output is still given every `step*ratio_step_output`. The RK4 steps of the start are kept within the tolerance too, so a stiff start only makes the steps small.
+///
+///```
+///use odesolver::solver_vector as SV;
+///
+///// Kepler orbit of eccentricity 0.5
+///fn kepler (_time: f64, state: &SV::State) -> SV::DState {
+///    let r3 = (state[0]*state[0] + state[1]*state[1]).powf(1.5);
+///    vec!(state[2], state[3], -state[0]/r3, -state[1]/r3)
+///}
+///
+///fn main() {
+///    let initial_state = vec!(0.5, 0.0, 0.0, 3.0_f64.sqrt());
+///    let period = 2.0*std::f64::consts::PI;
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 10.0*period
+///                          ,step : 0.001
+///                          ,ratio_step_output : 1000
+///                          };
+///
+///    let tolerance = SV::Tolerance { rtol : 1.0e-10, atol : 1.0e-12 };
+///    let adams = SV::solve_ode(kepler, odeparam.clone(), initial_state.clone(), SV::ODESolver::Adams(tolerance));
+///    let rk4 = SV::solve_ode(kepler, odeparam, initial_state.clone(), SV::ODESolver::RK4);
+///
+///    // back to the initial position after 10 periods
+///    assert!((adams.system[0] - 0.5).abs() < 1.0e-7 && adams.system[1].abs() < 1.0e-7);
+///    assert!(adams.stats.fevals*20 < rk4.stats.fevals);
+///    assert_eq!(adams.times, rk4.times);
+///
+///    // Van der Pol oscillator, stiff from the start, with a first step far above the stability limit
+///    let vdp = |_time: f64, y: &SV::State| -> SV::DState { vec!(y[1], 1000.0*(1.0 - y[0]*y[0])*y[1] - y[0]) };
+///    let odeparam = SV::ODEParam { time : 0.0, tend : 1.0, step : 0.01, ratio_step_output : 100 };
+///    let tolerance = SV::Tolerance { rtol : 1.0e-6, atol : 1.0e-6 };
+///    let adams = SV::solve_ode(vdp, odeparam.clone(), vec!(2.0, 0.0), SV::ODESolver::Adams(tolerance));
+///    let radau = SV::solve_ode(vdp, odeparam, vec!(2.0, 0.0), SV::ODESolver::Radau5(tolerance));
+///    assert_eq!(adams.stats.termination, SV::Termination::ReachedEnd);
+///    assert!((adams.system[0] - radau.system[0]).abs() < 1.0e-6);
+///}
+///```
+///
+///# Example with a problem which becomes stiff
+///
+///`ODESolver::Lsoda` starts with the Adams methods and switches to the BDF, which are stable for stiff problems, when the stability of Adams limits the steps, and back when the problem is no longer stiff.
+///
+///```
+///use odesolver::solver_vector as SV;
+///
+///fn main() {
+///    // propagation of a flame, stiff after the ignition at t = 1/delta
+///    let delta = 1.0e-4;
+///    let flame = |_time: f64, state: &SV::State| -> SV::DState { vec!(state[0]*state[0] - state[0]*state[0]*state[0]) };
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 2.0/delta
+///                          ,step : 0.1
+///                          ,ratio_step_output : 1000
+///                          };
+///
+///    let tolerance = SV::Tolerance::default();
+///    let lsoda = SV::solve_ode(flame, odeparam.clone(), vec!(delta), SV::ODESolver::Lsoda(tolerance));
+///    let adams = SV::solve_ode(flame, odeparam, vec!(delta), SV::ODESolver::Adams(tolerance));
+///
+///    assert!((lsoda.system[0] - 1.0).abs() < 1.0e-5);
+///    assert!(lsoda.stats.fevals*5 < adams.stats.fevals);
+///
+///    // Van der Pol oscillator, stiff from the first step
+///    let vdp = |_time: f64, y: &SV::State| -> SV::DState { vec!(y[1], 1000.0*(1.0 - y[0]*y[0])*y[1] - y[0]) };
+///    let odeparam = SV::ODEParam { time : 0.0, tend : 1.0, step : 0.01, ratio_step_output : 100 };
+///    let tolerance = SV::Tolerance { rtol : 1.0e-6, atol : 1.0e-6 };
+///    let lsoda = SV::solve_ode(vdp, odeparam.clone(), vec!(2.0, 0.0), SV::ODESolver::Lsoda(tolerance));
+///    let adams = SV::solve_ode(vdp, odeparam.clone(), vec!(2.0, 0.0), SV::ODESolver::Adams(tolerance));
+///    let radau = SV::solve_ode(vdp, odeparam, vec!(2.0, 0.0), SV::ODESolver::Radau5(tolerance));
+///    assert_eq!(lsoda.stats.termination, SV::Termination::ReachedEnd);
+///    assert!((lsoda.system[0] - radau.system[0]).abs() < 1.0e-6);
+///    assert!(lsoda.stats.fevals*10 < adams.stats.fevals);
+///}
+///```
+///
+///# Example with a Rosenbrock method and the exact Jacobian
+///
+///`ODESolver::Ros3p` and `ODESolver::Rodas4` are stable for stiff problems with one Jacobian and linear solves per step, without Newton iterations. With `solve_ode_with_jacobian` they use the given Jacobian instead of finite differences.
+///
+///```
+///use odesolver::solver_vector as SV;
+///
+///fn main() {
+///    // Robertson chemical kinetics
+///    let robertson = |_time: f64, y: &SV::State| -> SV::DState {
+///        vec!(-0.04*y[0] + 1.0e4*y[1]*y[2], 0.04*y[0] - 1.0e4*y[1]*y[2] - 3.0e7*y[1]*y[1], 3.0e7*y[1]*y[1])
+///    };
+///    let jacobian = |_time: f64, y: &SV::State| -> Vec<Vec<f64>> {
+///        vec!(vec!(-0.04, 1.0e4*y[2], 1.0e4*y[1]),
+///             vec!(0.04, -1.0e4*y[2] - 6.0e7*y[1], -1.0e4*y[1]),
+///             vec!(0.0, 6.0e7*y[1], 0.0))
+///    };
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 40.0
+///                          ,step : 1.0e-4
+///                          ,ratio_step_output : 100000
+///                          };
+///
+///    let tolerance = SV::Tolerance { rtol : 1.0e-6, atol : 1.0e-10 };
+///    let rodas = SV::solve_ode_with_jacobian(robertson, jacobian, odeparam.clone(), vec!(1.0, 0.0, 0.0), SV::ODESolver::Rodas4(tolerance));
+///    let ros3p = SV::solve_ode(robertson, odeparam.clone(), vec!(1.0, 0.0, 0.0), SV::ODESolver::Ros3p(tolerance));
+///
+///    // reference values at t = 40
+///    assert!((rodas.system[0] - 0.7158270687).abs() < 1.0e-6);
+///    assert!((ros3p.system[0] - 0.7158270687).abs() < 1.0e-5);
+///    assert!(rodas.stats.accepted_steps < 200);
+///
+///    // the same landing at a tstop, with the output given to a sink
+///    let mut data : SV::Data = Vec::new();
+///    let (state, _) = SV::solve_ode_with_jacobian_tstops_to_sink(robertson, jacobian, odeparam, vec!(1.0, 0.0, 0.0), SV::ODESolver::Rodas4(tolerance), &[0.5], true, &mut data).unwrap();
+///    assert!((state[0] - rodas.system[0]).abs() < 1.0e-6);
+///    assert_eq!(data[1][0], 0.5);
+///
+///    // x' = -4 sqrt(x) is not defined for x < 0: the first step overshoots, its error is NaN and it is retried with a smaller one
+///    let sqrt_decay = |_time: f64, x: &SV::State| -> SV::DState { vec!(-4.0*x[0].sqrt()) };
+///    let odeparam = SV::ODEParam { time : 0.0, tend : 0.45, step : 0.45, ratio_step_output : 1 };
+///    for odesolver in [SV::ODESolver::Rodas4(tolerance), SV::ODESolver::Ros3p(tolerance)] {
+///        let result = SV::solve_ode(sqrt_decay, odeparam.clone(), vec!(1.0), odesolver);
+///        assert_eq!(result.stats.termination, SV::Termination::ReachedEnd);
+///        assert!(result.stats.rejected_steps > 0);
+///        assert!((result.system[0] - 0.01).abs() < 1.0e-6);
+///    }
+///}
+///```
+///
+///# Example with implicit Runge–Kutta methods
+///
+///`ODESolver::Radau5` is L-stable and of order 5, for very stiff problems at tight tolerances. `ODESolver::GaussLegendre` has a fixed step and is symplectic, so the energy of a conservative system stays bounded over long integrations, while it drifts with `RK4`.
+///
+///```
+///use odesolver::solver_vector as SV;
+///
+///fn main() {
+///    let robertson = |_time: f64, y: &SV::State| -> SV::DState {
+///        vec!(-0.04*y[0] + 1.0e4*y[1]*y[2], 0.04*y[0] - 1.0e4*y[1]*y[2] - 3.0e7*y[1]*y[1], 3.0e7*y[1]*y[1])
+///    };
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 40.0
+///                          ,step : 1.0e-4
+///                          ,ratio_step_output : 100000
+///                          };
+///    let tolerance = SV::Tolerance { rtol : 1.0e-8, atol : 1.0e-12 };
+///    let radau = SV::solve_ode(robertson, odeparam, vec!(1.0, 0.0, 0.0), SV::ODESolver::Radau5(tolerance));
+///    assert!((radau.system[0] - 0.7158270687).abs() < 1.0e-8);
+///
+///    // pendulum with large oscillations
+///    let pendulum = |_time: f64, state: &SV::State| -> SV::DState { vec!(state[1], -state[0].sin()) };
+///    let energy = |state: &[f64]| 0.5*state[1]*state[1] - state[0].cos();
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 2000.0
+///                          ,step : 0.25
+///                          ,ratio_step_output : 40
+///                          };
+///    let gauss = SV::solve_ode(pendulum, odeparam.clone(), vec!(2.0, 0.0), SV::ODESolver::GaussLegendre);
+///    let rk4 = SV::solve_ode(pendulum, odeparam, vec!(2.0, 0.0), SV::ODESolver::RK4);
+///
+///    let drift = |states: &[SV::State]| states.iter().map(|state| (energy(state) - energy(&[2.0, 0.0])).abs()).fold(0.0, f64::max);
+///    assert!(drift(&gauss.states) < 1.0e-7);
+///    assert!(drift(&rk4.states) > 1.0e-3);
+///}
+///```
+///
+///# Example with fixed step implicit methods
+///
+///The heat equation `u_t = u_xx` on `[0, 1]`, discretized on a grid of spacing `dx`, is stable with `Euler` only for steps below `dx*dx/2`. `ODESolver::BackwardEuler` and `ODESolver::Trapezoidal` are stable with any step.
+///
+///```
+///use odesolver::solver_vector as SV;
+///
+///fn main() {
+///    let n = 49;
+///    let dx = 1.0/(n + 1) as f64;
+///    let heat = move |_time: f64, u: &SV::State| -> SV::DState {
+///        (0 .. n).map(|i| {
+///            let left = if i == 0 { 0.0 } else { u[i - 1] };
+///            let right = if i == n - 1 { 0.0 } else { u[i + 1] };
+///            (left - 2.0*u[i] + right)/(dx*dx)
+///        }).collect()
+///    };
+///    let initial : SV::State = (1 ..= n).map(|i| (std::f64::consts::PI*i as f64*dx).sin()).collect();
+///    // the exact solution decays as exp(-pi^2*t)
+///    let exact = |u0: f64| u0*(-std::f64::consts::PI.powi(2)*0.5).exp();
+///
+///    // 50 steps of 0.01, fifty times the stability limit of Euler
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 0.5
+///                          ,step : 0.01
+///                          ,ratio_step_output : 50
+///                          };
+///    let euler = SV::solve_ode(heat, odeparam.clone(), initial.clone(), SV::ODESolver::Euler);
+///    let backward = SV::solve_ode(heat, odeparam.clone(), initial.clone(), SV::ODESolver::BackwardEuler);
+///    let trapezoidal = SV::solve_ode(heat, odeparam, initial.clone(), SV::ODESolver::Trapezoidal);
+///
+///    let middle = n/2;
+///    assert!(!euler.system[middle].is_finite() || euler.system[middle].abs() > 1.0);
+///    assert!((backward.system[middle] - exact(initial[middle])).abs() < 3.0e-3);
+///    assert!((trapezoidal.system[middle] - exact(initial[middle])).abs() < 5.0e-5);
+///}
+///```
 pub mod solver_vector;
 
 
@@ -153,10 +624,10 @@ pub mod solver_vector;
 ///                          };
 ///
 ///    let sist = FSist2 { state : initial_state };
-///    let file_vec_trait = "./teste_vec_trait.txt".to_string();
-///    let(data,_,_) = SVT::solve_ode::<_>(sist, odeparam, SVT::ODESolver::RK4);
+///    let file_vec_trait = std::env::temp_dir().join("odesolver_teste_vec_trait.txt").to_str().unwrap().to_string();
+///    let result = SVT::solve_ode::<_>(sist, odeparam, SVT::ODESolver::RK4);
 ///    
-///    SVT::data_to_file(&data, file_vec_trait, None).unwrap();
+///    SVT::data_to_file(&result.data(), file_vec_trait, None).unwrap();
 ///}
 ///
 ///#[derive(Clone)]
@@ -185,3 +656,907 @@ pub mod solver_vector;
 ///}
 ///```
 pub mod solver_vector_trait;
+
+
+
+
+
+
+///Output of the solvers: times, states, final system and parameters and statistics of the integration.
+///
+///The states are kept without the time column, so a component of the state has the same index in `states[i]` as in the state of the system, and `component`/`time_series` give the time series of a single component. `data` builds the rows `[time, state...]` used to export the results.
+///
+///# Example
+///
+///```
+///use odesolver::solver_vector as SV;
+///
+///fn main() {
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 1.0
+///                          ,step : 0.01
+///                          ,ratio_step_output : 10
+///                          };
+///    let decay = |_time: f64, state: &SV::State| -> SV::DState { vec!(-state[0], -2.0*state[1]) };
+///    let result = SV::solve_ode(decay, odeparam, vec!(1.0, 1.0), SV::ODESolver::RK4);
+///
+///    assert_eq!(result.len(), 11);
+///    assert_eq!(result.times[10], result.odeparam.time);
+///    assert!((result.component(1)[10] - (-2.0_f64).exp()).abs() < 1.0e-8);
+///    assert_eq!(result.time_series(0)[0], (0.0, 1.0));
+///    assert_eq!(result.final_state(), Some(&result.system));
+///    assert_eq!(result.data()[10][2], result.states[10][1]);
+///
+///    assert_eq!(result.stats.accepted_steps, 100);
+///    assert_eq!(result.stats.fevals, 400);
+///    assert_eq!(result.stats.rejected_steps, 0);
+///    assert_eq!(result.stats.termination, SV::Termination::ReachedEnd);
+///
+///    // the integration stops when the state is not finite
+///    let blow_up = |_time: f64, state: &SV::State| -> SV::DState { vec!(state[0]*state[0]) };
+///    let result = SV::solve_ode(blow_up, SV::ODEParam { time : 0.0, tend : 2.0, step : 0.01, ratio_step_output : 1 }, vec!(1.0), SV::ODESolver::Euler);
+///    assert!(matches!(result.stats.termination, SV::Termination::Error(_)));
+///    assert!(result.odeparam.time < 2.0);
+///}
+///```
+///
+///With the `serde` feature, the results can be stored (here as JSON) together with the parameters of the simulation and read back:
+///
+///```
+///# #[cfg(feature = "serde")] {
+///use odesolver::solver_vector as SV;
+///
+///let odeparam = SV::ODEParam { time : 0.0, tend : 1.0, step : 0.1, ratio_step_output : 2 };
+///let decay = |_time: f64, state: &SV::State| -> SV::DState { vec!(-state[0]) };
+///let odesolver = SV::ODESolver::Rodas4(SV::Tolerance { rtol : 1.0e-8, ..SV::Tolerance::default() });
+///let result = SV::solve_ode(decay, odeparam.clone(), vec!(1.0), odesolver);
+///
+///let json = serde_json::to_string(&result).unwrap();
+///let back : SV::SolveResult<SV::State> = serde_json::from_str(&json).unwrap();
+///assert_eq!(back.times, result.times);
+///assert_eq!(back.states, result.states);
+///assert_eq!(back.system, result.system);
+///assert_eq!(back.odeparam, result.odeparam);
+///assert_eq!(back.stats, result.stats);
+///
+///let config = serde_json::to_string(&(&odeparam, odesolver)).unwrap();
+///let (param, solver) : (SV::ODEParam, SV::ODESolver) = serde_json::from_str(&config).unwrap();
+///assert_eq!((param, solver), (odeparam, odesolver));
+///
+///let error = SV::Termination::Error("state not finite".to_string());
+///assert_eq!(serde_json::from_str::<SV::Termination>(&serde_json::to_string(&error).unwrap()).unwrap(), error);
+///# }
+///```
+pub mod result;
+
+
+
+
+
+
+///Integration engine shared by the modules `solver_trait`, `solver_vector` and `solver_vector_trait`.
+///
+///The states implement `VectorSpace`, vectors of `Scalar` components which are `f64` by default (it is implemented for `[T;N]` and `Vec<T>`), and the systems implement `System`, which gives the differential at any state. The three modules adapt their systems to `System` and call `integrate`, so the solvers and the integration loop are written once. It can also be used directly, e.g. with a state type of your own.
+///
+///# Example
+///
+///```
+///use odesolver::engine::{self, System, VectorSpace};
+///use odesolver::result::SolveStats;
+///use odesolver::{ODEParam, ODESolver};
+///
+///// position of a point, with named accessors
+///#[derive(Clone)]
+///struct Point([f64;2]);
+///
+///impl Point {
+///    fn x (&self) -> f64 { self.0[0] }
+///    fn y (&self) -> f64 { self.0[1] }
+///}
+///
+///impl VectorSpace for Point {
+///    fn components (&self) -> &[f64] { &self.0 }
+///    fn components_mut (&mut self) -> &mut [f64] { &mut self.0 }
+///}
+///
+///// rotation around the origin with angular speed `omega`
+///struct Rotation {
+///    point : Point,
+///    omega : f64,
+///}
+///
+///impl System for Rotation {
+///    type State = Point;
+///
+///    fn state (&self) -> &Point { &self.point }
+///
+///    fn dstate_at (&mut self, _time : f64, point : &Point, dstate : &mut Point) {
+///        dstate.0 = [-self.omega*point.y(), self.omega*point.x()];
+///    }
+///
+///    fn update_state (&mut self, point : &Point) { self.point = point.clone(); }
+///}
+///
+///fn main() {
+///    let odeparam = ODEParam { time : 0.0, tend : 1.0, step : 0.001, ratio_step_output : 100 };
+///    let mut rotation = Rotation { point : Point([1.0, 0.0]), omega : 2.0 };
+///    let mut data : Vec<Vec<f64>> = Vec::new();
+///    let mut stats = SolveStats::default();
+///
+///    let odeparam = engine::integrate(&mut rotation, odeparam, ODESolver::RK4, &[], false, &mut data, &mut stats).unwrap();
+///
+///    assert_eq!(data.len(), 11);
+///    assert_eq!(odeparam.time, 1.0);
+///    assert_eq!(stats.accepted_steps, 1000);
+///    assert!((rotation.point.x() - 2.0_f64.cos()).abs() < 1.0e-12);
+///    assert!((rotation.point.y() - 2.0_f64.sin()).abs() < 1.0e-12);
+///}
+///```
+pub mod engine;
+
+
+
+
+
+
+///Continuation of equilibria of ODEs depending on a parameter.
+///
+///Traces the equilibria of `f(x, lambda) = 0` as the parameter `lambda` varies, by natural-parameter or pseudo-arclength continuation, detecting fold and Hopf bifurcations along the branch. The branch is returned as a `Data` (rows `[lambda, x1, x2, ...]`) that can be exported with `data_to_file`.
+///
+///# Example
+///
+///```
+///use odesolver::continuation as CT;
+///
+///fn main() {
+///    // x' = lambda + x^2 has a fold at lambda = 0
+///    let fold = |state: &CT::State, lambda: f64| -> CT::DState { vec!(lambda + state[0]*state[0]) };
+///    let param = CT::ContinuationParam { lambda : -1.0, lambda_end : 1.0
+///                          ,ds : 0.05
+///                          ,max_points : 200
+///                          ,tol : 1.0e-10
+///                          ,max_iter : 20
+///                          };
+///
+///    let (data, bifurcations) = CT::arclength_continuation(fold, vec!(-1.0), param.clone());
+///    assert_eq!(bifurcations[0].kind, CT::BifurcationKind::Fold);
+///    assert!(bifurcations[0].lambda.abs() < 1.0e-2);
+///
+///    // around the fold and back to lambda = -1 on the upper branch, where the continuation stops
+///    let last = data.last().unwrap();
+///    assert_eq!(last[0], -1.0);
+///    assert!((last[1] - 1.0).abs() < 1.0e-8);
+///    assert!(data.iter().all(|row| row[0] >= -1.0 && row[0] <= 1.0));
+///
+///    let file_continuation = std::env::temp_dir().join("odesolver_test_continuation.txt").to_str().unwrap().to_string();
+///    CT::data_to_file(&data, file_continuation, None).unwrap();
+///
+///    // Hopf normal form, the origin loses stability at lambda = 0
+///    let hopf = |state: &CT::State, lambda: f64| -> CT::DState {
+///        let (x, y) = (state[0], state[1]);
+///        let r2 = x*x + y*y;
+///        vec!(lambda*x - y - x*r2, x + lambda*y - y*r2)
+///    };
+///    let (_, bifurcations) = CT::natural_continuation(hopf, vec!(0.0,0.0), param.clone());
+///    assert_eq!(bifurcations[0].kind, CT::BifurcationKind::Hopf);
+///    assert!(bifurcations[0].lambda.abs() < 1.0e-2);
+///
+///    // two unstable real eigenvalues 1 +- sqrt(-lambda) which become a complex pair at lambda = 0, without crossing the imaginary axis
+///    let collision = |state: &CT::State, lambda: f64| -> CT::DState { vec!(state[0] + state[1], -lambda*state[0] + state[1]) };
+///    let (_, bifurcations) = CT::natural_continuation(collision, vec!(0.0,0.0), CT::ContinuationParam { lambda : -0.5, lambda_end : 0.5, ..param });
+///    assert!(bifurcations.is_empty());
+///}
+///```
+pub mod continuation;
+
+
+
+
+
+
+///Parallel ensemble simulations over initial conditions and parameters.
+///
+///Many integrations of perturbed copies of a system are run across threads, returning all the trajectories or their statistics (mean and quantiles) at each output time. Each member only depends on its index, so the results do not depend on the number of threads.
+///
+///# Example
+///
+///```
+///use odesolver::ensemble as EN;
+///
+///#[derive(Clone)]
+///struct Decay {
+///    state : EN::State,
+///    rate : f64,
+///}
+///
+///impl EN::ODESystem for Decay {
+///    fn state (&self) -> &EN::State{
+///        &self.state
+///    }
+///    
+///    fn dstate (&self, _time : f64) -> EN::DState{
+///        vec!(-self.rate*self.state[0])
+///    }
+///
+///    fn update_state(&mut self, state : EN::State) {
+///        self.state = state;
+///    }
+///}
+///
+///fn main() {
+///    let odeparam = EN::ODEParam {time : 0.0, tend : 1.0
+///                          ,step : 0.001
+///                          ,ratio_step_output : 100
+///                          };
+///    let sist = Decay { state : vec!(1.0), rate : 1.0 };
+///    let generator = |member: usize, sist: &mut Decay| sist.rate = 1.0 + 0.1*(member as f64);
+///
+///    let trajectories = EN::solve_ensemble(sist.clone(), generator, 20, odeparam.clone(), EN::ODESolver::RK4, 4);
+///    let single_thread = EN::solve_ensemble(sist, generator, 20, odeparam, EN::ODESolver::RK4, 1);
+///    assert_eq!(trajectories, single_thread);
+///
+///    let statistics = EN::ensemble_statistics(&trajectories, &[0.05, 0.5, 0.95]);
+///    let (_, median) = &statistics.quantiles[1];
+///    assert!(median.last().unwrap()[1] < statistics.mean[0][1]);
+///    assert!(statistics.incomplete.is_empty());
+///
+///    // a member which stopped early is reported and left out, the others keep all their output times
+///    let mut stopped = trajectories.clone();
+///    stopped[3].truncate(4);
+///    // a failed integration ends with its state which is not finite
+///    stopped[5][10][1] = f64::NAN;
+///    let statistics = EN::ensemble_statistics(&stopped, &[0.5]);
+///    assert_eq!(statistics.incomplete, vec!(3, 5));
+///    assert_eq!(statistics.mean.len(), 11);
+///    let mean = (0..20).filter(|&member| member != 3 && member != 5).map(|member| trajectories[member][10][1]).sum::<f64>()/18.0;
+///    assert!((statistics.mean[10][1] - mean).abs() < 1.0e-12);
+///}
+///```
+pub mod ensemble;
+
+
+
+
+
+
+///Monte Carlo propagation of uncertainties.
+///
+///Uncertain parameters and initial conditions are sampled from the given distributions (uniform, normal or log-normal) with a seeded random number generator, each sample is integrated and the time resolved mean, variance, percentiles and histograms of the chosen state components are computed. The statistics can be saved with labelled columns with `statistics_to_file`.
+///
+///# Example
+///
+///```
+///use odesolver::monte_carlo as MC;
+///
+///fn main() {
+///    let odeparam = MC::ODEParam {time : 0.0, tend : 1.0
+///                          ,step : 0.001
+///                          ,ratio_step_output : 100
+///                          };
+///    // uncertain decay rate and initial condition
+///    let distributions = [MC::Distribution::Uniform { low : 0.5, high : 1.5 },
+///                         MC::Distribution::Normal { mean : 1.0, std_dev : 0.1 }];
+///    let build = |sample: &[f64]| {
+///        let rate = sample[0];
+///        (move |_time: f64, state: &MC::State| -> MC::DState { vec!(-rate*state[0]) }, vec!(sample[1]))
+///    };
+///    let param = MC::MonteCarloParam { samples : 200, seed : 42
+///                          ,components : vec!(0)
+///                          ,percentiles : vec!(0.05, 0.5, 0.95)
+///                          ,histogram_bins : 10
+///                          ,threads : 0
+///                          };
+///
+///    let statistics = MC::monte_carlo_vector(&distributions, build, odeparam.clone(), MC::ODESolver::RK4, param);
+///    assert!((statistics.mean[0][1] - 1.0).abs() < 0.05);
+///
+///    let (summary, labels) = statistics.summary(None);
+///    assert_eq!(labels, ["t", "mean(x0)", "var(x0)", "p5(x0)", "p50(x0)", "p95(x0)"]);
+///    assert_eq!(summary[0].len(), labels.len());
+///    // the components without a name keep the default one
+///    assert_eq!(statistics.summary(Some(&[])).1, labels);
+///
+///    let file_monte_carlo = std::env::temp_dir().join("odesolver_test_monte_carlo.txt").to_str().unwrap().to_string();
+///    MC::statistics_to_file(&statistics, file_monte_carlo, Some(&["x"])).unwrap();
+///
+///    // x' = -rate*sqrt(x) reaches 0 at t = 2/rate and then leaves the domain of sqrt: the samples with rate > 2 fail before tend,
+///    // they are reported and left out instead of shortening the statistics of all the others
+///    let build = |sample: &[f64]| {
+///        let rate = sample[0];
+///        (move |_time: f64, state: &MC::State| -> MC::DState { vec!(-rate*state[0].sqrt()) }, vec!(1.0))
+///    };
+///    let param = MC::MonteCarloParam { samples : 50, seed : 7
+///                          ,components : vec!(0)
+///                          ,percentiles : vec!(0.5)
+///                          ,histogram_bins : 0
+///                          ,threads : 0
+///                          };
+///    let statistics = MC::monte_carlo_vector(&[MC::Distribution::Uniform { low : 1.0, high : 3.0 }], build, odeparam, MC::ODESolver::RK4, param);
+///    assert!(!statistics.failed.is_empty());
+///    assert!(statistics.failed.iter().all(|(_, termination)| matches!(termination, MC::Termination::Error(_))));
+///    for (sample, values) in statistics.samples.iter().enumerate() {
+///        // close to rate 2 the last steps can still end just above 0
+///        if values[0] < 2.0 || values[0] > 2.5 {
+///            assert_eq!(statistics.failed.iter().any(|(failed, _)| *failed == sample), values[0] > 2.5, "sample {} with rate {}", sample, values[0]);
+///        }
+///    }
+///    assert_eq!(statistics.mean.len(), 11);
+///    assert!(statistics.mean.iter().all(|datum| datum[1].is_finite()));
+///}
+///```
+pub mod monte_carlo;
+
+
+
+
+
+
+///Hybrid systems with continuous flow and discrete jumps of the state.
+///
+///Data types that implement `HybridSystem` (besides `ODESystem`) define guard functions and reset maps. The solver locates the time where a guard is triggered, applies the reset map and restarts the integration, recording both the pre-jump and the post-jump points in `Data`.
+///
+///# Example
+///
+///```
+///use odesolver::hybrid as HY;
+///
+///#[derive(Clone)]
+///struct BouncingBall {
+///    state : HY::State,
+///    restitution : f64,
+///}
+///
+///impl HY::ODESystem for BouncingBall {
+///    fn state (&self) -> &HY::State{
+///        &self.state
+///    }
+///    
+///    fn dstate (&self, _time : f64) -> HY::DState{
+///        vec!(self.state[1], -9.81)
+///    }
+///
+///    fn update_state(&mut self, state : HY::State) {
+///        self.state = state;
+///    }
+///}
+///
+///impl HY::HybridSystem for BouncingBall {
+///    fn guards (&self, _time : f64) -> Vec<f64> {
+///        vec!(self.state[0])
+///    }
+///
+///    fn reset (&mut self, _guard : usize, _time : f64) {
+///        self.state[1] = -self.restitution*self.state[1];
+///    }
+///}
+///
+///fn main() {
+///    let odeparam = HY::ODEParam {time : 0.0, tend : 2.0
+///                          ,step : 0.001
+///                          ,ratio_step_output : 10
+///                          };
+///    let ball = BouncingBall { state : vec!(1.0, 0.0), restitution : 0.8 };
+///
+///    let (result, events) = HY::solve_hybrid(ball, odeparam.clone(), HY::ODESolver::RK4, 1.0e-10);
+///    assert!((events[0].time - (2.0/9.81_f64).sqrt()).abs() < 1.0e-8);
+///    assert_eq!(result.stats.termination, HY::Termination::ReachedEnd);
+///    // the evaluations of the steps over the events and of the bisections are counted
+///    let stats = &result.stats;
+///    assert_eq!(stats.rejected_steps, events.len() as u64);
+///    assert!(stats.fevals > 4*(stats.accepted_steps + stats.rejected_steps));
+///
+///    // the adaptive solvers keep their history between the events
+///    let ball = BouncingBall { state : vec!(1.0, 0.0), restitution : 0.8 };
+///    let (adams, adams_events) = HY::solve_hybrid(ball, odeparam, HY::ODESolver::Adams(HY::Tolerance::default()), 1.0e-10);
+///    assert!((adams_events[0].time - events[0].time).abs() < 1.0e-8);
+///    assert!(adams.stats.fevals < 3*adams.stats.accepted_steps);
+///
+///    // pre-jump and post-jump points at the event time
+///    let jump = result.times.iter().position(|time| *time == events[0].time).unwrap();
+///    assert_eq!(result.times[jump+1], events[0].time);
+///    assert!(result.states[jump][1] < 0.0 && result.states[jump+1][1] > 0.0);
+///    // the other output times stay on the grid of the steps after the jumps
+///    assert!(result.times.iter().filter(|time| !events.iter().any(|event| event.time == **time))
+///        .all(|time| (100.0*time - (100.0*time).round()).abs() < 1.0e-8));
+///    assert_eq!(result.times.len(), 201 + 2*events.len());
+///
+///    let file_hybrid = std::env::temp_dir().join("odesolver_test_hybrid.txt").to_str().unwrap().to_string();
+///    HY::data_to_file(&result.data(), file_hybrid, None).unwrap();
+///}
+///```
+pub mod hybrid;
+
+
+
+
+
+
+///CSV export of the data with named columns and configurable formatting.
+///
+///The delimiter, precision and float format are configurable, the names of the columns are validated against the width of the rows and the fields are quoted following RFC 4180, so the files can be read directly by pandas, Excel, etc. It works with the `Data` of all the modules.
+///
+///# Example
+///
+///```
+///use odesolver::solver_vector as SV;
+///use odesolver::csv::{self, CsvFormat, FloatFormat};
+///
+///fn main() {
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 1.0
+///                          ,step : 0.001
+///                          ,ratio_step_output : 100
+///                          };
+///    let oscillator = |_time: f64, state: &SV::State| -> SV::DState { vec!(state[1], -state[0]) };
+///    let data = SV::solve_ode(oscillator, odeparam, vec!(1.0, 0.0), SV::ODESolver::RK4).data();
+///
+///    let format = CsvFormat { delimiter : ';'
+///                          ,precision : 6
+///                          ,float_format : FloatFormat::Fixed
+///                          ,names : Some(vec!("t".to_string(), "x".to_string(), "v".to_string()))
+///                          };
+///    let file_csv = std::env::temp_dir().join("odesolver_test_csv.csv").to_str().unwrap().to_string();
+///    csv::data_to_csv(&data, file_csv.clone(), &format).unwrap();
+///
+///    let mut buffer = Vec::new();
+///    csv::write_csv(&mut buffer, &data[..1], &format).unwrap();
+///    assert_eq!(String::from_utf8(buffer).unwrap(), "t;x;v\r\n0.000000;1.000000;0.000000\r\n");
+///
+///    // names that do not match the width of the rows are rejected
+///    let format = CsvFormat { names : Some(vec!("t".to_string(), "x".to_string())), ..CsvFormat::default() };
+///    assert!(csv::data_to_csv(&data, file_csv.clone(), &format).is_err());
+///
+///    // and so are delimiters which could be read as part of the values or of the quoting
+///    for delimiter in ['"', '\n', '.', 'e', '-', '+', '0'] {
+///        let format = CsvFormat { delimiter, ..CsvFormat::default() };
+///        assert!(csv::write_csv(&mut Vec::new(), &data, &format).is_err());
+///    }
+///    assert!(csv::data_to_csv(&data, file_csv, &CsvFormat { delimiter : '\t', ..CsvFormat::default() }).is_ok());
+///}
+///```
+pub mod csv;
+
+
+
+
+
+
+///NumPy `.npy` and `.npz` binary export and import of the data.
+///
+///Written in pure rust, it works with `solver_vector::Data` and `solver_trait::Data<M>`, so the results can be loaded in python with `np.load` without parsing text, and binary files are much smaller and faster to write than the text files of `data_to_file`.
+///
+///# Example
+///
+///```
+///use odesolver::solver_trait as ST;
+///use odesolver::npy;
+///
+///#[derive(Clone)]
+///struct Oscillator {
+///    state : ST::State<2>,
+///}
+///
+///impl ST::ODESystem<2> for Oscillator {
+///    fn state (&self) -> &ST::State<2>{
+///        &self.state
+///    }
+///    
+///    fn dstate (&self, _time : f64) -> ST::DState<2>{
+///        [self.state[1], -self.state[0]]
+///    }
+///
+///    fn update_state(&mut self, state : ST::State<2>) {
+///        self.state = state;
+///    }
+///}
+///
+///fn main() {
+///    let odeparam = ST::ODEParam {time : 0.0, tend : 1.0
+///                          ,step : 0.001
+///                          ,ratio_step_output : 100
+///                          };
+///    let data = ST::solve_ode(Oscillator { state : [1.0, 0.0] }, odeparam, ST::ODESolver::RK4).data();
+///
+///    let file_npy = std::env::temp_dir().join("odesolver_test_npy.npy").to_str().unwrap().to_string();
+///    npy::data_to_npy(&data, file_npy.clone()).unwrap();
+///    assert_eq!(npy::npy_to_data(file_npy.clone()).unwrap(), data);
+///    // or as rows of an array of fixed size
+///    let read : ST::Data<3> = npy::npy_to_data_array(file_npy).unwrap();
+///    assert_eq!(read[10].to_vec(), data[10]);
+///
+///    let file_npz = std::env::temp_dir().join("odesolver_test_npz.npz").to_str().unwrap().to_string();
+///    npy::data_to_npz(&[("oscillator", &data[..]), ("start", &data[..1])], file_npz.clone()).unwrap();
+///    let arrays = npy::npz_to_data(file_npz.clone()).unwrap();
+///    assert_eq!(arrays[0].0, "oscillator");
+///    assert_eq!(arrays[1].1, data[..1]);
+///
+///    // truncated or corrupted files give an error, also when the header claims a huge array
+///    let mut bytes = std::fs::read(&file_npz).unwrap();
+///    let len = bytes.len();
+///    bytes[len-6 .. len-2].copy_from_slice(&u32::MAX.to_le_bytes());
+///    std::fs::write(&file_npz, &bytes).unwrap();
+///    assert!(npy::npz_to_data(file_npz).is_err());
+///
+///    let mut bytes = Vec::new();
+///    npy::write_npy(&mut bytes, &data[..1]).unwrap();
+///    let shape = bytes.windows(6).position(|window| window == b"(1, 3)").unwrap();
+///    bytes[shape .. shape+18].copy_from_slice(b"(4294967295, 3), }");
+///    assert!(npy::read_npy(&mut &bytes[..]).unwrap_err().to_string().contains("Truncated"));
+///}
+///```
+pub mod npy;
+
+
+
+
+
+
+///Output sinks, destinations of the output rows of the solvers.
+///
+///Every solver has a `_to_sink` variant which gives each output row to an `OutputSink` instead of keeping the data in memory. There are sinks for in memory `Data`, text files (as `data_to_file`), CSV files, binary `.npy` files and channels, and new ones can be made by implementing `OutputSink`.
+///
+///# Example
+///
+///```
+///use odesolver::solver_vector as SV;
+///use odesolver::sink::{NpySink, TextFileSink};
+///use std::sync::mpsc;
+///use std::thread;
+///
+///fn main() {
+///    let odeparam = SV::ODEParam {time : 0.0, tend : 1.0
+///                          ,step : 0.001
+///                          ,ratio_step_output : 100
+///                          };
+///    let oscillator = |_time: f64, state: &SV::State| -> SV::DState { vec!(state[1], -state[0]) };
+///
+///    let file_text = std::env::temp_dir().join("odesolver_test_sink.txt").to_str().unwrap().to_string();
+///    let mut text = TextFileSink::new(file_text, None).unwrap();
+///    SV::solve_ode_to_sink(oscillator, odeparam.clone(), vec!(1.0, 0.0), SV::ODESolver::RK4, &mut text).unwrap();
+///
+///    let file_npy = std::env::temp_dir().join("odesolver_test_sink.npy").to_str().unwrap().to_string();
+///    let mut binary = NpySink::new(file_npy).unwrap();
+///    SV::solve_ode_to_sink(oscillator, odeparam.clone(), vec!(1.0, 0.0), SV::ODESolver::RK4, &mut binary).unwrap();
+///
+///    // rows processed by another thread while the simulation runs
+///    let (mut sender, receiver) = mpsc::channel();
+///    let consumer = thread::spawn(move || receiver.iter().count());
+///    SV::solve_ode_to_sink(oscillator, odeparam, vec!(1.0, 0.0), SV::ODESolver::RK4, &mut sender).unwrap();
+///    drop(sender);
+///    assert_eq!(consumer.join().unwrap(), 11);
+///}
+///```
+pub mod sink;
+
+
+
+
+
+
+///Checkpoint and restart of long simulations.
+///
+///A `CheckpointSink` saves the full state of the integration (with the history of the multistep solvers) to a file at regular intervals of output rows. The integration can be resumed later from the last checkpoint, giving exactly the same output as the uninterrupted integration.
+///
+///# Example
+///
+///```
+///use odesolver::solver_vector_trait as SVT;
+///use odesolver::checkpoint::{Checkpoint, CheckpointSink};
+///use odesolver::engine::History;
+///
+///#[derive(Clone)]
+///struct Oscillator {
+///    state : SVT::State,
+///}
+///
+///impl SVT::ODESystem for Oscillator {
+///    fn state (&self) -> &SVT::State{
+///        &self.state
+///    }
+///    
+///    fn dstate (&self, _time : f64) -> SVT::DState{
+///        vec!(self.state[1], -self.state[0])
+///    }
+///
+///    fn update_state(&mut self, state : SVT::State) {
+///        self.state = state;
+///    }
+///}
+///
+///#[derive(Clone)]
+///struct Stiff {
+///    state : SVT::State,
+///}
+///
+///impl SVT::ODESystem for Stiff {
+///    fn state (&self) -> &SVT::State{
+///        &self.state
+///    }
+///
+///    fn dstate (&self, time : f64) -> SVT::DState{
+///        vec!(-1000.0*(self.state[0] - time.cos()))
+///    }
+///
+///    fn update_state(&mut self, state : SVT::State) {
+///        self.state = state;
+///    }
+///}
+///
+///fn main() {
+///    let odeparam = SVT::ODEParam {time : 0.0, tend : 10.0
+///                          ,step : 0.001
+///                          ,ratio_step_output : 100
+///                          };
+///    let file_checkpoint = std::env::temp_dir().join("odesolver_test_checkpoint.bin").to_str().unwrap().to_string();
+///
+///    // the multistep solvers also save their history of past states and differentials
+///    for odesolver in [SVT::ODESolver::RK4, SVT::ODESolver::Adams(SVT::Tolerance::default()), SVT::ODESolver::Lsoda(SVT::Tolerance::default()), SVT::ODESolver::Rodas4(SVT::Tolerance::default())] {
+///        let mut sist = Oscillator { state : vec!(1.0, 0.0) };
+///        let mut data : SVT::Data = Vec::new();
+///        let mut sink = CheckpointSink::new(&mut data, file_checkpoint.clone(), 40, odeparam.clone(), odesolver);
+///        SVT::solve_ode_sist_mut_to_sink(&mut sist, odeparam.clone(), odesolver, &mut sink).unwrap();
+///
+///        // resume from the last checkpoint as if the simulation had been interrupted
+///        let checkpoint = Checkpoint::from_file(file_checkpoint.clone()).unwrap();
+///        let mut resumed_sist = Oscillator { state : checkpoint.state.clone() };
+///        let mut resumed : SVT::Data = Vec::new();
+///        let mut sink = CheckpointSink::resume(&mut resumed, file_checkpoint.clone(), 40, &checkpoint);
+///        SVT::solve_ode_sist_mut_to_sink(&mut resumed_sist, checkpoint.odeparam.clone(), checkpoint.odesolver, &mut sink).unwrap();
+///
+///        assert_eq!(checkpoint.output_rows, 80);
+///        assert_eq!(checkpoint.history.times.is_empty(), odesolver == SVT::ODESolver::RK4);
+///        assert_eq!(resumed, data[80..].to_vec());
+///    }
+///
+///    // a stiff system: Lsoda switches to BDF and saves its Jacobian, Radau5 saves the Jacobian of its next step
+///    let odeparam = SVT::ODEParam { time : 0.0, tend : 2.0, step : 0.01, ratio_step_output : 10 };
+///    for odesolver in [SVT::ODESolver::Lsoda(SVT::Tolerance::default()), SVT::ODESolver::Radau5(SVT::Tolerance::default())] {
+///        let mut sist = Stiff { state : vec!(0.0) };
+///        let mut data : SVT::Data = Vec::new();
+///        let mut sink = CheckpointSink::new(&mut data, file_checkpoint.clone(), 15, odeparam.clone(), odesolver);
+///        SVT::solve_ode_sist_mut_to_sink(&mut sist, odeparam.clone(), odesolver, &mut sink).unwrap();
+///
+///        let checkpoint = Checkpoint::from_file(file_checkpoint.clone()).unwrap();
+///        assert_eq!(checkpoint.history.stiff, matches!(odesolver, SVT::ODESolver::Lsoda(_)));
+///        assert!(!checkpoint.history.jacobian.is_empty());
+///
+///        let mut resumed_sist = Stiff { state : checkpoint.state.clone() };
+///        let mut resumed : SVT::Data = Vec::new();
+///        let mut sink = CheckpointSink::resume(&mut resumed, file_checkpoint.clone(), 15, &checkpoint);
+///        SVT::solve_ode_sist_mut_to_sink(&mut resumed_sist, checkpoint.odeparam.clone(), checkpoint.odesolver, &mut sink).unwrap();
+///        assert_eq!(resumed, data[checkpoint.output_rows as usize..].to_vec());
+///
+///        // an inconsistent history (a corrupted or edited file) is dropped and the solver starts again at the checkpoint
+///        let past = checkpoint.history.times.len();
+///        for history in [History { order : 0, .. checkpoint.history.clone() },
+///                        History { order : past, stiff : true, .. checkpoint.history.clone() },
+///                        History { step : f64::NAN, .. checkpoint.history.clone() }] {
+///            let corrupted = Checkpoint { history, .. checkpoint.clone() };
+///            let mut resumed_sist = Stiff { state : corrupted.state.clone() };
+///            let mut resumed : SVT::Data = Vec::new();
+///            let mut sink = CheckpointSink::resume(&mut resumed, file_checkpoint.clone(), 15, &corrupted);
+///            SVT::solve_ode_sist_mut_to_sink(&mut resumed_sist, corrupted.odeparam.clone(), corrupted.odesolver, &mut sink).unwrap();
+///            assert!((resumed.last().unwrap()[1] - data.last().unwrap()[1]).abs() < 1.0e-5);
+///        }
+///    }
+///
+///    // checkpoints of the older formats, version 2 (history of Adams without the past states) and 1 (no history), are still read
+///    let checkpoint = Checkpoint { history : Default::default(), .. Checkpoint::from_file(file_checkpoint).unwrap() };
+///    let mut version_2 = checkpoint.to_bytes();
+///    version_2[7] = 2;
+///    version_2.truncate(version_2.len() - 25);
+///    assert_eq!(Checkpoint::from_bytes(&version_2).unwrap(), checkpoint);
+///    let mut version_1 = version_2.clone();
+///    version_1[7] = 1;
+///    version_1.truncate(version_2.len() - 32);
+///    assert_eq!(Checkpoint::from_bytes(&version_1).unwrap(), checkpoint);
+///}
+///```
+pub mod checkpoint;
+
+
+
+
+
+
+
+///Parser and evaluator of mathematical expressions.
+///
+///Expressions with arithmetic, powers, the standard functions and named variables are parsed into an `Expr`, which can be evaluated given the values of the variables. It is used to define models in text files, see the module `model`.
+///
+///For the integration the expressions are compiled to a `Program`, with the variables resolved to indexes, and an `ExprSystem` gives the system function used by `solver_vector::solve_ode`. The expressions can be differentiated symbolically, which gives the exact Jacobian of the system.
+///
+///# Example
+///
+///```
+///use odesolver::expression::{Expr, ExprSystem};
+///use odesolver::solver_vector as SV;
+///
+///fn main() {
+///    let expr = Expr::parse("mu*(1 - x^2)*v - x").unwrap();
+///    assert_eq!(expr.variables(), vec!("mu", "x", "v"));
+///
+///    let lookup = |name : &str| match name { "mu" => Some(2.0), "x" => Some(0.5), "v" => Some(1.0), _ => None };
+///    assert_eq!(expr.eval(&lookup).unwrap(), 1.0);
+///
+///    let expr = Expr::parse("-2^2 + atan2(1, 1)*4/pi + 1.5e1").unwrap();
+///    assert_eq!(expr.eval(&|_| None).unwrap(), 12.0);
+///
+///    // errors have the position in the text
+///    assert!(Expr::parse("sin(x").is_err());
+///    assert!(Expr::parse("x + * y").is_err());
+///    assert!(Expr::parse("foo(x)").is_err());
+///    assert!(Expr::parse("x").unwrap().eval(&|_| None).is_err());
+///
+///    // symbolic derivatives, written back as text
+///    let expr = Expr::parse("x^3 + sin(2*x)*y").unwrap();
+///    assert_eq!(expr.derivative("x").to_string(), "3 * x^2 + cos(2 * x) * 2 * y");
+///    assert_eq!(expr.derivative("y").to_string(), "sin(2 * x)");
+///    assert_eq!(Expr::parse(&expr.to_string()).unwrap(), expr);
+///
+///    // compiled system of the Van der Pol oscillator, solved by `solver_vector`
+///    let derivatives = vec!(Expr::parse("v").unwrap(), Expr::parse("mu*(1 - x^2)*v - x").unwrap());
+///    let system = ExprSystem::new(&derivatives, &["x", "v"], &[("mu", 1.5)]).unwrap();
+///    let odeparam = SV::ODEParam { time : 0.0, tend : 1.0, step : 0.001, ratio_step_output : 100 };
+///    let result = SV::solve_ode(system.system_function(), odeparam.clone(), vec!(2.0, 0.0), SV::ODESolver::RK4);
+///    let native = SV::solve_ode(|_t: f64, s: &SV::State| vec!(s[1], 1.5*(1.0 - s[0]*s[0])*s[1] - s[0]), odeparam, vec!(2.0, 0.0), SV::ODESolver::RK4);
+///    assert!((result.states[10][0] - native.states[10][0]).abs() < 1.0e-12);
+///
+///    // exact Jacobian
+///    let mut jacobian = system.jacobian_function();
+///    assert_eq!(jacobian(0.0, &vec!(2.0, 1.0)), vec!(vec!(0.0, 1.0), vec!(-2.0*1.5*2.0*1.0 - 1.0, 1.5*(1.0 - 4.0))));
+///
+///    // unknown variables are found when compiling
+///    assert!(ExprSystem::new(&derivatives, &["x", "v"], &[]).is_err());
+///}
+///```
+pub mod expression;
+
+
+
+
+
+
+///Models defined by a text specification: states, parameters, expressions of the derivatives and settings of the simulation.
+///
+///It is used by the command line program, so models can be solved without writing and compiling Rust code.
+///
+///# Example
+///
+///```
+///use odesolver::model::Model;
+///
+///fn main() {
+///    let text = "
+///        param w = 2.0    # harmonic oscillator
+///        state x = 1.0
+///        state v = 0.0
+///        x' = v
+///        v' = -w^2*x
+///        tend = 1.0
+///        step = 0.001
+///        ratio_step_output = 100
+///        solver = rk4
+///    ";
+///    let mut model = Model::parse(text).unwrap();
+///    assert_eq!(model.column_names(), vec!("t", "x", "v"));
+///
+///    // tolerances of the adaptive solvers, in any order with the solver
+///    let adaptive = Model::parse(&format!("{}\nrtol = 1e-10\natol = 1e-12\nsolver = Radau5", text)).unwrap();
+///    let tolerance = odesolver::Tolerance { rtol : 1.0e-10, atol : 1.0e-12 };
+///    assert_eq!(adaptive.odesolver, odesolver::ODESolver::Radau5(tolerance));
+///    let result = adaptive.solve().unwrap();
+///    assert!((result.component(0)[10] - 2.0_f64.cos()).abs() < 1.0e-8);
+///    assert!(Model::parse(&format!("{}\nrtol = -1", text)).is_err());
+///
+///    let result = model.solve().unwrap();
+///    assert!((result.component(0)[10] - 2.0_f64.cos()).abs() < 1.0e-8);
+///
+///    model.set("w", 1.0).unwrap();
+///    let result = model.solve().unwrap();
+///    assert!((result.component(0)[10] - 1.0_f64.cos()).abs() < 1.0e-8);
+///
+///    let mut jacobian = model.system().unwrap().jacobian_function();
+///    assert_eq!(jacobian(0.0, &vec!(1.0, 0.0)), vec!(vec!(0.0, 1.0), vec!(-1.0, 0.0)));
+///
+///    // missing derivatives and unknown variables are rejected
+///    assert!(Model::parse("state x = 1\ntend = 1\nstep = 0.1").is_err());
+///    assert!(Model::parse("state x = 1\nx' = -k*x\ntend = 1\nstep = 0.1").is_err());
+///
+///    // invalid settings of the simulation
+///    assert!(Model::parse("state x = 1\nx' = -x\ntend = 1\nstep = -0.1").is_err());
+///    assert!(Model::parse("state x = 1\nx' = -x\ntend = 1\nstep = 0.1\nratio_step_output = 0").is_err());
+///    assert!(Model::parse("state x = 1\nx' = -x\ntime = 2\ntend = 1\nstep = 0.1").is_err());
+///    assert!(Model::parse("state x = 1\nx' = -x\ntend = 1/0\nstep = 0.1").is_err());
+///    assert!(model.set("w", f64::NAN).is_err());
+///    model.odeparam.step = 0.0;
+///    assert!(model.validate().is_err());
+///
+///    // a model changed after parsing with an unknown variable can not be compiled
+///    model.derivatives[1] = odesolver::expression::Expr::parse("-k*x").unwrap();
+///    assert!(model.system_function().is_err());
+///    assert!(model.solve().is_err());
+///}
+///```
+pub mod model;
+
+
+
+
+
+
+///Dual numbers for forward mode automatic differentiation.
+///
+///A function written for any `T : Real` (arithmetic and the elementary functions) is evaluated with `Dual` numbers to get its exact derivatives, without the truncation error of finite differences. `jacobian` gives the Jacobian of a function of a vector and `solver_vector_trait::GenericODESystem` the Jacobian of a system, which the implicit solvers use when it is solved with `solver_vector_trait::solve_ode_generic_system`. As `Dual` implements `engine::Scalar`, it can also be integrated by the solvers, giving the sensitivity of the solution.
+///
+///# Example
+///
+///```
+///use odesolver::dual::{self, Dual, Real};
+///use odesolver::solver_vector as SV;
+///use odesolver::solver_vector_trait as SVT;
+///use odesolver::solver_vector_trait::GenericODESystem;
+///
+///// Van der Pol oscillator, written once for any real scalar
+///#[derive(Clone)]
+///struct VanDerPol {
+///    state : SVT::State,
+///    mu : f64,
+///}
+///
+///impl SVT::ODESystem for VanDerPol {
+///    fn state (&self) -> &SVT::State { &self.state }
+///    fn dstate (&self, time : f64) -> SVT::DState { self.dstate_generic(time, &self.state) }
+///    fn update_state (&mut self, state : SVT::State) { self.state = state; }
+///}
+///
+///impl GenericODESystem for VanDerPol {
+///    fn dstate_generic<T : Real> (&self, _time : f64, state : &[T]) -> Vec<T> {
+///        let (x, v) = (state[0], state[1]);
+///        let mu = T::from_f64(self.mu);
+///        vec!(v, mu*(T::from_f64(1.0) - x*x)*v - x)
+///    }
+///}
+///
+///fn main() {
+///    let derivative = dual::derivative(|x : Dual| x.sin()*x.exp(), 0.5);
+///    assert!((derivative - (0.5_f64.cos() + 0.5_f64.sin())*0.5_f64.exp()).abs() < 1.0e-15);
+///
+///    let sist = VanDerPol { state : vec!(2.0, 1.0), mu : 1.5 };
+///    assert_eq!(sist.jacobian(0.0), vec!(vec!(0.0, 1.0), vec!(-2.0*1.5*2.0*1.0 - 1.0, 1.5*(1.0 - 4.0))));
+///
+///    // the implicit solvers use the exact Jacobian instead of finite differences, which cost one evaluation for each component
+///    let odeparam = SV::ODEParam { time : 0.0, tend : 2.0, step : 0.1, ratio_step_output : 1 };
+///    let exact = SVT::solve_ode_generic_system(sist.clone(), odeparam.clone(), SV::ODESolver::Rodas4(SV::Tolerance::default()));
+///    let differences = SVT::solve_ode(sist.clone(), odeparam.clone(), SV::ODESolver::Rodas4(SV::Tolerance::default()));
+///    assert!(exact.stats.fevals < differences.stats.fevals);
+///    assert!((exact.system.state[0] - differences.system.state[0]).abs() < 1.0e-4);
+///    let mut data : SVT::Data = Vec::new();
+///    let (landed, _) = SVT::solve_ode_generic_system_tstops_to_sink(sist.clone(), odeparam.clone(), SV::ODESolver::Rodas4(SV::Tolerance::default()), &[0.25], true, &mut data).unwrap();
+///    assert_eq!(data[3][0], 0.25);
+///    assert!((landed.state[0] - exact.system.state[0]).abs() < 1.0e-4);
+///
+///    let jacobian = dual::jacobian(|x : &[Dual]| vec!(x[0]*x[1], x[0].powi(3)/x[1]), &[2.0, 4.0]);
+///    assert_eq!(jacobian, vec!(vec!(4.0, 2.0), vec!(3.0, -0.5)));
+///
+///    // sensitivity of the solution of x' = -k x to the initial state, through the solver
+///    let odeparam = SV::ODEParam { time : 0.0, tend : 1.0, step : 0.01, ratio_step_output : 10 };
+///    let decay = |_time : f64, state : &[Dual;1]| [-2.0*state[0]];
+///    let result = SV::solve_ode_generic(decay, odeparam, [Dual::variable(3.0)], SV::ODESolver::RK4);
+///    assert!((result.system[0].value - 3.0*(-2.0_f64).exp()).abs() < 1.0e-8);
+///    assert!((result.system[0].derivative - (-2.0_f64).exp()).abs() < 1.0e-8);
+///}
+///```
+pub mod dual;
+
+mod adams;
+
+mod lsoda;
+
+mod rosenbrock;
+
+mod implicit;
+
+mod collocation;
+
+mod theta;
+
+mod linalg;
@@ -0,0 +1,280 @@
+//! Small dense linear algebra helpers used internally by the library (LU factorization, determinant, eigenvalues and finite difference jacobians).
+
+#![allow(clippy::needless_range_loop)]
+
+use crate::engine::Scalar;
+
+/// Dense matrix stored by rows.
+pub type Matrix = Vec<Vec<f64>>;
+
+// QR iterations allowed to find each eigenvalue (or pair)
+const MAX_QR_ITERATIONS : usize = 60;
+
+
+/// LU factorization with partial pivoting of a square matrix, of `f64` or of any `Scalar` (used by the implicit solvers).
+pub struct LU<T = f64> {
+    lu : Vec<Vec<T>>,
+    pivots : Vec<usize>,
+    sign : f64,
+}
+
+impl<T : Scalar> LU<T> {
+
+    /// Factorizes `a`. Returns `None` if the matrix is singular.
+    pub fn new (a : &[Vec<T>]) -> Option<LU<T>> {
+        let n = a.len();
+        let mut lu = a.to_vec();
+        let mut pivots : Vec<usize> = (0..n).collect();
+        let mut sign = 1.0;
+
+        for k in 0..n {
+            let mut p = k;
+            let mut max = lu[k][k].magnitude();
+            for (i,row) in lu.iter().enumerate().skip(k+1) {
+                if row[k].magnitude() > max {
+                    max = row[k].magnitude();
+                    p = i;
+                }
+            }
+            if max == 0.0 || !max.is_finite() {
+                return None;
+            }
+            if p != k {
+                lu.swap(p,k);
+                pivots.swap(p,k);
+                sign = -sign;
+            }
+            let pivot_row = lu[k].clone();
+            for row in lu.iter_mut().skip(k+1) {
+                let factor = row[k]/pivot_row[k];
+                row[k] = factor;
+                for j in k+1 .. n {
+                    row[j] = row[j] - factor*pivot_row[j];
+                }
+            }
+        }
+
+        Some(LU { lu, pivots, sign })
+    }
+
+    /// Solves `a x = b` using the factorization.
+    pub fn solve (&self, b : &[T]) -> Vec<T> {
+        let n = self.lu.len();
+        let mut x : Vec<T> = self.pivots.iter().map(|&p| b[p]).collect();
+
+        for i in 0..n {
+            for j in 0..i {
+                x[i] = x[i] - self.lu[i][j]*x[j];
+            }
+        }
+        for i in (0..n).rev() {
+            for j in i+1 .. n {
+                x[i] = x[i] - self.lu[i][j]*x[j];
+            }
+            x[i] = x[i]/self.lu[i][i];
+        }
+
+        x
+    }
+
+    /// Determinant of the factorized matrix.
+    pub fn determinant (&self) -> T {
+        self.lu.iter().enumerate().fold(T::from_f64(self.sign), |det,(i,row)| det*row[i])
+    }
+}
+
+
+/// Determinant of a square matrix (zero if it is singular).
+pub fn determinant (a : &Matrix) -> f64 {
+    match LU::new(a) {
+        Some(lu) => lu.determinant(),
+        None => 0.0,
+    }
+}
+
+
+/// Jacobian of `function` at `x` by forward finite differences. `fx` is the value of `function` at `x`.
+pub fn jacobian_fd<F : FnMut (&[f64]) -> Vec<f64>> (function : &mut F, x : &[f64], fx : &[f64]) -> Matrix {
+    let n = x.len();
+    let m = fx.len();
+    let mut jac = vec!(vec!(0.0; n); m);
+    let mut xh = x.to_vec();
+
+    for j in 0..n {
+        let h = f64::EPSILON.sqrt()*x[j].abs().max(1.0);
+        xh[j] = x[j] + h;
+        let fh = function(&xh);
+        for i in 0..m {
+            jac[i][j] = (fh[i] - fx[i])/h;
+        }
+        xh[j] = x[j];
+    }
+
+    jac
+}
+
+
+/// Eigenvalues `(real, imaginary)` of a real square matrix.
+///
+/// The matrix is reduced to Hessenberg form by Householder reflections and the eigenvalues are found by the Francis double shift QR algorithm, as in Golub and Van Loan, "Matrix Computations". Returns `None` if the QR iterations do not converge.
+pub fn eigenvalues (a : &Matrix) -> Option<Vec<(f64,f64)>> {
+    let n = a.len();
+    let mut h = a.clone();
+    hessenberg(&mut h);
+    let norm = h.iter().flatten().map(|x| x.abs()).sum::<f64>();
+
+    let mut eigenvalues = Vec::with_capacity(n);
+    // the eigenvalues of the rows and columns `hi ..` are already found
+    let mut hi = n;
+    let mut iterations = 0;
+    while hi > 0 {
+        // start of the unreduced block which ends at `hi`
+        let mut lo = hi - 1;
+        while lo > 0 {
+            let mut scale = h[lo-1][lo-1].abs() + h[lo][lo].abs();
+            if scale == 0.0 {
+                scale = norm;
+            }
+            if h[lo][lo-1].abs() <= f64::EPSILON*scale {
+                h[lo][lo-1] = 0.0;
+                break;
+            }
+            lo -= 1;
+        }
+
+        if lo + 1 == hi {
+            eigenvalues.push((h[lo][lo], 0.0));
+            hi -= 1;
+            iterations = 0;
+        } else if lo + 2 == hi {
+            let (first, second) = eigenvalues_2x2(h[lo][lo], h[lo][lo+1], h[lo+1][lo], h[lo+1][lo+1]);
+            eigenvalues.push(first);
+            eigenvalues.push(second);
+            hi -= 2;
+            iterations = 0;
+        } else {
+            if iterations == MAX_QR_ITERATIONS {
+                return None;
+            }
+            iterations += 1;
+            francis_step(&mut h, lo, hi, iterations % 10 == 0);
+        }
+    }
+
+    Some(eigenvalues)
+}
+
+// Eigenvalues of the 2x2 matrix [[a, b], [c, d]]
+fn eigenvalues_2x2 (a : f64, b : f64, c : f64, d : f64) -> ((f64,f64), (f64,f64)) {
+    let mean = 0.5*(a + d);
+    let half_difference = 0.5*(a - d);
+    let discriminant = half_difference*half_difference + b*c;
+    if discriminant >= 0.0 {
+        // the root of largest magnitude, the other one from the determinant without cancellation
+        let large = mean + discriminant.sqrt().copysign(half_difference);
+        let small = if large != 0.0 { (a*d - b*c)/large } else { mean - discriminant.sqrt().copysign(half_difference) };
+        ((large, 0.0), (small, 0.0))
+    } else {
+        let imaginary = (-discriminant).sqrt();
+        ((mean, imaginary), (mean, -imaginary))
+    }
+}
+
+
+// Householder vector `v` of `x`, such that the reflection `I - 2 v v'/(v'v)` maps `x` to a multiple of the first unit vector. Returns `v'v`, zero if `x` is already that multiple.
+fn householder (x : &[f64], v : &mut [f64]) -> f64 {
+    let norm = x.iter().map(|x| x*x).sum::<f64>().sqrt();
+    v.copy_from_slice(x);
+    if norm == 0.0 {
+        return 0.0;
+    }
+    v[0] += norm.copysign(x[0]);
+    let vv = v.iter().map(|v| v*v).sum::<f64>();
+    if x[1..].iter().all(|x| *x == 0.0) { 0.0 } else { vv }
+}
+
+// Applies the reflection of `v` from the left to the rows `first ..` (as many as `v`) in the columns `columns`
+fn reflect_rows (h : &mut Matrix, v : &[f64], vv : f64, first : usize, columns : std::ops::Range<usize>) {
+    for j in columns {
+        let dot = v.iter().enumerate().map(|(i, v)| v*h[first+i][j]).sum::<f64>();
+        let factor = 2.0*dot/vv;
+        for (i, v) in v.iter().enumerate() {
+            h[first+i][j] -= factor*v;
+        }
+    }
+}
+
+// Applies the reflection of `v` from the right to the columns `first ..` (as many as `v`) in the rows `rows`
+fn reflect_columns (h : &mut Matrix, v : &[f64], vv : f64, first : usize, rows : std::ops::Range<usize>) {
+    for row in &mut h[rows] {
+        let dot = v.iter().enumerate().map(|(j, v)| v*row[first+j]).sum::<f64>();
+        let factor = 2.0*dot/vv;
+        for (j, v) in v.iter().enumerate() {
+            row[first+j] -= factor*v;
+        }
+    }
+}
+
+
+// Reduces `h` to upper Hessenberg form by a similarity transformation with Householder reflections
+fn hessenberg (h : &mut Matrix) {
+    let n = h.len();
+    let mut x = vec!(0.0; n);
+    let mut v = vec!(0.0; n);
+    for k in 0 .. n.saturating_sub(2) {
+        let size = n - k - 1;
+        for i in 0 .. size {
+            x[i] = h[k+1+i][k];
+        }
+        let vv = householder(&x[.. size], &mut v[.. size]);
+        if vv == 0.0 {
+            continue;
+        }
+        reflect_rows(h, &v[.. size], vv, k+1, k .. n);
+        reflect_columns(h, &v[.. size], vv, k+1, 0 .. n);
+        for row in h.iter_mut().skip(k+2) {
+            row[k] = 0.0;
+        }
+    }
+}
+
+
+// One Francis double shift QR step on the unreduced Hessenberg block of the rows and columns `lo .. hi` (at least 3x3), with an exceptional shift to break cycles if `exceptional`
+fn francis_step (h : &mut Matrix, lo : usize, hi : usize, exceptional : bool) {
+    let m = hi - 1;
+    // sum and product of the two shifts, the eigenvalues of the last 2x2 block
+    let (sum, product) = if exceptional {
+        let w = h[m][m-1].abs() + h[m-1][m-2].abs();
+        (1.5*w, w*w)
+    } else {
+        (h[m-1][m-1] + h[m][m], h[m-1][m-1]*h[m][m] - h[m-1][m]*h[m][m-1])
+    };
+
+    // first column of (H - s1 I)(H - s2 I)
+    let mut x = [
+        h[lo][lo]*h[lo][lo] + h[lo][lo+1]*h[lo+1][lo] - sum*h[lo][lo] + product,
+        h[lo+1][lo]*(h[lo][lo] + h[lo+1][lo+1] - sum),
+        h[lo+1][lo]*h[lo+2][lo+1],
+    ];
+    let mut v = [0.0; 3];
+
+    // chase the bulge down the block
+    for k in lo .. hi - 2 {
+        let vv = householder(&x, &mut v);
+        if vv != 0.0 {
+            reflect_rows(h, &v, vv, k, k.max(lo+1) - 1 .. hi);
+            reflect_columns(h, &v, vv, k, lo .. (k+4).min(hi));
+        }
+        x[0] = h[k+1][k];
+        x[1] = h[k+2][k];
+        if k + 3 < hi {
+            x[2] = h[k+3][k];
+        }
+    }
+    let vv = householder(&x[.. 2], &mut v[.. 2]);
+    if vv != 0.0 {
+        reflect_rows(h, &v[.. 2], vv, hi-2, hi-3 .. hi);
+        reflect_columns(h, &v[.. 2], vv, hi-2, lo .. hi);
+    }
+}
+
@@ -0,0 +1,215 @@
+//! Automatic switching between the Adams methods and the BDF (backward differentiation formulas) used by `ODESolver::Lsoda`, as in LSODA (Petzold and Hindmarsh).
+//!
+//! In the Adams steps the spectral radius of the jacobian is estimated from the differentials at the predicted and the corrected states, and in the BDF steps it is bounded by the norm of the jacobian. The method is switched to BDF when, for some consecutive steps, the stability of the Adams methods limits the step to less than a fifth of the step allowed by the error, and back to Adams when their stability no longer limits the step.
+//!
+//! The BDF of order k has the coefficients of the derivative of the polynomial through the last k states and the new one, whose implicit equation is solved by Newton iterations with the jacobian of the system, or a finite difference one (kept for some steps). The local error is estimated from the difference of the new state with its extrapolation from the last k + 1 states.
+
+use crate::adams::{self, error_norm, step_factor, step_ratio, MAX_ORDER};
+use crate::engine::{Scalar, SolveStats, System, VectorSpace, Workspace};
+use crate::implicit::{self, factorize};
+use crate::Tolerance;
+
+pub(crate) const MAX_ORDER_BDF : usize = 5;
+
+// Radius of the stability regions of the Adams methods of orders 1 to 12, as in LSODA
+const ADAMS_STABILITY : [f64; MAX_ORDER] = [0.5, 0.575, 0.55, 0.45, 0.35, 0.25, 0.2, 0.15, 0.1, 0.075, 0.05, 0.025];
+// Ratio of the steps which makes BDF preferable, and number of consecutive steps where it has to happen to switch the method
+const SWITCH_RATIO : f64 = 5.0;
+const SWITCH_STEPS : usize = 10;
+
+const JACOBIAN_MAX_AGE : usize = 20;
+const NEWTON_ITERATIONS : usize = 4;
+// Tolerance of the Newton iterations relative to the tolerance of the step
+const NEWTON_TOLERANCE : f64 = 0.03;
+
+
+// Maximum of the sums of the absolute values of the rows, a bound of the spectral radius
+fn jacobian_norm<T : Scalar, S : VectorSpace<T>> (jacobian : &[S]) -> f64 {
+    jacobian.iter().map(|row| row.components().iter().map(|x| x.magnitude()).sum()).fold(0.0, f64::max)
+}
+
+// Values (`derivative` false) or derivatives at `s` of the Lagrange polynomials of the `nodes`
+fn lagrange_at (nodes : &[f64], s : f64, derivative : bool, weights : &mut Vec<f64>) {
+    weights.clear();
+    for (j, node_j) in nodes.iter().enumerate() {
+        let mut value = 1.0;
+        let mut slope = 0.0;
+        for (i, node) in nodes.iter().enumerate() {
+            if i != j {
+                let factor = (s - node)/(node_j - node);
+                slope = slope*factor + value/(node_j - node);
+                value *= factor;
+            }
+        }
+        weights.push(if derivative { slope } else { value });
+    }
+}
+
+// `output = sum(weights[j]*vectors[j])`
+fn linear_combination<T : Scalar, S : VectorSpace<T>> (output : &mut S, weights : &[f64], vectors : &[S]) {
+    output.components_mut().fill(T::zero());
+    for (weight, vector) in weights.iter().zip(vectors) {
+        output.axpy(*weight, vector);
+    }
+}
+
+
+// Makes one accepted step of the BDF from `time`, of at most `goal - time`, and returns the time reached
+fn bdf_step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, goal : f64, tolerance : Tolerance, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) -> f64 {
+    let Workspace { k1, k2, k3, k4, stage, multistep, .. } = workspace;
+    let size = system.state().components().len();
+
+    loop {
+        if multistep.jacobian.is_empty() || multistep.jacobian_age >= JACOBIAN_MAX_AGE {
+            let dstate = multistep.dstates.last().expect("history of the BDF");
+            stats.fevals += implicit::jacobian(system, time, dstate, &mut multistep.jacobian, stage, k1);
+            multistep.jacobian_age = 0;
+        }
+
+        let order = multistep.order;
+        let proposed = multistep.step;
+        let clipped = time + proposed >= goal;
+        let (step, new_time) = if clipped { (goal - time, goal) } else { (proposed, time + proposed) };
+        let too_small = time + step == time;
+
+        let past = multistep.times.len();
+        multistep.nodes.clear();
+        multistep.nodes.extend(multistep.times.iter().map(|past_time| (past_time - time)/step));
+        multistep.nodes.push(1.0);
+        let nodes = &multistep.nodes;
+
+        // prediction by extrapolation of the last order + 1 states
+        lagrange_at(&nodes[past - order - 1 .. past], 1.0, false, &mut multistep.weights);
+        linear_combination(k2, &multistep.weights, &multistep.states[past - order - 1 ..]);
+
+        // the new state y solves c*y + k3 = f(new_time, y)
+        lagrange_at(&nodes[past - order ..], 1.0, true, &mut multistep.weights);
+        let c = multistep.weights[order]/step;
+        let coefficients : Vec<f64> = multistep.weights[.. order].iter().map(|weight| weight/step).collect();
+        linear_combination(k3, &coefficients, &multistep.states[past - order ..]);
+
+        // Newton iterations from the prediction
+        let mut converged = false;
+        if let Some(lu) = factorize(c, &multistep.jacobian) {
+            stage.assign(k2);
+            let mut previous = f64::INFINITY;
+            for _iteration in 0 .. NEWTON_ITERATIONS {
+                system.dstate_at(new_time, stage, k1);
+                stats.fevals += 1;
+                let residual : Vec<T> = stage.components().iter().zip(k3.components()).zip(k1.components())
+                    .map(|((y, r), f)| *f - *r - y.scale(c)).collect();
+                let delta = lu.solve(&residual);
+
+                let mut sum = 0.0;
+                for (y, d) in stage.components_mut().iter_mut().zip(&delta) {
+                    *y += *d;
+                    let scale = tolerance.atol + tolerance.rtol*y.magnitude();
+                    sum += (d.magnitude()/scale).powi(2);
+                }
+                let norm = (sum/size.max(1) as f64).sqrt();
+
+                if norm <= NEWTON_TOLERANCE {
+                    converged = true;
+                    break;
+                }
+                if !norm.is_finite() || norm > 2.0*previous {
+                    break;
+                }
+                previous = norm;
+            }
+        }
+
+        if !converged && !too_small {
+            // with a new jacobian, else with a smaller step
+            stats.rejected_steps += 1;
+            if multistep.jacobian_age > 0 {
+                multistep.jacobian_age = JACOBIAN_MAX_AGE;
+            } else {
+                multistep.step = 0.25*step;
+            }
+            continue;
+        }
+
+        let error = error_norm(tolerance, system.state(), stage, k2)/(order as f64 + 1.0);
+        if (error > 1.0 || error.is_nan()) && !too_small {
+            multistep.step = if error.is_finite() { step*step_factor(error, order).min(1.0) } else { 0.5*step };
+            stats.rejected_steps += 1;
+            continue;
+        }
+
+        // order of the next step, by the errors of the extrapolations of the neighbouring orders
+        let mut new_order = order;
+        let mut new_error = error;
+        if order > 1 {
+            lagrange_at(&nodes[past - order .. past], 1.0, false, &mut multistep.weights);
+            linear_combination(k3, &multistep.weights, &multistep.states[past - order ..]);
+            let lower = error_norm(tolerance, system.state(), stage, k3)/order as f64;
+            if step_ratio(lower, order - 1) > step_ratio(new_error, new_order) {
+                new_order = order - 1;
+                new_error = lower;
+            }
+        }
+        if order < MAX_ORDER_BDF && past > order + 1 {
+            lagrange_at(&nodes[past - order - 2 .. past], 1.0, false, &mut multistep.weights);
+            linear_combination(k3, &multistep.weights, &multistep.states[past - order - 2 ..]);
+            let higher = error_norm(tolerance, system.state(), stage, k3)/(order as f64 + 2.0);
+            if step_ratio(higher, order + 1) > step_ratio(new_error, new_order) {
+                new_order = order + 1;
+                new_error = higher;
+            }
+        }
+
+        system.dstate_at(new_time, stage, k4);
+        stats.fevals += 1;
+        stats.accepted_steps += 1;
+        system.update_state(stage);
+        multistep.push(new_time, system.state(), k4);
+
+        multistep.order = new_order;
+        multistep.ratio = step_ratio(new_error, new_order);
+        multistep.jacobian_age += 1;
+        if error.is_finite() {
+            multistep.step = step*step_factor(new_error, new_order);
+            if clipped {
+                multistep.step = multistep.step.max(proposed);
+            }
+        }
+        return new_time;
+    }
+}
+
+
+// Makes one accepted step from `time`, of at most `goal - time`, with Adams or BDF, and returns the time reached. The method for the next step is chosen after the step.
+pub(crate) fn step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, goal : f64, initial_step : f64, tolerance : Tolerance, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) -> f64 {
+    let stiff = workspace.multistep.stiff && workspace.multistep.times.last() == Some(&time);
+
+    let new_time = if stiff {
+        bdf_step(system, time, goal, tolerance, workspace, stats)
+    } else {
+        adams::step(system, time, goal, initial_step, tolerance, workspace, stats)
+    };
+
+    // ratio of the next step to the last one allowed by the stability of the Adams methods
+    let multistep = &mut workspace.multistep;
+    let spectral_radius = if stiff { jacobian_norm(&multistep.jacobian) } else { multistep.spectral_radius };
+    let stability = ADAMS_STABILITY[multistep.order - 1]/((new_time - time)*spectral_radius);
+
+    // the Adams steps are kept stable, so their error is the error of the smooth solution
+    if !stiff && spectral_radius > 0.0 {
+        multistep.step = multistep.step.min(stability*(new_time - time));
+    }
+
+    let switch = if stiff { stability >= multistep.ratio } else { multistep.ratio >= SWITCH_RATIO*stability };
+    multistep.switch_count = if switch { multistep.switch_count + 1 } else { 0 };
+
+    if multistep.switch_count >= SWITCH_STEPS {
+        multistep.switch_count = 0;
+        multistep.stiff = !stiff;
+        if multistep.stiff {
+            multistep.order = multistep.order.min(MAX_ORDER_BDF).min(multistep.times.len() - 1);
+            multistep.jacobian.clear();
+        }
+    }
+
+    new_time
+}
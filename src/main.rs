@@ -1,115 +1,141 @@
+use std::env;
+use std::error::Error;
+use std::path::Path;
+use std::process;
+
+use odesolver::csv::CsvFormat;
+use odesolver::model::Model;
+use odesolver::sink::{CsvSink, NpySink, OutputSink, TextFileSink};
 use odesolver::solver_vector as SV;
-use odesolver::solver_vector_trait as SVT;
-// use odesolver::solver_trait as ST;
 
-
-// const ORDER:usize = 2;
-// const ORDER_T:usize = ORDER + 1;
-
-fn main() {
-    let initial_state = vec!(3.0,2.0);
-    let mut test_state = initial_state.clone();
-    // let initial_state = [3.0,2.0];
-    let tstart = 0.0;
-    let tend = 400.0;
-    let step = 0.0001;
-    let ratio_step_output = 100;
-    let odeparam = SVT::ODEParam {time : tstart, tend
-                          ,step
-                          ,ratio_step_output
-                          };
-
-    let test = |a:f64 , b:& SV::State | -> SV::DState  {
-        test_state = vec!(3.0,3.0); 
-        system_function(a,b)};
-    let file_vec = "./teste_vec.txt".to_string();
-    let (data,_) = SV::solve_ode(test, odeparam.clone(), initial_state.clone(), SV::ODESolver::RK4); 
-    // let (data,_) = SV::solve_ode(system_function, odeparam.clone(), initial_state.clone(), SV::ODESolver::RK4); 
-    
-    SV::data_to_file(&data, file_vec, None).unwrap();
-
-    
-    // let sist = FSist1 { state : initial_state
-    //                     // , outros_parametros : false
-    // };
-
-    // let file_trait = "./teste_trait.txt".to_string();
-    // let(data,_,_) = ST::solve_ode::<ORDER,ORDER_T,_>(sist, odeparam, ST::ODESolver::RK4);
-    // ST::data_to_file(&data, file_trait, None).unwrap();
-    
-    
-    // let sist = FSist2 { state : initial_state };
-    // let file_vec_trait = "./teste_vec_trait.txt".to_string();
-    // let(data,_,_) = SVT::solve_ode::<_>(sist, odeparam, SVT::ODESolver::RK4);
-    
-    
-    // SVT::data_to_file(&data, file_vec_trait, None).unwrap();
-    
+const USAGE : &str = "Usage: odesolver MODEL [options]
+
+Solves the ODEs of the model file MODEL and writes the results.
+
+Options:
+  -o, --output FILE     output file (default: MODEL with the extension of the format)
+  -f, --format FORMAT   text, csv or npy (default: from the extension of FILE, else text)
+  --solver SOLVER       solver, overriding the one of the model (RK4, Euler, Adams, LSODA, ROS3P, Rodas4,
+                        Radau5, GaussLegendre, BackwardEuler, Trapezoidal)
+  --rtol VALUE          relative tolerance of the adaptive solvers, overriding the one of the model
+  --atol VALUE          absolute tolerance of the adaptive solvers, overriding the one of the model
+  -s, --set NAME=VALUE  value of a parameter or initial condition of a state
+  -h, --help            prints this message
+
+The model file has one item per line, `#` starts a comment:
+
+  param mu = 1.5
+  state x = 2.0
+  state v = 0.0
+  x' = v
+  v' = mu*(1 - x^2)*v - x
+  tend = 20.0
+  step = 0.001
+  ratio_step_output = 10
+  solver = RK4
+  rtol = 1e-6           # tolerances of the adaptive solvers (Adams, LSODA, ROS3P, Rodas4, Radau5)
+  atol = 1e-9";
+
+
+// Options of the command line
+struct Options {
+    model : String,
+    output : Option<String>,
+    format : Option<String>,
+    solver : Option<String>,
+    rtol : Option<f64>,
+    atol : Option<f64>,
+    set : Vec<(String, f64)>,
 }
 
 
-// #[derive(Clone)]
-// struct FSist1<const N: usize> {
-//     state : ST::State<N>,
-// }
-
+fn parse_args (args : &[String]) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options { model : String::new(), output : None, format : None, solver : None, rtol : None, atol : None, set : Vec::new() };
+    let mut model = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| format!("Missing value of {}", arg));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            "-o" | "--output" => options.output = Some(value()?),
+            "-f" | "--format" => options.format = Some(value()?),
+            "--solver" => options.solver = Some(value()?),
+            "--rtol" | "--atol" => {
+                let number = value()?;
+                let number = number.trim().parse().map_err(|_| format!("Invalid value of {}: '{}'", arg, number))?;
+                if arg == "--rtol" { options.rtol = Some(number) } else { options.atol = Some(number) }
+            },
+            "-s" | "--set" => {
+                let assignment = value()?;
+                let (name, number) = assignment.split_once('=').ok_or_else(|| format!("Expected NAME=VALUE, found '{}'", assignment))?;
+                let number = number.trim().parse().map_err(|_| format!("Invalid value in '{}'", assignment))?;
+                options.set.push((name.trim().to_string(), number));
+            },
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg).into()),
+            _ if model.is_none() => model = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument {}", arg).into()),
+        }
+    }
 
-#[derive(Clone)]
-struct FSist2 {
-    state : SVT::State,
+    options.model = model.ok_or("Missing the model file")?;
+    Ok(options)
 }
 
 
-fn system_function (_time: f64, state: &SV::State) -> SV::DState {
-    let mut dstate =  vec!(0.0;2);
-    dstate[0] = -0.5*state[0];
-    dstate[1] = -0.00001*state[1];
-    
-    dstate
+fn run (options : Options) -> Result<(), Box<dyn Error>> {
+    let mut model = Model::from_file(options.model.clone())?;
+    for (name, value) in &options.set {
+        model.set(name, *value)?;
+    }
+    if let Some(solver) = &options.solver {
+        model.odesolver = solver.parse()?;
+    }
+    model.tolerance.rtol = options.rtol.unwrap_or(model.tolerance.rtol);
+    model.tolerance.atol = options.atol.unwrap_or(model.tolerance.atol);
+    model.odesolver = model.odesolver.with_tolerance(model.tolerance);
+    model.validate()?;
+
+    let extension = options.output.as_ref()
+        .and_then(|output| Path::new(output).extension())
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    let format = match (&options.format, extension.as_deref()) {
+        (Some(format), _) => format.to_lowercase(),
+        (None, Some("csv")) => "csv".to_string(),
+        (None, Some("npy")) => "npy".to_string(),
+        (None, _) => "text".to_string(),
+    };
+    let output = options.output.unwrap_or_else(|| {
+        let extension = if format == "text" { "txt" } else { format.as_str() };
+        Path::new(&options.model).with_extension(extension).to_string_lossy().into_owned()
+    });
+
+    let names = model.column_names();
+    let mut sink : Box<dyn OutputSink> = match format.as_str() {
+        "text" => Box::new(TextFileSink::new(output, Some(names.join("\t")))?),
+        "csv" => Box::new(CsvSink::new(output, CsvFormat { names : Some(names), ..CsvFormat::default() })?),
+        "npy" => Box::new(NpySink::new(output)?),
+        _ => return Err(format!("Unknown format '{}'", format).into()),
+    };
+
+    let system = model.system()?;
+    SV::solve_ode_with_jacobian_to_sink(system.system_function(), system.jacobian_function(), model.odeparam.clone(), model.initial_state.clone(), model.odesolver, sink.as_mut())?;
+
+    Ok(())
 }
 
-// impl ST::ODESystem<ORDER> for FSist1<ORDER> {
-//     fn state (&self) -> &ST::State<ORDER>{
-//         return &self.state;
-//     }
-    
-//     fn dstate (&self, _time : f64) -> ST::DState<ORDER>{
-//         let state = self.state;
-//         let mut dstate  = [0.0; ORDER];
-        
-//         dstate[0] = -0.5*state[0];
-//         dstate[1] =  -0.00001*state[1];
-
-//         dstate
-//     }
 
-//     // fn new_from_state (&self, state : ST::State<ORDER>) -> Self {
-//     //     FSist1::<ORDER> { state, .. *self}
-//     // }
-
-//     fn update_state(&mut self, state : ST::State<ORDER>) {
-//         self.state = state;
-//     }
-// }
-
-
-
-impl SVT::ODESystem for FSist2 {
-    fn state (&self) -> &SVT::State{
-        return &self.state;
-    }
-    
-    fn dstate (&self, _time : f64) -> SVT::DState{
-        let state = self.state();
-        let mut dstate  = Vec::<f64>::new();
-        
-        dstate.push(-0.5*state[0]); //[0]
-        dstate.push(-0.00001*state[1]); //[1]
-
-        dstate
-    }
+fn main() {
+    let args : Vec<String> = env::args().skip(1).collect();
 
-    fn update_state(&mut self, state : SVT::State) {
-        self.state = state;
+    let result = parse_args(&args).and_then(run);
+    if let Err(erro) = result {
+        eprintln!("Error: {}", erro);
+        eprintln!("Run with --help for the usage.");
+        process::exit(1);
     }
 }
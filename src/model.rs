@@ -0,0 +1,238 @@
+pub use super::*;
+pub use crate::solver_vector::{State, DState, Data, SolveResult};
+use crate::expression::{Expr, ExprSystem, is_reserved};
+use std::io;
+
+/// Struct Model
+///
+/// System of ODEs read from a text specification: the names of the `states` with their `initial_state`, the named `params` with their values, the expressions of the `derivatives` (one for each state, in the same order) and the `odeparam` and `odesolver` of the simulation.
+///
+/// The specification has one item per line, and `#` starts a comment:
+///
+///```text
+/// # Van der Pol oscillator
+/// param mu = 1.5
+/// state x = 2.0
+/// state v = 0.0
+/// x' = v
+/// v' = mu*(1 - x^2)*v - x
+/// tend = 20.0
+/// step = 0.001
+/// ratio_step_output = 10
+/// solver = RK4
+///```
+///
+/// The values of the parameters and initial conditions can be expressions of the parameters declared before them. The derivatives are expressions of the states, the parameters and the time `t` (see `expression::Expr::parse`). The settings are `time` (initial time, 0 by default), `tend`, `step`, `ratio_step_output` (1 by default), `solver` (RK4 by default) and the `rtol` and `atol` of the `tolerance` of the adaptive solvers (the default `Tolerance`), which is given to `odesolver`.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Model {
+    pub states : Vec<String>,
+    pub initial_state : State,
+    pub params : Vec<(String, f64)>,
+    pub derivatives : Vec<Expr>,
+    pub odeparam : ODEParam,
+    pub odesolver : ODESolver,
+    pub tolerance : Tolerance,
+}
+
+
+fn invalid (message : String) -> Box<dyn Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+fn check_name (name : &str, model : &Model) -> Result<(), Box<dyn Error>> {
+    let valid = name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    if !valid || name == "t" || is_reserved(name) {
+        return Err(invalid(format!("Invalid name '{}'", name)));
+    }
+    if model.states.iter().any(|state| state == name) || model.params.iter().any(|(param, _)| param == name) {
+        return Err(invalid(format!("'{}' declared twice", name)));
+    }
+    Ok(())
+}
+
+
+impl Model {
+
+    /// Parses a model from its text specification. The errors have the number of the line.
+    pub fn parse (text : &str) -> Result<Model, Box<dyn Error>> {
+        let mut model = Model {
+            states : Vec::new(),
+            initial_state : Vec::new(),
+            params : Vec::new(),
+            derivatives : Vec::new(),
+            odeparam : ODEParam { time : 0.0, tend : f64::NAN, step : f64::NAN, ratio_step_output : 1 },
+            odesolver : ODESolver::RK4,
+            tolerance : Tolerance::default(),
+        };
+        let mut derivatives : Vec<(String, Expr)> = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            model.parse_line(line, &mut derivatives)
+                .map_err(|erro| invalid(format!("Line {}: {}", number + 1, erro)))?;
+        }
+
+        for (state, _) in &derivatives {
+            if !model.states.contains(state) {
+                return Err(invalid(format!("Derivative of '{}', which is not a state", state)));
+            }
+        }
+        for state in &model.states {
+            let mut expressions = derivatives.iter().filter(|(name, _)| name == state);
+            match (expressions.next(), expressions.next()) {
+                (Some((_, expr)), None) => model.derivatives.push(expr.clone()),
+                (None, _) => return Err(invalid(format!("No derivative for the state '{}'", state))),
+                (Some(_), Some(_)) => return Err(invalid(format!("Two derivatives for the state '{}'", state))),
+            }
+        }
+        for expr in &model.derivatives {
+            for variable in expr.variables() {
+                if variable != "t" && !model.states.contains(&variable) && !model.params.iter().any(|(param, _)| *param == variable) {
+                    return Err(invalid(format!("Unknown variable '{}' in the derivatives", variable)));
+                }
+            }
+        }
+        if model.odeparam.tend.is_nan() || model.odeparam.step.is_nan() {
+            return Err(invalid("The settings 'tend' and 'step' are required".to_string()));
+        }
+        model.odesolver = model.odesolver.with_tolerance(model.tolerance);
+        model.validate()?;
+
+        Ok(model)
+    }
+
+    /// Checks the settings of the simulation: `time`, `tend` and `step` finite, `step` positive, `tend` not before `time`, `ratio_step_output` at least 1, `rtol` and `atol` finite, not negative and not both zero, and the values of the parameters and initial conditions finite.
+    pub fn validate (&self) -> Result<(), Box<dyn Error>> {
+        let odeparam = &self.odeparam;
+        for (name, value) in [("time", odeparam.time), ("tend", odeparam.tend), ("step", odeparam.step)] {
+            if !value.is_finite() {
+                return Err(invalid(format!("The setting '{}' is not finite", name)));
+            }
+        }
+        if odeparam.step <= 0.0 {
+            return Err(invalid(format!("The setting 'step' must be positive, found {}", odeparam.step)));
+        }
+        if odeparam.tend < odeparam.time {
+            return Err(invalid(format!("The setting 'tend' ({}) is before 'time' ({})", odeparam.tend, odeparam.time)));
+        }
+        if odeparam.ratio_step_output == 0 {
+            return Err(invalid("The setting 'ratio_step_output' must be at least 1".to_string()));
+        }
+        for (name, value) in [("rtol", self.tolerance.rtol), ("atol", self.tolerance.atol)] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(invalid(format!("The setting '{}' must be finite and not negative, found {}", name, value)));
+            }
+        }
+        if self.tolerance.rtol == 0.0 && self.tolerance.atol == 0.0 {
+            return Err(invalid("The settings 'rtol' and 'atol' can not be both zero".to_string()));
+        }
+
+        let values = self.params.iter().map(|(name, value)| (name, *value))
+            .chain(self.states.iter().zip(self.initial_state.iter().copied()));
+        for (name, value) in values {
+            if !value.is_finite() {
+                return Err(invalid(format!("The value of '{}' is not finite", name)));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_line (&mut self, line : &str, derivatives : &mut Vec<(String, Expr)>) -> Result<(), Box<dyn Error>> {
+        let (left, right) = line.split_once('=').ok_or_else(|| invalid("Expected '='".to_string()))?;
+        let left = left.trim();
+        let right = right.trim();
+
+        if let Some(name) = left.strip_prefix("param ") {
+            let name = name.trim();
+            check_name(name, self)?;
+            let value = self.constant(right)?;
+            self.params.push((name.to_string(), value));
+        } else if let Some(name) = left.strip_prefix("state ") {
+            let name = name.trim();
+            check_name(name, self)?;
+            let value = self.constant(right)?;
+            self.states.push(name.to_string());
+            self.initial_state.push(value);
+        } else if let Some(name) = left.strip_suffix('\'') {
+            derivatives.push((name.trim().to_string(), Expr::parse(right)?));
+        } else {
+            match left {
+                "time" => self.odeparam.time = self.constant(right)?,
+                "tend" => self.odeparam.tend = self.constant(right)?,
+                "step" => self.odeparam.step = self.constant(right)?,
+                "ratio_step_output" => self.odeparam.ratio_step_output = right.parse()
+                    .map_err(|_| invalid(format!("Invalid ratio_step_output '{}'", right)))?,
+                "solver" => self.odesolver = right.parse()?,
+                "rtol" => self.tolerance.rtol = self.constant(right)?,
+                "atol" => self.tolerance.atol = self.constant(right)?,
+                _ => return Err(invalid(format!("Unknown setting '{}'", left))),
+            }
+        }
+        Ok(())
+    }
+
+    // Value of an expression of the parameters declared so far
+    fn constant (&self, text : &str) -> Result<f64, Box<dyn Error>> {
+        Expr::parse(text)?.eval(&|name| self.param(name))
+    }
+
+    /// Reads a model from a given filename/filepath.
+    pub fn from_file (file_as_string : String) -> Result<Model, Box<dyn Error>> {
+        let text = match std::fs::read_to_string(&file_as_string) {
+            Ok(text) => text,
+            Err(erro) => {
+                eprintln!("Not possible to read the model file {}. Error: {}", file_as_string, erro);
+                return Err(Box::new(erro) as Box<dyn Error>);},
+        };
+        Model::parse(&text)
+    }
+
+    /// Value of the parameter with the given name.
+    pub fn param (&self, name : &str) -> Option<f64> {
+        self.params.iter().find(|(param, _)| param == name).map(|(_, value)| *value)
+    }
+
+    /// Changes the value of a parameter or the initial condition of a state. The value has to be finite.
+    pub fn set (&mut self, name : &str, value : f64) -> Result<(), Box<dyn Error>> {
+        if !value.is_finite() {
+            return Err(invalid(format!("The value of '{}' is not finite", name)));
+        }
+        if let Some((_, param)) = self.params.iter_mut().find(|(param, _)| param == name) {
+            *param = value;
+        } else if let Some(index) = self.states.iter().position(|state| state == name) {
+            self.initial_state[index] = value;
+        } else {
+            return Err(invalid(format!("No parameter or state named '{}'", name)));
+        }
+        Ok(())
+    }
+
+    /// Names of the columns of the output: the time `t` and the states.
+    pub fn column_names (&self) -> Vec<String> {
+        let mut names = vec!("t".to_string());
+        names.extend(self.states.iter().cloned());
+        names
+    }
+
+    /// Compiled system of the model, which also gives the exact Jacobian, see `expression::ExprSystem`.
+    pub fn system (&self) -> Result<ExprSystem, Box<dyn Error>> {
+        let states : Vec<&str> = self.states.iter().map(|state| state.as_str()).collect();
+        let params : Vec<(&str, f64)> = self.params.iter().map(|(name, value)| (name.as_str(), *value)).collect();
+        ExprSystem::new(&self.derivatives, &states, &params)
+    }
+
+    /// System function of the model, as used by `solver_vector::solve_ode`. Returns the error of `system` if the model can not be compiled (the fields were changed after `parse` with unknown variables).
+    pub fn system_function (&self) -> Result<impl FnMut (f64, &State) -> DState + Clone, Box<dyn Error>> {
+        Ok(self.system()?.system_function())
+    }
+
+    /// Solves the model with `solver_vector::solve_ode_with_jacobian`, so the implicit solvers use the exact Jacobian of the expressions, and the adaptive ones the `tolerance`. Returns the error of `system` if the model can not be compiled.
+    pub fn solve (&self) -> Result<SolveResult<State>, Box<dyn Error>> {
+        let system = self.system()?;
+        Ok(solver_vector::solve_ode_with_jacobian(system.system_function(), system.jacobian_function(), self.odeparam.clone(), self.initial_state.clone(), self.odesolver.with_tolerance(self.tolerance)))
+    }
+}
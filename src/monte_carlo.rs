@@ -0,0 +1,273 @@
+pub use super::*;
+pub use crate::solver_vector_trait::{ODESystem, State, DState, Data};
+pub use crate::solver_vector::data_to_file_labelled;
+pub use crate::result::Termination;
+use crate::ensemble::{run_parallel, quantile};
+
+/// Seeded pseudo random number generator (xoshiro256**).
+///
+/// The same seed always gives the same sequence of numbers, in any platform.
+#[derive(Clone,Debug)]
+pub struct Rng {
+    s : [u64;4],
+}
+
+fn splitmix64 (x : &mut u64) -> u64 {
+    *x = x.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *x;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+impl Rng {
+
+    /// Creates a generator from a seed.
+    pub fn new (seed : u64) -> Rng {
+        let mut x = seed;
+        let s = [splitmix64(&mut x), splitmix64(&mut x), splitmix64(&mut x), splitmix64(&mut x)];
+        Rng { s }
+    }
+
+    /// Next random `u64`.
+    pub fn next_u64 (&mut self) -> u64 {
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.s[1] << 17;
+
+        self.s[2] ^= self.s[0];
+        self.s[3] ^= self.s[1];
+        self.s[1] ^= self.s[2];
+        self.s[0] ^= self.s[3];
+        self.s[2] ^= t;
+        self.s[3] = self.s[3].rotate_left(45);
+
+        result
+    }
+
+    /// Random number uniformly distributed in [0, 1).
+    pub fn uniform (&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0/((1u64 << 53) as f64))
+    }
+
+    /// Random number with standard normal distribution (Box-Muller transform).
+    pub fn standard_normal (&mut self) -> f64 {
+        let u1 = 1.0 - self.uniform();
+        let u2 = self.uniform();
+        (-2.0*u1.ln()).sqrt()*(2.0*std::f64::consts::PI*u2).cos()
+    }
+}
+
+
+/// Enum Distribution
+///
+/// Distributions of the uncertain parameters and initial conditions. `LogNormal` is parametrized by the mean `mu` and standard deviation `sigma` of the logarithm of the variable.
+#[derive(Clone,Copy,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Distribution {
+    Uniform { low : f64, high : f64 },
+    Normal { mean : f64, std_dev : f64 },
+    LogNormal { mu : f64, sigma : f64 },
+}
+
+impl Distribution {
+
+    /// Draws a sample of the distribution.
+    pub fn sample (&self, rng : &mut Rng) -> f64 {
+        match *self {
+            Distribution::Uniform { low, high } => low + (high - low)*rng.uniform(),
+            Distribution::Normal { mean, std_dev } => mean + std_dev*rng.standard_normal(),
+            Distribution::LogNormal { mu, sigma } => (mu + sigma*rng.standard_normal()).exp(),
+        }
+    }
+}
+
+
+/// Struct MonteCarloParam
+///
+/// Parameters of the Monte Carlo propagation: number of `samples`, the `seed` of the random number generator, the state `components` (indexes in the state, starting at 0) whose statistics are computed, the `percentiles` (values between 0 and 1), the number of `histogram_bins` and the number of worker `threads` (if 0 the available parallelism of the machine is used).
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MonteCarloParam {
+    pub samples : usize,
+    pub seed : u64,
+    pub components : Vec<usize>,
+    pub percentiles : Vec<f64>,
+    pub histogram_bins : usize,
+    pub threads : usize,
+}
+
+/// Struct MonteCarloStatistics
+///
+/// Time resolved statistics of the chosen components. `mean`, `variance` and each of the `percentiles` have the time in the first column and one column for each component, in the order given in `MonteCarloParam.components`. There is one histogram for each component, with rows `[time, min, max, count_1, ..., count_bins]` where the bins divide `[min, max]` evenly. `samples` has the sampled values of each sample. `failed` has the index and the `Termination` of each sample whose integration did not reach `tend`, which are left out of the statistics.
+#[derive(Clone,Debug)]
+pub struct MonteCarloStatistics {
+    pub components : Vec<usize>,
+    pub mean : Data,
+    pub variance : Data,
+    pub percentiles : Vec<(f64, Data)>,
+    pub histograms : Vec<Data>,
+    pub samples : Vec<Vec<f64>>,
+    pub failed : Vec<(usize, Termination)>,
+}
+
+
+// Each sample has its own stream of random numbers, so the samples do not depend on the number of threads
+fn draw_samples (distributions : &[Distribution], param : &MonteCarloParam) -> Vec<Vec<f64>> {
+    let mut seeder = param.seed;
+    (0..param.samples).map(|_| {
+        let mut rng = Rng::new(splitmix64(&mut seeder));
+        distributions.iter().map(|d| d.sample(&mut rng)).collect()
+    }).collect()
+}
+
+
+/// Monte Carlo propagation of uncertainties using systems which implement `ODESystem`. Returns the `MonteCarloStatistics` of the chosen components, over the samples which are not `failed`.
+///
+///# Inputs
+///
+///`distributions : &[Distribution]`. Distribution of each uncertain value (parameters and initial conditions).
+///
+///`build : Fn (&[f64]) -> Sist`. Builds the system of a sample given the sampled values, in the same order as `distributions`.
+///
+///`odeparam : ODEParam`. An value of ODEParam.
+///
+///`odesolver : ODESolver`. A choice of an ODE solver.
+///
+///`param : MonteCarloParam`. A value of MonteCarloParam.
+pub fn monte_carlo<Sist, Build> (distributions : &[Distribution], build : Build, odeparam : ODEParam, odesolver : ODESolver, param : MonteCarloParam) -> MonteCarloStatistics
+where Sist : ODESystem + Clone,
+      Build : Fn (&[f64]) -> Sist + Sync,
+{
+    let samples = draw_samples(distributions, &param);
+    let trajectories = run_parallel(param.samples, param.threads, |sample| {
+        let mut sist = build(&samples[sample]);
+        let result = solver_vector_trait::solve_ode_sist_mut(&mut sist, odeparam.clone(), odesolver);
+        (result.data(), result.stats.termination)
+    });
+
+    statistics(&trajectories, samples, &param)
+}
+
+
+/// Similar to `monte_carlo` but using closures as in `solver_vector`.
+///
+/// `build` receives the sampled values and returns the system function (a closure which can capture the sampled parameters) and the initial state of the sample.
+pub fn monte_carlo_vector<SysFunc, Build> (distributions : &[Distribution], build : Build, odeparam : ODEParam, odesolver : ODESolver, param : MonteCarloParam) -> MonteCarloStatistics
+where SysFunc : FnMut (f64, &State) -> DState,
+      Build : Fn (&[f64]) -> (SysFunc, State) + Sync,
+{
+    let samples = draw_samples(distributions, &param);
+    let trajectories = run_parallel(param.samples, param.threads, |sample| {
+        let (system_function, state) = build(&samples[sample]);
+        let result = solver_vector::solve_ode(system_function, odeparam.clone(), state, odesolver);
+        (result.data(), result.stats.termination)
+    });
+
+    statistics(&trajectories, samples, &param)
+}
+
+
+fn statistics (trajectories : &[(Data, Termination)], samples : Vec<Vec<f64>>, param : &MonteCarloParam) -> MonteCarloStatistics {
+    let mut mean : Data = Vec::new();
+    let mut variance : Data = Vec::new();
+    let mut percentiles : Vec<(f64, Data)> = param.percentiles.iter().map(|p| (*p, Vec::new())).collect();
+    let mut histograms : Vec<Data> = param.components.iter().map(|_| Vec::new()).collect();
+
+    let failed : Vec<(usize, Termination)> = trajectories.iter().enumerate()
+        .filter(|(_, (_, termination))| *termination != Termination::ReachedEnd)
+        .map(|(sample, (_, termination))| (sample, termination.clone())).collect();
+    let trajectories : Vec<&Data> = trajectories.iter().filter(|(_, termination)| *termination == Termination::ReachedEnd).map(|(data, _)| data).collect();
+    let rows = trajectories.iter().map(|d| d.len()).min().unwrap_or(0);
+    let n = trajectories.len() as f64;
+
+    for row in 0..rows {
+        let time = trajectories[0][row][0];
+        let mut mean_datum = vec!(time);
+        let mut variance_datum = vec!(time);
+        let mut percentile_datums : Vec<Vec<f64>> = param.percentiles.iter().map(|_| vec!(time)).collect();
+
+        for (component, histogram) in param.components.iter().zip(histograms.iter_mut()) {
+            // the first column of the data is the time
+            let mut values : Vec<f64> = trajectories.iter().map(|d| d[row][component+1]).collect();
+
+            let m = values.iter().sum::<f64>()/n;
+            let v = if n > 1.0 { values.iter().map(|x| (x - m)*(x - m)).sum::<f64>()/(n - 1.0) } else { 0.0 };
+            mean_datum.push(m);
+            variance_datum.push(v);
+
+            values.sort_by(|a,b| a.total_cmp(b));
+            for (datum, p) in percentile_datums.iter_mut().zip(&param.percentiles) {
+                datum.push(quantile(&values, *p));
+            }
+
+            let min = values[0];
+            let max = values[values.len()-1];
+            let mut counts = vec!(0.0; param.histogram_bins);
+            if param.histogram_bins > 0 {
+                let width = (max - min)/(param.histogram_bins as f64);
+                for x in &values {
+                    let bin = if width > 0.0 { ((x - min)/width) as usize } else { 0 };
+                    counts[bin.min(param.histogram_bins - 1)] += 1.0;
+                }
+            }
+            let mut histogram_datum = vec!(time, min, max);
+            histogram_datum.append(&mut counts);
+            histogram.push(histogram_datum);
+        }
+
+        mean.push(mean_datum);
+        variance.push(variance_datum);
+        for ((_, data), datum) in percentiles.iter_mut().zip(percentile_datums) {
+            data.push(datum);
+        }
+    }
+
+    MonteCarloStatistics { components : param.components.clone(), mean, variance, percentiles, histograms, samples, failed }
+}
+
+
+impl MonteCarloStatistics {
+
+    /// Summary table with one row per output time: `[time, mean, variance, percentiles...]` for each component, together with the labels of its columns.
+    ///
+    /// `names` are the names of all the states of the system (indexed by the components), the components without a name (all of them if `None`) are named `x0`, `x1`, ... The percentiles are labelled by their percent, as `p5(x0)` or `p2.5(x0)`.
+    pub fn summary (&self, names : Option<&[&str]>) -> (Data, Vec<String>) {
+        let name = |component : usize| match names.and_then(|names| names.get(component)) {
+            Some(name) => name.to_string(),
+            None => format!("x{}", component),
+        };
+        // rounded to remove the error of the product, as in 0.05*100 = 5.000000000000001
+        let percent = |p : f64| (p*100.0*1.0e9).round()/1.0e9;
+
+        let mut labels = vec!("t".to_string());
+        for component in &self.components {
+            labels.push(format!("mean({})", name(*component)));
+            labels.push(format!("var({})", name(*component)));
+            for (p, _) in &self.percentiles {
+                labels.push(format!("p{}({})", percent(*p), name(*component)));
+            }
+        }
+
+        let data = self.mean.iter().enumerate().map(|(row, mean_datum)| {
+            let mut datum = vec!(mean_datum[0]);
+            for column in 1 .. mean_datum.len() {
+                datum.push(mean_datum[column]);
+                datum.push(self.variance[row][column]);
+                for (_, percentile) in &self.percentiles {
+                    datum.push(percentile[row][column]);
+                }
+            }
+            datum
+        }).collect();
+
+        (data, labels)
+    }
+}
+
+
+/// Saves the `summary` of the statistics to a given filename/filepath, with the labels of the columns as header.
+pub fn statistics_to_file (statistics : &MonteCarloStatistics, file_as_string : String, names : Option<&[&str]>) -> Result<(), Box<dyn Error>> {
+    let (data, labels) = statistics.summary(names);
+    let labels : Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+    data_to_file_labelled(&data, file_as_string, &labels)
+}
@@ -0,0 +1,346 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+/// 2D vector of data, as `solver_vector::Data`
+pub type Data = Vec<Vec<f64>>;
+/// Vector of rows of size M, as `solver_trait::Data<M>`
+pub type DataArray<const M: usize> = Vec<[f64;M]>;
+
+const MAGIC : &[u8] = b"\x93NUMPY";
+// The capacity reserved for the rows read from a header, which can not be trusted: the vector grows from there
+const MAX_ROWS_CAPACITY : usize = 1 << 16;
+
+
+fn invalid (message : String) -> Box<dyn Error> {
+    Box::new(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+
+fn width<Row : AsRef<[f64]>> (data : &[Row]) -> Result<usize, Box<dyn Error>> {
+    let width = data.first().map_or(0, |row| row.as_ref().len());
+    if data.iter().any(|row| row.as_ref().len() != width) {
+        return Err(Box::new(io::Error::new(io::ErrorKind::InvalidInput, "Rows of the data with different sizes")));
+    }
+    Ok(width)
+}
+
+
+// Reads exactly n bytes, growing the buffer with what is actually read instead of allocating n bytes up front
+fn read_bytes<R : Read> (reader : &mut R, n : usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    reader.take(n as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < n {
+        return Err(invalid("Truncated .npy file".to_string()));
+    }
+    Ok(bytes)
+}
+
+
+/// Writes the data in the NumPy `.npy` format (version 1.0, little endian `f8`, C order) to any writer.
+///
+/// Works with the `Data` of all the modules: any collection of rows that can be seen as `&[f64]` (`Vec<f64>` or `[f64;M]`). All the rows must have the same size, the array has shape `(rows, columns)`.
+pub fn write_npy<W : Write, Row : AsRef<[f64]>> (writer : &mut W, data : &[Row]) -> Result<(), Box<dyn Error>> {
+    let columns = width(data)?;
+
+    writer.write_all(&npy_header(data.len(), columns, 0))?;
+    for row in data {
+        for value in row.as_ref() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+
+// Magic string, version, and header of a .npy file with a 2D array of `f8`. The total size is a multiple of 64 bytes, at least `min_len`, so the header can be rewritten in place with a larger number of rows.
+pub(crate) fn npy_header (rows : usize, columns : usize, min_len : usize) -> Vec<u8> {
+    let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}", rows, columns);
+    // magic, version and header length take 10 bytes
+    let len = usize::max(10 + header.len() + 1, min_len);
+    let total = len.div_ceil(64)*64;
+    header.push_str(&" ".repeat(total - 10 - header.len() - 1));
+    header.push('\n');
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&[1, 0]);
+    bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    bytes
+}
+
+
+// Value of a key in the header dictionary of a .npy file
+fn header_value<'a> (header : &'a str, key : &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}'", key))? + key.len() + 2;
+    let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+    let end = if rest.starts_with('(') {
+        rest.find(')')? + 1
+    } else {
+        rest.find([',', '}'])?
+    };
+    Some(rest[..end].trim())
+}
+
+
+/// Reads data in the NumPy `.npy` format from any reader.
+///
+/// Only arrays of little endian `f8` in C order are supported. 2D arrays are read by rows and 1D arrays as a single column.
+pub fn read_npy<R : Read> (reader : &mut R) -> Result<Data, Box<dyn Error>> {
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic[..6] != MAGIC {
+        return Err(invalid("Not a .npy file".to_string()));
+    }
+
+    let header_len = if magic[6] == 1 {
+        let mut len = [0u8; 2];
+        reader.read_exact(&mut len)?;
+        u16::from_le_bytes(len) as usize
+    } else {
+        let mut len = [0u8; 4];
+        reader.read_exact(&mut len)?;
+        u32::from_le_bytes(len) as usize
+    };
+    let header = read_bytes(reader, header_len)?;
+    let header = String::from_utf8_lossy(&header).to_string();
+
+    let descr = header_value(&header, "descr").unwrap_or("");
+    if descr != "'<f8'" {
+        return Err(invalid(format!("Unsupported .npy data type {}, only '<f8' is supported", descr)));
+    }
+    if header_value(&header, "fortran_order") != Some("False") {
+        return Err(invalid("Unsupported .npy array in Fortran order".to_string()));
+    }
+
+    let shape = header_value(&header, "shape").unwrap_or("()");
+    let shape : Vec<usize> = shape.trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| s.trim().parse::<usize>())
+        .collect::<Result<_,_>>()?;
+    let (rows, columns) = match shape[..] {
+        [rows] => (rows, 1),
+        [rows, columns] => (rows, columns),
+        _ => return Err(invalid(format!("Unsupported .npy array with shape {:?}", shape))),
+    };
+
+    let row_len = columns.checked_mul(8).ok_or_else(|| invalid(format!("Unsupported .npy array with shape {:?}", shape)))?;
+
+    let mut data : Data = Vec::with_capacity(rows.min(MAX_ROWS_CAPACITY));
+    for _i in 0..rows {
+        let bytes = read_bytes(reader, row_len)?;
+        data.push(bytes.chunks_exact(8)
+                  .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                  .collect());
+    }
+
+    Ok(data)
+}
+
+
+/// Saves the data to a given filename/filepath in the NumPy `.npy` format, so it can be read with `np.load`.
+pub fn data_to_npy<Row : AsRef<[f64]>> (data : &[Row], file_as_string : String) -> Result<(), Box<dyn Error>> {
+    let file = match File::create(file_as_string) {
+        Ok(f) => f,
+        Err(erro) => {
+            eprintln!("Not possible to create/find the file for exporting the data. Error: {}", erro);
+            return Err(Box::new(erro) as Box<dyn Error>);},
+    };
+
+    write_npy(&mut BufWriter::new(file), data)
+}
+
+
+/// Reads a `.npy` file as a `solver_vector::Data`.
+pub fn npy_to_data (file_as_string : String) -> Result<Data, Box<dyn Error>> {
+    let file = File::open(file_as_string)?;
+    read_npy(&mut BufReader::new(file))
+}
+
+
+/// Reads a `.npy` file as a `solver_trait::Data<M>`. The array must have `M` columns.
+pub fn npy_to_data_array<const M : usize> (file_as_string : String) -> Result<DataArray<M>, Box<dyn Error>> {
+    data_to_array(npy_to_data(file_as_string)?)
+}
+
+
+fn data_to_array<const M : usize> (data : Data) -> Result<DataArray<M>, Box<dyn Error>> {
+    data.into_iter().map(|row| {
+        let columns = row.len();
+        row.try_into().map_err(|_| invalid(format!("Array with {} columns read as rows of size {}", columns, M)))
+    }).collect()
+}
+
+
+// CRC-32 (IEEE) used by the zip format
+fn crc32_update (crc : u32, bytes : &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _k in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// Writer which only computes the size and CRC-32 of what is written
+struct Checksum {
+    crc : u32,
+    size : u64,
+}
+
+impl Write for Checksum {
+    fn write (&mut self, buf : &[u8]) -> io::Result<usize> {
+        self.crc = crc32_update(self.crc, buf);
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush (&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+/// Writes several arrays in the NumPy `.npz` format (an uncompressed zip archive of `.npy` files, as written by `np.savez`) to any writer.
+///
+/// Each array is given with its name, which is the key used to get it from the object returned by `np.load`. Each array is limited to 4 GiB.
+pub fn write_npz<W : Write, Row : AsRef<[f64]>> (writer : &mut W, arrays : &[(&str, &[Row])]) -> Result<(), Box<dyn Error>> {
+    let mut central_directory : Vec<u8> = Vec::new();
+    let mut offset : u64 = 0;
+
+    for (name, data) in arrays {
+        // first pass only to get the size and checksum, the local header comes before the data
+        let mut checksum = Checksum { crc : 0, size : 0 };
+        write_npy(&mut checksum, data)?;
+        if checksum.size > u32::MAX as u64 || offset > u32::MAX as u64 {
+            return Err(Box::new(io::Error::new(io::ErrorKind::InvalidInput, "Array too large for the .npz format")));
+        }
+        let file_name = format!("{}.npy", name);
+
+        let mut header : Vec<u8> = Vec::new();
+        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        header.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        header.extend_from_slice(&0u16.to_le_bytes()); // flags
+        header.extend_from_slice(&0u16.to_le_bytes()); // stored, no compression
+        header.extend_from_slice(&0u16.to_le_bytes()); // time
+        header.extend_from_slice(&0x21u16.to_le_bytes()); // date, 1980-01-01
+        header.extend_from_slice(&checksum.crc.to_le_bytes());
+        header.extend_from_slice(&(checksum.size as u32).to_le_bytes());
+        header.extend_from_slice(&(checksum.size as u32).to_le_bytes());
+        header.extend_from_slice(&(file_name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // extra field
+        header.extend_from_slice(file_name.as_bytes());
+        writer.write_all(&header)?;
+        write_npy(writer, data)?;
+
+        central_directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central_directory.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central_directory.extend_from_slice(&header[4..30]);
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // comment
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // disk
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        central_directory.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        central_directory.extend_from_slice(&(offset as u32).to_le_bytes());
+        central_directory.extend_from_slice(file_name.as_bytes());
+
+        offset += header.len() as u64 + checksum.size;
+    }
+
+    if offset > u32::MAX as u64 {
+        return Err(Box::new(io::Error::new(io::ErrorKind::InvalidInput, "Arrays too large for the .npz format")));
+    }
+    writer.write_all(&central_directory)?;
+    writer.write_all(&0x06054b50u32.to_le_bytes())?;
+    writer.write_all(&0u16.to_le_bytes())?;
+    writer.write_all(&0u16.to_le_bytes())?;
+    writer.write_all(&(arrays.len() as u16).to_le_bytes())?;
+    writer.write_all(&(arrays.len() as u16).to_le_bytes())?;
+    writer.write_all(&(central_directory.len() as u32).to_le_bytes())?;
+    writer.write_all(&(offset as u32).to_le_bytes())?;
+    writer.write_all(&0u16.to_le_bytes())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+
+/// Saves several arrays to a given filename/filepath in the NumPy `.npz` format, see `write_npz`.
+pub fn data_to_npz<Row : AsRef<[f64]>> (arrays : &[(&str, &[Row])], file_as_string : String) -> Result<(), Box<dyn Error>> {
+    let file = match File::create(file_as_string) {
+        Ok(f) => f,
+        Err(erro) => {
+            eprintln!("Not possible to create/find the file for exporting the data. Error: {}", erro);
+            return Err(Box::new(erro) as Box<dyn Error>);},
+    };
+
+    write_npz(&mut BufWriter::new(file), arrays)
+}
+
+
+// Slice of n bytes at position i of a .npz file, or an error if it goes past the end of the file
+fn bytes_at (bytes : &[u8], i : usize, n : usize) -> Result<&[u8], Box<dyn Error>> {
+    i.checked_add(n)
+        .and_then(|end| bytes.get(i .. end))
+        .ok_or_else(|| invalid("Truncated .npz file".to_string()))
+}
+
+fn u16_at (bytes : &[u8], i : usize) -> Result<usize, Box<dyn Error>> {
+    Ok(u16::from_le_bytes(bytes_at(bytes, i, 2)?.try_into()?) as usize)
+}
+
+fn u32_at (bytes : &[u8], i : usize) -> Result<usize, Box<dyn Error>> {
+    Ok(u32::from_le_bytes(bytes_at(bytes, i, 4)?.try_into()?) as usize)
+}
+
+
+/// Reads the arrays of a `.npz` file, returns a vector with the name and the `solver_vector::Data` of each array.
+///
+/// Only uncompressed archives are supported (written by `np.savez` or `data_to_npz`, not by `np.savez_compressed`).
+pub fn npz_to_data (file_as_string : String) -> Result<Vec<(String, Data)>, Box<dyn Error>> {
+    let mut bytes = Vec::new();
+    File::open(file_as_string)?.read_to_end(&mut bytes)?;
+
+    let end = (0 .. bytes.len().saturating_sub(21)).rev()
+        .find(|i| u32_at(&bytes, *i).is_ok_and(|signature| signature == 0x06054b50))
+        .ok_or_else(|| invalid("Not a .npz file".to_string()))?;
+    let entries = u16_at(&bytes, end + 10)?;
+    let mut position = u32_at(&bytes, end + 16)?;
+
+    let mut arrays = Vec::with_capacity(entries);
+    for _i in 0..entries {
+        if u32_at(&bytes, position)? != 0x02014b50 {
+            return Err(invalid("Corrupted .npz file".to_string()));
+        }
+        let compression = u16_at(&bytes, position + 10)?;
+        let size = u32_at(&bytes, position + 20)?;
+        let name_len = u16_at(&bytes, position + 28)?;
+        let extra_len = u16_at(&bytes, position + 30)?;
+        let comment_len = u16_at(&bytes, position + 32)?;
+        let local = u32_at(&bytes, position + 42)?;
+        let name = String::from_utf8_lossy(bytes_at(&bytes, position + 46, name_len)?).to_string();
+        if compression != 0 {
+            return Err(invalid(format!("Compressed array {} in .npz file, only uncompressed files are supported", name)));
+        }
+
+        let start = local + 30 + u16_at(&bytes, local + 26)? + u16_at(&bytes, local + 28)?;
+        let data = read_npy(&mut bytes_at(&bytes, start, size)?)?;
+        arrays.push((name.trim_end_matches(".npy").to_string(), data));
+
+        position += 46 + name_len + extra_len + comment_len;
+    }
+
+    Ok(arrays)
+}
+
+
+/// Reads the arrays of a `.npz` file as `solver_trait::Data<M>`, see `npz_to_data`. The arrays must have `M` columns.
+pub fn npz_to_data_array<const M : usize> (file_as_string : String) -> Result<Vec<(String, DataArray<M>)>, Box<dyn Error>> {
+    npz_to_data(file_as_string)?.into_iter()
+        .map(|(name, data)| Ok((name, data_to_array(data)?)))
+        .collect()
+}
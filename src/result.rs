@@ -0,0 +1,258 @@
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+use crate::ODEParam;
+use crate::sink::OutputSink;
+
+/// Enum Termination
+///
+/// Why the integration stopped: it `ReachedEnd` (`ODEParam.tend`), it stopped at a terminal `Event` of a hybrid system, or an `Error` happened (a state which is not finite, a sink which failed...), with its description.
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Termination {
+    ReachedEnd,
+    Event,
+    Error(String),
+}
+
+/// Struct SolveStats
+///
+/// Statistics of an integration: the number of evaluations of the system (`fevals`), of `accepted_steps` and `rejected_steps`, the `wall_time` spent and the `termination` reason.
+#[derive(Clone,Debug,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SolveStats {
+    pub fevals : u64,
+    pub accepted_steps : u64,
+    pub rejected_steps : u64,
+    pub wall_time : Duration,
+    pub termination : Termination,
+}
+
+impl Default for SolveStats {
+    fn default () -> Self {
+        SolveStats { fevals : 0, accepted_steps : 0, rejected_steps : 0, wall_time : Duration::ZERO, termination : Termination::ReachedEnd }
+    }
+}
+
+
+/// Struct SolveResult
+///
+/// Output of the solvers: the output `times` and the `states` at those times (without the time column, so `states[i][j]` is the component `j` at `times[i]`), the final `system` (for `solver_vector`, which has no system, the final state), the final `odeparam` and the `stats` of the integration.
+///
+/// The rows of `solver_trait` are arrays `[f64;N]`, the rows of the other modules are vectors.
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SolveResult<Sist, Row = Vec<f64>> {
+    pub times : Vec<f64>,
+    pub states : Vec<Row>,
+    pub system : Sist,
+    pub odeparam : ODEParam,
+    pub stats : SolveStats,
+}
+
+impl<Sist, Row : AsRef<[f64]>> SolveResult<Sist, Row> {
+
+    /// Number of output rows.
+    pub fn len (&self) -> usize {
+        self.times.len()
+    }
+
+    /// True if there are no output rows.
+    pub fn is_empty (&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// Values of the component of index `component` of the state (starting at 0) at each output time.
+    pub fn component (&self, component : usize) -> Vec<f64> {
+        self.states.iter().map(|state| state.as_ref()[component]).collect()
+    }
+
+    /// Time series of the component of index `component` of the state, as pairs `(time, value)`.
+    pub fn time_series (&self, component : usize) -> Vec<(f64, f64)> {
+        self.times.iter().zip(&self.states).map(|(time, state)| (*time, state.as_ref()[component])).collect()
+    }
+
+    /// State at the last output time.
+    pub fn final_state (&self) -> Option<&Row> {
+        self.states.last()
+    }
+
+    /// Rows `[time, state...]`, as the `Data` used by `data_to_file`, `csv` and `npy`.
+    pub fn data (&self) -> Vec<Vec<f64>> {
+        self.times.iter().zip(&self.states).map(|(time, state)| {
+            let mut datum = Vec::with_capacity(state.as_ref().len() + 1);
+            datum.push(*time);
+            datum.extend_from_slice(state.as_ref());
+            datum
+        }).collect()
+    }
+}
+
+
+/// Struct FlatData
+///
+/// Output rows `[time, state...]` stored in one contiguous buffer: the row `i` is `values[i*stride .. (i+1)*stride]`, so the `stride` is the size of the state plus one. Adding a row does not allocate a new vector, as with `Data`.
+///
+/// The rows can be exported by `csv` and `npy` as slices, e.g. `csv::data_to_csv(&data.rows().collect::<Vec<_>>(), ...)`.
+#[derive(Clone,Debug,Default,PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlatData {
+    pub stride : usize,
+    pub values : Vec<f64>,
+}
+
+impl FlatData {
+
+    /// Creates an empty buffer for rows of size `stride`. If `stride` is 0 it is taken from the first row.
+    pub fn new (stride : usize) -> FlatData {
+        FlatData { stride, values : Vec::new() }
+    }
+
+    /// Creates an empty buffer with room for `rows` rows of size `stride`.
+    pub fn with_capacity (stride : usize, rows : usize) -> FlatData {
+        FlatData { stride, values : Vec::with_capacity(stride*rows) }
+    }
+
+    /// Number of rows.
+    pub fn len (&self) -> usize {
+        self.values.len().checked_div(self.stride).unwrap_or(0)
+    }
+
+    /// True if there are no rows.
+    pub fn is_empty (&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Row `[time, state...]` of index `row`.
+    pub fn row (&self, row : usize) -> &[f64] {
+        &self.values[row*self.stride .. (row + 1)*self.stride]
+    }
+
+    /// Time of the row of index `row`.
+    pub fn time (&self, row : usize) -> f64 {
+        self.values[row*self.stride]
+    }
+
+    /// State of the row of index `row`, without the time.
+    pub fn state (&self, row : usize) -> &[f64] {
+        &self.row(row)[1..]
+    }
+
+    /// Iterator over the rows `[time, state...]`.
+    pub fn rows (&self) -> std::slice::ChunksExact<'_, f64> {
+        self.values.chunks_exact(self.stride.max(1))
+    }
+
+    /// Output times.
+    pub fn times (&self) -> Vec<f64> {
+        self.rows().map(|row| row[0]).collect()
+    }
+
+    /// Values of the component of index `component` of the state (starting at 0) at each output time.
+    pub fn component (&self, component : usize) -> Vec<f64> {
+        self.rows().map(|row| row[component + 1]).collect()
+    }
+
+    /// Time series of the component of index `component` of the state, as pairs `(time, value)`.
+    pub fn time_series (&self, component : usize) -> Vec<(f64, f64)> {
+        self.rows().map(|row| (row[0], row[component + 1])).collect()
+    }
+
+    /// Copy of the rows as `Data`.
+    pub fn to_data (&self) -> Vec<Vec<f64>> {
+        self.rows().map(|row| row.to_vec()).collect()
+    }
+}
+
+impl OutputSink for FlatData {
+    fn push (&mut self, time : f64, state : &[f64]) -> Result<(), Box<dyn Error>> {
+        if self.stride == 0 && self.values.is_empty() {
+            self.stride = state.len() + 1;
+        }
+        if state.len() + 1 != self.stride {
+            return Err(Box::new(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("State of size {} in rows of size {}, it should be {}", state.len(), self.stride, state.len() + 1))));
+        }
+        self.values.push(time);
+        self.values.extend_from_slice(state);
+        Ok(())
+    }
+}
+
+
+/// Struct FlatSolveResult
+///
+/// Output of the solvers which store the rows in a `FlatData`: the `data`, the final state (as `system`, as in the `SolveResult` of `solver_vector`), the final `odeparam` and the `stats` of the integration.
+#[derive(Clone,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlatSolveResult {
+    pub data : FlatData,
+    pub system : Vec<f64>,
+    pub odeparam : ODEParam,
+    pub stats : SolveStats,
+}
+
+impl FlatSolveResult {
+
+    /// Number of output rows.
+    pub fn len (&self) -> usize {
+        self.data.len()
+    }
+
+    /// True if there are no output rows.
+    pub fn is_empty (&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Values of the component of index `component` of the state (starting at 0) at each output time.
+    pub fn component (&self, component : usize) -> Vec<f64> {
+        self.data.component(component)
+    }
+
+    /// Time series of the component of index `component` of the state, as pairs `(time, value)`.
+    pub fn time_series (&self, component : usize) -> Vec<(f64, f64)> {
+        self.data.time_series(component)
+    }
+}
+
+
+// Sink which keeps the times and the states in separate vectors, used to build a `SolveResult`
+pub(crate) struct Trajectory<Row> {
+    pub times : Vec<f64>,
+    pub states : Vec<Row>,
+}
+
+impl<Row> Trajectory<Row> {
+    pub fn with_capacity (capacity : usize) -> Self {
+        Trajectory { times : Vec::with_capacity(capacity), states : Vec::with_capacity(capacity) }
+    }
+}
+
+impl<T : Copy> OutputSink<T> for Trajectory<Vec<T>> {
+    fn push (&mut self, time : f64, state : &[T]) -> Result<(), Box<dyn Error>> {
+        self.times.push(time);
+        self.states.push(state.to_vec());
+        Ok(())
+    }
+}
+
+impl<T : Copy, const N : usize> OutputSink<T> for Trajectory<[T;N]> {
+    fn push (&mut self, time : f64, state : &[T]) -> Result<(), Box<dyn Error>> {
+        self.times.push(time);
+        self.states.push(state.try_into().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput,
+            format!("State of size {}, it should be {}", state.len(), N)))?);
+        Ok(())
+    }
+}
+
+
+// Error returned by the `_to_sink` solvers when the state stops being finite
+pub(crate) fn non_finite (time : f64) -> String {
+    format!("State is not finite at time {}", time)
+}
+
+// Capacity of the output for the given parameters
+pub(crate) fn capacity (odeparam : &ODEParam) -> usize {
+    ((odeparam.tend - odeparam.time)/(odeparam.step*(odeparam.ratio_step_output as f64)) + 10.0) as usize
+}
@@ -0,0 +1,166 @@
+//! Rosenbrock methods used by `ODESolver::Ros3p` and `ODESolver::Rodas4`, with the step chosen by the error of an embedded method.
+//!
+//! They are written for the stage increments `u` (Hairer and Wanner, Solving ODEs II, IV.7): at each stage `(I/(h*gamma) - J)*u[i] = f(t + alpha[i]*h, y + sum(a[i][j]*u[j])) + sum(c[i][j]/h*u[j]) + h*d[i]*df/dt`, so only one Jacobian J and one LU factorization are needed per step, and no Newton iterations. The derivative in time of the system is computed by finite differences.
+
+use crate::adams::{error_norm, step_factor};
+use crate::engine::{SolveStats, Scalar, System, VectorSpace, Workspace};
+use crate::implicit::{self, factorize, solve_in_place};
+use crate::ODESolver;
+
+
+/// Coefficients of a Rosenbrock method, by stages.
+struct Rosenbrock {
+    gamma : f64,
+    alpha : &'static [f64],
+    a : &'static [&'static [f64]],
+    c : &'static [&'static [f64]],
+    d : &'static [f64],
+    // weights of the increments in the solution and in the embedded solution, of order `embedded_order`
+    m : &'static [f64],
+    m_embedded : &'static [f64],
+    embedded_order : usize,
+}
+
+// ROS3P, of Lang and Verwer (2001)
+const ROS3P : Rosenbrock = Rosenbrock {
+    gamma : 7.886_751_345_948_129e-1,
+    alpha : &[0.0, 1.0, 1.0],
+    a : &[&[], &[1.267_949_192_431_123], &[1.267_949_192_431_123, 0.0]],
+    c : &[&[], &[-1.607_695_154_586_736], &[-3.464_101_615_137_755, -1.732_050_807_568_877]],
+    d : &[7.886_751_345_948_129e-1, -2.113_248_654_051_871e-1, -1.077_350_269_189_626],
+    m : &[2.0, 5.773_502_691_896_258e-1, 4.226_497_308_103_742e-1],
+    m_embedded : &[2.113_248_654_051_871, 1.0, 4.226_497_308_103_742e-1],
+    embedded_order : 2,
+};
+
+const A51 : f64 = 1.221_224_509_226_641;
+const A52 : f64 = 6.019_134_481_288_629;
+const A53 : f64 = 12.537_083_329_320_87;
+const A54 : f64 = -0.687_886_036_105_895;
+
+// RODAS4 of Hairer and Wanner. The last two stages start from the embedded solution and from the solution, so the increment of the last one is the error estimate.
+const RODAS4 : Rosenbrock = Rosenbrock {
+    gamma : 0.25,
+    alpha : &[0.0, 0.386, 0.21, 0.63, 1.0, 1.0],
+    a : &[
+        &[],
+        &[1.544],
+        &[0.946_678_528_081_582_6, 0.255_701_169_898_328_4],
+        &[3.314_825_187_068_521, 2.896_124_015_972_201, 0.998_641_913_997_781_7],
+        &[A51, A52, A53, A54],
+        &[A51, A52, A53, A54, 1.0],
+    ],
+    c : &[
+        &[],
+        &[-5.668_8],
+        &[-2.430_093_356_833_875, -0.206_359_915_709_191_5],
+        &[-0.107_352_905_815_137_5, -9.594_562_251_023_355, -20.470_286_148_096_16],
+        &[7.496_443_313_967_647, -10.246_804_314_643_52, -33.999_903_528_199_05, 11.708_908_932_061_6],
+        &[8.083_246_795_921_522, -7.981_132_988_064_893, -31.521_594_328_743_71, 16.319_305_431_231_36, -6.058_818_238_834_054],
+    ],
+    d : &[0.25, -0.1043, 0.1035, -0.0362, 0.0, 0.0],
+    m : &[A51, A52, A53, A54, 1.0, 1.0],
+    m_embedded : &[A51, A52, A53, A54, 1.0, 0.0],
+    embedded_order : 3,
+};
+
+
+// Makes one accepted step of `odesolver` (`Ros3p` or `Rodas4`) from `time`, of at most `goal - time`, and returns the time reached. Without a history at `time` the integration starts again with a step of `initial_step`.
+pub(crate) fn step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, goal : f64, initial_step : f64, odesolver : ODESolver, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) -> f64 {
+    let (method, tolerance) = match odesolver {
+        ODESolver::Ros3p(tolerance) => (&ROS3P, tolerance),
+        ODESolver::Rodas4(tolerance) => (&RODAS4, tolerance),
+        _ => unreachable!("{:?} is not a Rosenbrock method", odesolver),
+    };
+
+    if workspace.multistep.times.last() != Some(&time) {
+        let Workspace { k4, multistep, .. } = workspace;
+        system.dstate(time, k4);
+        stats.fevals += 1;
+        multistep.restart(time, system.state(), k4, initial_step);
+    }
+
+    let Workspace { k1, k2, k3, k4, stage, stages, multistep, .. } = workspace;
+    stages.resize_with(method.m.len(), || k1.zeros_like());
+    let dstate = multistep.dstates.last().expect("history of the Rosenbrock method");
+
+    // Jacobian and derivative in time at the start of the step
+    stats.fevals += implicit::jacobian(system, time, dstate, &mut multistep.jacobian, stage, k1);
+    let delta = (time + f64::EPSILON.sqrt()*time.abs().max(1.0)) - time;
+    stage.assign(system.state());
+    system.dstate_at(time + delta, stage, k2);
+    for (dt, f) in k2.components_mut().iter_mut().zip(dstate.components()) {
+        *dt = (*dt - *f).scale(1.0/delta);
+    }
+    stats.fevals += 1;
+
+    loop {
+        let proposed = multistep.step;
+        let clipped = time + proposed >= goal;
+        let (step, new_time) = if clipped { (goal - time, goal) } else { (proposed, time + proposed) };
+        let too_small = time + step == time;
+
+        let lu = match factorize(1.0/(step*method.gamma), &multistep.jacobian) {
+            Some(lu) => lu,
+            None if !too_small => {
+                stats.rejected_steps += 1;
+                multistep.step = 0.25*step;
+                continue;
+            },
+            None => {
+                // no step is possible, the state which is not finite stops the integration
+                k3.components_mut().fill(T::from_f64(f64::NAN));
+                system.update_state(k3);
+                return goal;
+            },
+        };
+
+        for i in 0 .. stages.len() {
+            if i == 0 {
+                k1.assign(dstate);
+            } else {
+                stage.assign(system.state());
+                for (a, u) in method.a[i].iter().zip(stages.iter()) {
+                    stage.axpy(*a, u);
+                }
+                system.dstate_at(time + method.alpha[i]*step, stage, k1);
+                stats.fevals += 1;
+            }
+            for (c, u) in method.c[i].iter().zip(stages.iter()) {
+                k1.axpy(c/step, u);
+            }
+            k1.axpy(step*method.d[i], k2);
+            solve_in_place(&lu, k1);
+            stages[i].assign(k1);
+        }
+
+        k3.assign(system.state());
+        stage.assign(system.state());
+        for ((m, m_embedded), u) in method.m.iter().zip(method.m_embedded).zip(stages.iter()) {
+            k3.axpy(*m, u);
+            stage.axpy(*m_embedded, u);
+        }
+
+        let error = error_norm(tolerance, system.state(), k3, stage);
+        // a step whose error is not finite (a stage out of the domain of the system) is also rejected
+        if (error > 1.0 || error.is_nan()) && !too_small {
+            multistep.step = if error.is_finite() { step*step_factor(error, method.embedded_order).min(1.0) } else { 0.5*step };
+            stats.rejected_steps += 1;
+            continue;
+        }
+
+        system.dstate_at(new_time, k3, k4);
+        stats.fevals += 1;
+        stats.accepted_steps += 1;
+        system.update_state(k3);
+        multistep.replace(new_time, system.state(), k4);
+
+        if error.is_finite() {
+            multistep.step = step*step_factor(error, method.embedded_order);
+            if clipped {
+                multistep.step = multistep.step.max(proposed);
+            }
+        }
+        return new_time;
+    }
+}
@@ -0,0 +1,187 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::sync::mpsc::{Sender, SyncSender};
+
+use crate::csv::CsvFormat;
+use crate::engine::History;
+use crate::npy::npy_header;
+
+///Trait used to caracterize a destination of the output of the solvers.
+///
+///At each output time the solvers give the time and the state to the sink, so the output can be kept in memory, written to a file or sent to another thread while the simulation runs. The components of the state are `f64` by default, other scalars (see `engine::Scalar`) need sinks for them.
+pub trait OutputSink<T = f64> {
+
+    ///Receives an output row: the time and the state of the system at that time.
+    fn push (&mut self, time : f64, state : &[T]) -> Result<(), Box<dyn Error>>;
+
+    ///Called by the solvers after the last row, to flush buffers and complete the output.
+    fn finish (&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    ///If the sink needs the history of the multistep solvers (see `engine::History`), only for sinks which save the full state of the integration as `checkpoint::CheckpointSink`.
+    fn needs_history (&self) -> bool {
+        false
+    }
+
+    ///Receives the history of the solver before each row, if `needs_history`.
+    fn push_history (&mut self, _history : History<T>) {
+    }
+
+    ///History given to the multistep solvers at the start, to resume an integration (see `checkpoint::CheckpointSink::resume`).
+    fn resume_history (&mut self) -> Option<History<T>> {
+        None
+    }
+}
+
+
+/// In memory `Data` of `solver_vector` and `solver_vector_trait`, each row is `[time, state...]`.
+impl OutputSink for Vec<Vec<f64>> {
+    fn push (&mut self, time : f64, state : &[f64]) -> Result<(), Box<dyn Error>> {
+        let mut datum = Vec::with_capacity(state.len() + 1);
+        datum.push(time);
+        datum.extend_from_slice(state);
+        Vec::push(self, datum);
+        Ok(())
+    }
+}
+
+
+/// Writes the output to a text file as `data_to_file`: the first column is the time, the others are the state, all separated by tabs.
+pub struct TextFileSink {
+    writer : BufWriter<File>,
+}
+
+impl TextFileSink {
+
+    /// Creates the file, when `header` is given it is written in the first line.
+    pub fn new (file_as_string : String, header : Option<String>) -> Result<TextFileSink, Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(file_as_string)?);
+        if let Some(headerstring) = header {
+            writeln!(writer, "{}", headerstring)?;
+        }
+        Ok(TextFileSink { writer })
+    }
+}
+
+impl OutputSink for TextFileSink {
+    fn push (&mut self, time : f64, state : &[f64]) -> Result<(), Box<dyn Error>> {
+        write!(self.writer, "{:10.8e}\t", time)?;
+        for value in state {
+            write!(self.writer, "{:10.8e}\t", value)?;
+        }
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn finish (&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+
+/// Writes the output to a CSV file with the given `CsvFormat`, see the module `csv`.
+pub struct CsvSink {
+    writer : BufWriter<File>,
+    format : CsvFormat,
+}
+
+impl CsvSink {
+
+    /// Creates the file and writes the header with the names of the columns, if given. Returns an error if the delimiter is not valid, see `CsvFormat::validate_delimiter`.
+    pub fn new (file_as_string : String, format : CsvFormat) -> Result<CsvSink, Box<dyn Error>> {
+        format.validate_delimiter()?;
+        let mut writer = BufWriter::new(File::create(file_as_string)?);
+        if let Some(header) = format.format_header() {
+            write!(writer, "{}\r\n", header)?;
+        }
+        Ok(CsvSink { writer, format })
+    }
+}
+
+impl OutputSink for CsvSink {
+    fn push (&mut self, time : f64, state : &[f64]) -> Result<(), Box<dyn Error>> {
+        self.format.validate(state.len() + 1)?;
+        let mut datum = vec!(time);
+        datum.extend_from_slice(state);
+        write!(self.writer, "{}\r\n", self.format.format_row(&datum))?;
+        Ok(())
+    }
+
+    fn finish (&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+
+/// Writes the output to a binary NumPy `.npy` file, see the module `npy`.
+///
+/// The number of rows is only known at the end, so the header is rewritten by `finish`.
+pub struct NpySink {
+    writer : BufWriter<File>,
+    rows : usize,
+    columns : Option<usize>,
+}
+
+// room for any number of rows and columns in the header
+const NPY_HEADER_LEN : usize = 128;
+
+impl NpySink {
+
+    /// Creates the file.
+    pub fn new (file_as_string : String) -> Result<NpySink, Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(file_as_string)?);
+        writer.write_all(&npy_header(0, 0, NPY_HEADER_LEN))?;
+        Ok(NpySink { writer, rows : 0, columns : None })
+    }
+}
+
+impl OutputSink for NpySink {
+    fn push (&mut self, time : f64, state : &[f64]) -> Result<(), Box<dyn Error>> {
+        let columns = *self.columns.get_or_insert(state.len() + 1);
+        if columns != state.len() + 1 {
+            return Err(Box::new(io::Error::new(io::ErrorKind::InvalidInput, "Rows of the data with different sizes")));
+        }
+        self.writer.write_all(&time.to_le_bytes())?;
+        for value in state {
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish (&mut self) -> Result<(), Box<dyn Error>> {
+        let header = npy_header(self.rows, self.columns.unwrap_or(0), NPY_HEADER_LEN);
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+
+fn disconnected () -> Box<dyn Error> {
+    Box::new(io::Error::new(io::ErrorKind::BrokenPipe, "Receiver of the output rows disconnected"))
+}
+
+/// Sends each row `[time, state...]` through a channel, so another thread can process the output while the simulation runs.
+impl OutputSink for Sender<Vec<f64>> {
+    fn push (&mut self, time : f64, state : &[f64]) -> Result<(), Box<dyn Error>> {
+        let mut datum = vec!(time);
+        datum.extend_from_slice(state);
+        self.send(datum).map_err(|_| disconnected())
+    }
+}
+
+/// Sends each row `[time, state...]` through a bounded channel, so another thread can process the output while the simulation runs.
+impl OutputSink for SyncSender<Vec<f64>> {
+    fn push (&mut self, time : f64, state : &[f64]) -> Result<(), Box<dyn Error>> {
+        let mut datum = vec!(time);
+        datum.extend_from_slice(state);
+        self.send(datum).map_err(|_| disconnected())
+    }
+}
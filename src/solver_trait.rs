@@ -1,12 +1,16 @@
 pub use super::*;
+use crate::sink::OutputSink;
+pub use crate::result::{SolveResult, SolveStats, Termination};
+use crate::result::{Trajectory, capacity};
+use crate::engine::System;
 
 ///Array of the state of a system
 pub type State<const N: usize> = [f64;N];
 ///Array of the differential of the state of a system
 pub type DState<const N: usize> = [f64;N];
-///Array of data of the simulation
+///Array of data of the simulation, rows `[time, state...]` of size M = N+1, as read by `npy::npy_to_data_array`.
 ///
-/// It should have size M = N+1. Rust does not allow for arithmetics on const generics, so I had to use an other generic.
+/// The solvers return the times and the states separately in a `SolveResult`, so only `N` is needed to solve a system, and `SolveResult::data` gives the rows as vectors.
 pub type Data<const M: usize> = Vec<[f64;M]>;
 
 ///Trait used to caracterize a data type as being a system defined by ODEs. An order N (number of ODEs) has to be specified.
@@ -20,53 +24,64 @@ pub trait ODESystem<const N: usize> {
     ///Returns the differential of the state of the system.
     fn dstate (&self, time : f64) -> DState<N>;
 
+    ///Returns the differential of the system at a given `state`, which is not the actual one. It is used by the solvers at the intermediate stages of a step.
+    ///
+    ///The default implementation clones the system, updates its state and calls `dstate`. Implement it to avoid the clone in systems with large parameters.
+    fn dstate_at (&self, time : f64, state : &State<N>) -> DState<N> where Self : Clone {
+        let mut sist = self.clone();
+        sist.update_state(*state);
+        sist.dstate(time)
+    }
+
     // fn new_from_state (&self, state : State<N>) -> Self; 
 
     ///Updates the state of the system.
     fn update_state (&mut self, state : State<N>);
+
+    ///Exact Jacobian of the differential at a given `state` (`jacobian[i][j]` is the derivative of the component `i` of the differential with respect to the component `j` of the state), used by the implicit solvers (`ODESolver::Lsoda`, `Ros3p`, `Rodas4`, `Radau5`, `GaussLegendre`, `BackwardEuler` and `Trapezoidal`).
+    ///
+    ///The default implementation returns `None` and the solvers compute it by finite differences.
+    fn exact_jacobian (&self, _time : f64, _state : &State<N>) -> Option<[[f64;N];N]> {
+        None
+    }
 }
 
 
-// pub type Solver<const N:usize, Sist: EdoSystem<N>> = fn (sist: &Sist, step : f64, time : f64) -> State<N>;
-// ODE Solver function
-type Solver<const N:usize, Sist> = fn (sist: &Sist, step : f64, time : f64) -> State<N>;
-
-
-fn integrator<const N:usize, Sist: ODESystem<N>> (sist : Sist, odeparam : ODEParam, solver : Solver<N,Sist>) -> (Sist, ODEParam)
-{
-    let mut time = odeparam.time;
-    let tend = odeparam.tend;
-    let step = odeparam.step;
-    let ratio_step_output = odeparam.ratio_step_output;
-    // let solver = odeparam.solver;
-    let mut new_state;  // = sist.state().clone();
-    let mut new_sist = sist; 
-
-    for _i in  0 .. ratio_step_output {
-        if (time + step) - tend > 0.0  {
-            let new_step = tend - time;
-            // new_state = (solver)(&new_sist, step, time);
-            new_state = (solver)(&new_sist, new_step, time);
-            // new_sist = new_sist.new_from_state(new_state);
-            new_sist.update_state(new_state);
-            time = tend;
-            break;
-        } else {
-            new_state = (solver)(&new_sist, step, time);
-            // new_sist = new_sist.new_from_state(new_state);
-            new_sist.update_state(new_state);
-            time += step;
-        }
+// System adapted to the engine
+struct Adapter<'a, const N:usize, Sist>(&'a mut Sist);
+
+impl<const N:usize, Sist: ODESystem<N> + Clone> System for Adapter<'_, N, Sist> {
+    type State = State<N>;
+
+    fn state (&self) -> &State<N> {
+        self.0.state()
     }
 
-    let new_param = ODEParam {
-        time, .. odeparam};
-    
-    (new_sist, new_param)
+    fn dstate (&mut self, time : f64, dstate : &mut State<N>) {
+        *dstate = self.0.dstate(time);
+    }
+
+    fn dstate_at (&mut self, time : f64, state : &State<N>, dstate : &mut State<N>) {
+        *dstate = self.0.dstate_at(time, state);
+    }
+
+    fn update_state (&mut self, state : &State<N>) {
+        self.0.update_state(*state);
+    }
+
+    fn jacobian_at (&mut self, time : f64, state : &State<N>, jacobian : &mut [State<N>]) -> bool {
+        match self.0.exact_jacobian(time, state) {
+            Some(exact) => {
+                jacobian.copy_from_slice(&exact);
+                true
+            },
+            None => false,
+        }
+    }
 }
 
 
-/// Main function for solving ODEs, returns an tuple with (Data, Sist, ODEParam) where Sist and ODEParam have updated values.
+/// Main function for solving ODEs, returns a `SolveResult` with the output times and states (arrays `[f64;N]`), the updated Sist and ODEParam and the statistics of the integration.
 ///
 ///# inputs
 ///
@@ -75,157 +90,47 @@ fn integrator<const N:usize, Sist: ODESystem<N>> (sist : Sist, odeparam : ODEPar
 ///`odeparam : ODEParam`. An value of ODEParam.
 ///
 ///`odesolver : ODESolver`. A choice of an ODE solver.
-pub fn solve_ode<const N:usize, const M:usize, Sist: ODESystem<N> + Clone> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver) -> (Data<M>, Sist, ODEParam) {
-    
-    let tini = odeparam.time;
-    let tend = odeparam.tend;
-    let step = odeparam.step;
-    let ratio_step_output = odeparam.ratio_step_output;
+pub fn solve_ode<const N:usize, Sist: ODESystem<N> + Clone> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver) -> SolveResult<Sist, State<N>> {
+    solve_ode_tstops::<N,Sist>(sist, odeparam, odesolver, &[], false)
+}
 
-    let capacity = ((tend-tini)/(step*(ratio_step_output as f64)) + 10.0) as usize;
-    
-    let mut data : Vec<[f64;M]> = Vec::<[f64;M]>::with_capacity(capacity);
 
-    let mut datum: [f64;M] = [0.0;M];
+/// Similar to `solve_ode` but the integrator lands exactly at each time of `tstops` (input steps, dosing events, scheduled switches...), shortening the step before it without changing `ODEParam.step`. The step after it ends at the next time of the grid of `ODEParam.step`, so the output times are not shifted, and the adaptive solvers start again from the tstop. If `output_tstops` is true the points at the `tstops` are also added to the data.
+pub fn solve_ode_tstops<const N:usize, Sist: ODESystem<N> + Clone> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver, tstops : &[f64], output_tstops : bool) -> SolveResult<Sist, State<N>> {
+    let mut trajectory = Trajectory::with_capacity(capacity(&odeparam));
+    let mut stats = SolveStats::default();
 
-    datum[0] = tini;
-    for (i,x) in sist.state().clone().iter().enumerate() {
-        datum[i+1] = *x;
-    }
-                                                  
-        // = vec!(tini);
-    // datum.append(&mut state.clone());
-    
-    data.push(datum);
+    let mut new_sist = sist;
 
-    let mut new_param  = odeparam;
-    let mut new_sist  = sist;
-    
-    let solver = match odesolver {
-        ODESolver::RK4 => rk4,
-        ODESolver::Euler => euler,
-    };
-    
-    loop {
-        (new_sist, new_param) =
-            integrator::<N,Sist>(new_sist,new_param,solver);
-
-        let new_time = new_param.time;
-
-        // datum = vec!(newTime);
-        // datum.append(&mut newState.clone());
-        
-        datum[0] = new_time;
-        for (i,x) in new_sist.state().clone().iter().enumerate() {
-            datum[i+1] = *x;
-        }
-        
-        data.push(datum);
+    let new_param = engine::integrate(&mut Adapter(&mut new_sist), odeparam, odesolver, tstops, output_tstops, &mut trajectory, &mut stats)
+        .unwrap_or_else(|erro| panic!("Not possible to store the data in memory. Error: {}", erro));
 
-        if (new_time - tend).abs() < 1.0e-10 {
-            break;
-        }
-    }
-
-    (data, new_sist, new_param)
+    SolveResult { times : trajectory.times, states : trajectory.states, system : new_sist, odeparam : new_param, stats }
 }
 
 
-
-
-fn euler<const N:usize, Sist: ODESystem<N>> (sist: &Sist, step : f64, time : f64) -> State<N> {
-    let state = sist.state();
-    let mut xps = sist.dstate(time);// funcSist(time, &state);
-    for (x,xp) in state.iter().zip(xps.iter_mut()) {
-        *xp = (*x) + step*(*xp);
-        //tou aproveitando o proprio xp pra nao criar outra variavel pro valor final.
-    }
-    xps
+/// Similar to `solve_ode` but the output is given to an `OutputSink` (in memory data, files, channels...) instead of being returned. Returns a tuple with (Sist, ODEParam) with updated values, or the error of the sink.
+pub fn solve_ode_to_sink<const N:usize, Sist: ODESystem<N> + Clone, Sink: OutputSink + ?Sized> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver, sink : &mut Sink) -> Result<(Sist, ODEParam), Box<dyn Error>> {
+    solve_ode_tstops_to_sink::<N,Sist,Sink>(sist, odeparam, odesolver, &[], false, sink)
 }
 
 
+/// Similar to `solve_ode_tstops` but the output is given to an `OutputSink`, as in `solve_ode_to_sink`. If the state stops being finite the integration is stopped and an error is returned.
+pub fn solve_ode_tstops_to_sink<const N:usize, Sist: ODESystem<N> + Clone, Sink: OutputSink + ?Sized> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver, tstops : &[f64], output_tstops : bool, sink : &mut Sink) -> Result<(Sist, ODEParam), Box<dyn Error>> {
+    let mut stats = SolveStats::default();
+    let mut new_sist = sist;
+    let new_param = engine::integrate(&mut Adapter(&mut new_sist), odeparam, odesolver, tstops, output_tstops, sink, &mut stats)?;
 
-fn rk4<const N:usize, Sist: ODESystem<N> + Clone> (sist: &Sist, step : f64, time : f64) -> State<N> {    
-    
-    let state = sist.state();
-    
-    let k1 = sist.dstate(time);
-
-    let time2 = time + 0.5*step;
-    let mut xs2  = [0.0;N];
-    for (i,x) in state.iter().enumerate(){
-        xs2[i] = *x + 0.5*k1[i]*step;
-    }
-    // let sist2 = sist.new_from_state(xs2);
-    let mut sist2 = sist.clone();
-    sist2.update_state(xs2);
-    let k2 = sist2.dstate(time2);
-
-    let time3 = time2;
-    let mut xs3 = [0.0;N];
-    for (i,x) in state.iter().enumerate(){
-        xs3[i] = *x + 0.5*k2[i]*step;
-    }
-    // let sist3 = sist.new_from_state(xs3);
-    let mut sist3 = sist.clone();
-    sist3.update_state(xs3);
-    let k3 = sist3.dstate(time3);
-    
-    let time4 = time + step;
-    let mut xs4 = [0.0;N];
-    for (i,x) in state.iter().enumerate(){
-        xs4[i] = *x + k3[i]*step;
+    match stats.termination {
+        Termination::Error(erro) => Err(erro.into()),
+        _ => Ok((new_sist, new_param)),
     }
-    // let sist4 = sist.new_from_state(xs4);
-    let mut sist4 = sist.clone();
-    sist4.update_state(xs4);
-    let k4 = sist4.dstate(time4); 
-    
-    let state = sist.state();
-    let mut output = [0.0; N]; 
-    for (i,_ka) in k1.iter().enumerate(){
-        output[i] = state[i] + step*(1.0/6.0)*(k1[i] + 2.0*k2[i] + 2.0*k3[i] + k4[i]);
-    }
-    
-    output
 }
 
 
 /// Saves the data to a given filename/filepath
 ///
-/// The first column of data is the times, the second onwards are the values of the state at that particular time. It has an `header: Option<String>` that when given a Some(String) will add the string as a header in the data file.
-pub fn data_to_file<const M : usize>(data:&Data<M>,file_as_string:String, header : Option<String>) -> Result<(), Box<dyn Error>> {
-    let file = File::create(file_as_string);
-
-    let mut file = match file {
-        Ok(f) => f,
-        Err(erro) => {
-            eprintln!("Not possible to create/find the file for exporting the data. Error: {}", erro);
-            return Err(Box::new(erro) as Box<dyn Error>);},
-    };
-
-    if let Some(headerstring) = header {
-        match writeln!(file,"{}", headerstring){
-            Ok(_) => (),
-            Err(erro) => {
-            eprintln!("Error in writing to file. Error: {}", erro);
-            return Err(Box::new(erro) as Box<dyn Error>);},
-        }
-    }
-
-    for datum in data {
-        let mut string = String::new();
-        for value in datum {
-             string += &format!("{:10.8e}\t",value).to_string();
-        }
-        match writeln!(file,"{}", string) {
-            Ok(_) => (),
-            Err(erro) => {
-            eprintln!("Error in writing to file. Error: {}", erro);
-            return Err(Box::new(erro) as Box<dyn Error>);},
-        }
-    }
-
-    Ok(())
+/// The first column of data is the times, the second onwards are the values of the state at that particular time. The rows can be arrays, as `Data<M>`, or vectors, as given by `SolveResult::data`. It has an `header: Option<String>` that when given a Some(String) will add the string as a header in the data file.
+pub fn data_to_file<Row : AsRef<[f64]>>(data:&[Row],file_as_string:String, header : Option<String>) -> Result<(), Box<dyn Error>> {
+    engine::rows_to_file(data, file_as_string, header)
 }
-
@@ -1,18 +1,136 @@
 pub use super::*;
-
-/// Vector of the state of the system
-pub type State = Vec<f64>;
+use crate::sink::{OutputSink, TextFileSink};
+pub use crate::result::{SolveResult, SolveStats, Termination, FlatData, FlatSolveResult};
+use crate::result::{Trajectory, capacity};
+use crate::engine::{System, Workspace};
+pub use crate::engine::{Scalar, VectorSpace};
+
+/// Vector of the state of the system, of `f64` by default (see `solve_ode_generic` for other scalars)
+pub type State<T = f64> = Vec<T>;
 /// Vector of the differential of the state of the system
-pub type DState = Vec<f64>;
+pub type DState<T = f64> = Vec<T>;
 /// 2D vector of the data of the simulation
 pub type Data = Vec<Vec<f64>>;
 
 /// Function that receives as input the time and state of the system and returns the differential of the state.
 pub type SystemFunction = fn (time: f64, state: &State) -> DState;
+/// Function that receives as input the time and state of the system and writes the differential of the state in `dstate`, without allocating it.
+pub type SystemFunctionInPlace = fn (time: f64, state: &[f64], dstate: &mut [f64]);
 // pub type SystemFunction2<T: Fn(f64, &State) -> DState> = fn (func : T) -> DState;
 
-// type Solver = fn (system_function : SystemFunction, step : f64, time : f64, state : &State) -> State;
-type SolverClosure<T> = fn (system_function : &mut T, step : f64, time : f64, state : &State) -> State;
+
+// System function and state adapted to the engine, for the closures which return a `DState`
+struct Allocating<SysFunc> {
+    system_function : SysFunc,
+    state : State,
+}
+
+impl<SysFunc : FnMut (f64, &State) -> DState> System for Allocating<SysFunc> {
+    type State = State;
+
+    fn state (&self) -> &State {
+        &self.state
+    }
+
+    fn dstate (&mut self, time : f64, dstate : &mut State) {
+        dstate.copy_from_slice(&(self.system_function)(time, &self.state));
+    }
+
+    fn dstate_at (&mut self, time : f64, state : &State, dstate : &mut State) {
+        dstate.copy_from_slice(&(self.system_function)(time, state));
+    }
+
+    fn update_state (&mut self, state : &State) {
+        self.state.copy_from_slice(state);
+    }
+}
+
+// Same for the system functions which write the differential in place
+struct InPlace<SysFunc> {
+    system_function : SysFunc,
+    state : State,
+}
+
+impl<SysFunc : FnMut (f64, &[f64], &mut [f64])> System for InPlace<SysFunc> {
+    type State = State;
+
+    fn state (&self) -> &State {
+        &self.state
+    }
+
+    fn dstate (&mut self, time : f64, dstate : &mut State) {
+        (self.system_function)(time, &self.state, dstate);
+    }
+
+    fn dstate_at (&mut self, time : f64, state : &State, dstate : &mut State) {
+        (self.system_function)(time, state, dstate);
+    }
+
+    fn update_state (&mut self, state : &State) {
+        self.state.copy_from_slice(state);
+    }
+}
+
+// Same for the closures which return a `DState`, with a closure which gives the Jacobian
+struct WithJacobian<SysFunc, JacFunc> {
+    system_function : SysFunc,
+    jacobian_function : JacFunc,
+    state : State,
+}
+
+impl<SysFunc : FnMut (f64, &State) -> DState, JacFunc : FnMut (f64, &State) -> Vec<Vec<f64>>> System for WithJacobian<SysFunc, JacFunc> {
+    type State = State;
+
+    fn state (&self) -> &State {
+        &self.state
+    }
+
+    fn dstate (&mut self, time : f64, dstate : &mut State) {
+        dstate.copy_from_slice(&(self.system_function)(time, &self.state));
+    }
+
+    fn dstate_at (&mut self, time : f64, state : &State, dstate : &mut State) {
+        dstate.copy_from_slice(&(self.system_function)(time, state));
+    }
+
+    fn update_state (&mut self, state : &State) {
+        self.state.copy_from_slice(state);
+    }
+
+    fn jacobian_at (&mut self, time : f64, state : &State, jacobian : &mut [State]) -> bool {
+        for (row, exact) in jacobian.iter_mut().zip((self.jacobian_function)(time, state)) {
+            row.copy_from_slice(&exact);
+        }
+        true
+    }
+}
+
+// Same for the closures with other types of state and differential, as `[f64;N]`, `nalgebra::DVector<f64>` or `ndarray::Array1<f64>`
+struct Generic<SysFunc, S> {
+    system_function : SysFunc,
+    state : S,
+}
+
+impl<T : Scalar, S : VectorSpace<T>, SysFunc : FnMut (f64, &S) -> S> System<T> for Generic<SysFunc, S> {
+    type State = S;
+
+    fn state (&self) -> &S {
+        &self.state
+    }
+
+    fn dstate (&mut self, time : f64, dstate : &mut S) {
+        *dstate = (self.system_function)(time, &self.state);
+    }
+
+    fn dstate_at (&mut self, time : f64, state : &S, dstate : &mut S) {
+        *dstate = (self.system_function)(time, state);
+    }
+
+    fn update_state (&mut self, state : &S) {
+        self.state.assign(state);
+    }
+}
+
 
 /// Solves one `step` of the ODE problem.
 ///
@@ -26,180 +144,214 @@ type SolverClosure<T> = fn (system_function : &mut T, step : f64, time : f64, st
 ///
 ///`odesolver: ODESolver`. A choice of an ODE solver.
 pub fn solve_edo_step<SysFunc : Fn (f64, &State) -> DState > (odeparam : ODEParam, system_function : &mut SysFunc, state : State, odesolver: ODESolver) -> State {
-    
-    let solver = match odesolver {
-        ODESolver::RK4 => rk4_closure::<SysFunc>,
-        ODESolver::Euler => euler_closure::<SysFunc>,
-    };
-
-    solver(system_function,odeparam.step
-                    ,odeparam.time,&state)
+
+    let mut system = Allocating { system_function, state };
+    let mut workspace = Workspace::new(&system.state);
+    engine::step(&mut system, odeparam.time, odeparam.step, odesolver, &mut workspace);
+
+    system.state
 }
 
 
-// fn rk4 (system_function : SystemFunction, step : f64, time : f64, state : &State) -> State {
-    
-//     let k1 = system_function (time, state);
-    
-//     let time2 = time + 0.5*step;
-//     let xs2 : Vec<f64> = state.iter().zip(&k1).map(|(x,k)|(*x) + 0.5 * k * step).collect();
-//     let k2 = system_function(time2, &xs2);
-
-//     let time3 = time2;
-//     let xs3: Vec<f64> = state.iter().zip(&k2)
-//         .map(|(x,kb)| (*x) + 0.5 * kb * step)
-//         .collect();
-//     let k3 = system_function(time3, &xs3);
-    
-//     let time4 = time + step;
-//     let xs4:Vec<f64> = state.iter().zip(&k3)
-//         .map(|(x,kc)| (*x) + kc * step).collect();
-//     let k4:Vec<f64> = system_function(time4, &xs4); 
-    
-//     let mut output = Vec::<f64>::new(); 
-//     for (i,_ka) in k1.iter().enumerate(){
-//         output.push(state[i] + step*(1.0/6.0)*(k1[i] + 2.0*k2[i] + 2.0*k3[i] + k4[i]));
-//     }
-    
-//     output
-// }
-
-// fn euler (system_function : SystemFunction, step : f64, time : f64, state : &State) -> State {
-//     let mut xps = system_function(time, &state);
-//     for (x,xp) in state.iter().zip(xps.iter_mut()) {
-//         *xp = (*x) + step*(*xp);
-//         //tou aproveitando o proprio xp pra nao criar outra variavel pro valor final.
-//     }
-
-//     xps
-// }
 
+/// Main function for solving ODEs using vectors. Returns a `SolveResult` with the output times and states, the final state (as `system`), the updated ODEParam and the statistics of the integration.
+///
+///# Inputs
+///
+///`system_function: Closure FnMut (f64,&State) -> DState` Also an closure Fn or an fn of the type `SystemFunction` are accepted. The input is a closure or fn which receives `time : f64` and `state : &State` returning a `DState`. In other words, function with the right parameters in order to obtain the differential of the state of the system.
+///
+///`odeparam: ODEParam`. A value of type ODEParam.
+///
+///`odesolver: ODESolver`. A choice of an ODE solver.
+pub fn solve_ode <SysFunc : FnMut (f64, &State) -> DState> (system_function: SysFunc, odeparam : ODEParam, state : State, odesolver: ODESolver) -> SolveResult<State> {
+    solve_ode_tstops(system_function, odeparam, state, odesolver, &[], false)
+}
 
-// pub type SystemFunction2<T: Fn(f64, &State) -> DState> = fn (func : T) -> DState;
 
+/// Similar to `solve_ode` but the integrator lands exactly at each time of `tstops` (input steps, dosing events, scheduled switches...), shortening the step before it without changing `ODEParam.step`. The step after it ends at the next time of the grid of `ODEParam.step`, so the output times are not shifted, and the adaptive solvers start again from the tstop. If `output_tstops` is true the points at the `tstops` are also added to the data.
+pub fn solve_ode_tstops <SysFunc : FnMut (f64, &State) -> DState> (system_function: SysFunc, odeparam : ODEParam, state : State, odesolver: ODESolver, tstops : &[f64], output_tstops : bool) -> SolveResult<State> {
 
-fn rk4_closure<SysFunction : FnMut (f64,&State) -> DState> (system_function : &mut SysFunction, step : f64, time : f64, state : &State) -> State {
-    
-    let k1 = system_function (time, state);
-    
-    let time2 = time + 0.5*step;
-    let xs2 : Vec<f64> = state.iter().zip(&k1).map(|(x,k)|(*x) + 0.5 * k * step).collect();
-    let k2 = system_function(time2, &xs2);
-
-    let time3 = time2;
-    let xs3: Vec<f64> = state.iter().zip(&k2)
-        .map(|(x,kb)| (*x) + 0.5 * kb * step)
-        .collect();
-    let k3 = system_function(time3, &xs3);
-    
-    let time4 = time + step;
-    let xs4:Vec<f64> = state.iter().zip(&k3)
-        .map(|(x,kc)| (*x) + kc * step).collect();
-    let k4:Vec<f64> = system_function(time4, &xs4); 
-    
-    let mut output = Vec::<f64>::new(); 
-    for (i,_ka) in k1.iter().enumerate(){
-        output.push(state[i] + step*(1.0/6.0)*(k1[i] + 2.0*k2[i] + 2.0*k3[i] + k4[i]));
-    }
-    
-    output
+    let mut trajectory = Trajectory::with_capacity(capacity(&odeparam));
+    let mut stats = SolveStats::default();
+    let mut system = Allocating { system_function, state };
+
+    let new_param = engine::integrate(&mut system, odeparam, odesolver, tstops, output_tstops, &mut trajectory, &mut stats)
+        .unwrap_or_else(|erro| panic!("Not possible to store the data in memory. Error: {}", erro));
+
+    SolveResult { times : trajectory.times, states : trajectory.states, system : system.state, odeparam : new_param, stats }
 }
 
-fn euler_closure<SysFunction : FnMut (f64,&State) -> DState> (system_function : &mut SysFunction, step : f64, time : f64, state : &State) -> State {
-    let mut xps = system_function(time, state);
-    for (x,xp) in state.iter().zip(xps.iter_mut()) {
-        *xp = (*x) + step*(*xp);
-        //tou aproveitando o proprio xp pra nao criar outra variavel pro valor final.
-    }
 
-    xps
+/// Similar to `solve_ode` but the output is given to an `OutputSink` (in memory data, files, channels...) instead of being returned. Returns a tuple with (State, ODEParam) with updated values, or the error of the sink.
+pub fn solve_ode_to_sink <SysFunc : FnMut (f64, &State) -> DState, Sink : OutputSink + ?Sized> (system_function: SysFunc, odeparam : ODEParam, state : State, odesolver: ODESolver, sink : &mut Sink) -> Result<(State, ODEParam), Box<dyn Error>> {
+    solve_ode_tstops_to_sink(system_function, odeparam, state, odesolver, &[], false, sink)
 }
 
 
+/// Similar to `solve_ode_tstops` but the output is given to an `OutputSink`, as in `solve_ode_to_sink`. If the state stops being finite the integration is stopped and an error is returned.
+pub fn solve_ode_tstops_to_sink <SysFunc : FnMut (f64, &State) -> DState, Sink : OutputSink + ?Sized> (system_function: SysFunc, odeparam : ODEParam, state : State, odesolver: ODESolver, tstops : &[f64], output_tstops : bool, sink : &mut Sink) -> Result<(State, ODEParam), Box<dyn Error>> {
+    let mut stats = SolveStats::default();
+    let mut system = Allocating { system_function, state };
+    let new_param = engine::integrate(&mut system, odeparam, odesolver, tstops, output_tstops, sink, &mut stats)?;
 
-/// Main function for solving ODEs using vectors. Returns a tuple with (Data, ODEParam), where ODEParam have updated values.
+    match stats.termination {
+        Termination::Error(erro) => Err(erro.into()),
+        _ => Ok((system.state, new_param)),
+    }
+}
+
+
+/// Similar to `solve_ode` but with the Jacobian of the system, used by the implicit solvers (`ODESolver::Lsoda`, `Ros3p`, `Rodas4`, `Radau5`, `GaussLegendre`, `BackwardEuler` and `Trapezoidal`) instead of the finite differences. The other solvers ignore it.
 ///
 ///# Inputs
 ///
-///`system_function: Closure FnMut (f64,&State) -> DState` Also an closure Fn or an fn of the type `SystemFunction` are accepted. The input is a closure or fn which receives `time : f64` and `state : &State` returning a `DState`. In other words, function with the right parameters in order to obtain the differential of the state of the system.
+///`system_function: Closure FnMut (f64,&State) -> DState`. As in `solve_ode`.
+///
+///`jacobian_function: Closure FnMut (f64,&State) -> Vec<Vec<f64>>`. A closure or fn which receives `time : f64` and `state : &State` returning the Jacobian, where the element `[i][j]` is the derivative of the component `i` of the differential with respect to the component `j` of the state. It can be made from expressions with `expression::ExprSystem::jacobian_function` or from a `GenericODESystem` with dual numbers.
 ///
 ///`odeparam: ODEParam`. A value of type ODEParam.
 ///
+///`state: State`. A vector with an initial state.
+///
 ///`odesolver: ODESolver`. A choice of an ODE solver.
-pub fn solve_ode <SysFunc : FnMut (f64, &State) -> DState> (mut system_function: SysFunc, odeparam : ODEParam, state : State, odesolver: ODESolver) -> (Data, ODEParam) {
-
-    let mut data : Vec<Vec<f64>> = Vec::<Vec<f64>>::new();
-    
-    let tini = odeparam.time;
-    let tend = odeparam.tend;
-    // let step = odeparam.step;
-    // let ratio_step_output = odeparam.ratio_step_output;
-
-
-    let mut datum: Vec<f64> = vec!(tini);
-    datum.append(&mut state.clone());
-    
-    data.push(datum);
-
-    let mut new_param : ODEParam = odeparam;
-    let mut new_state : State = state;
-    
-    let solver = match odesolver {
-        ODESolver::RK4 => rk4_closure,
-        ODESolver::Euler => euler_closure,
-    };
-    
-    loop {
-        (new_state, new_param) =
-            integrator_closure(new_param, &mut system_function, new_state, solver);
-
-        let new_time = new_param.time;
-
-        datum = vec!(new_time);
-        datum.append(&mut new_state.clone());
-        data.push(datum);
-
-        if (new_time - tend).abs() < 1.0e-10 {
-            break;
-        }
+pub fn solve_ode_with_jacobian <SysFunc : FnMut (f64, &State) -> DState, JacFunc : FnMut (f64, &State) -> Vec<Vec<f64>>> (system_function: SysFunc, jacobian_function: JacFunc, odeparam : ODEParam, state : State, odesolver: ODESolver) -> SolveResult<State> {
+    solve_ode_with_jacobian_tstops(system_function, jacobian_function, odeparam, state, odesolver, &[], false)
+}
+
+
+/// Similar to `solve_ode_with_jacobian` but the integrator lands exactly at each time of `tstops`, as in `solve_ode_tstops`.
+pub fn solve_ode_with_jacobian_tstops <SysFunc : FnMut (f64, &State) -> DState, JacFunc : FnMut (f64, &State) -> Vec<Vec<f64>>> (system_function: SysFunc, jacobian_function: JacFunc, odeparam : ODEParam, state : State, odesolver: ODESolver, tstops : &[f64], output_tstops : bool) -> SolveResult<State> {
+
+    let mut trajectory = Trajectory::with_capacity(capacity(&odeparam));
+    let mut stats = SolveStats::default();
+    let mut system = WithJacobian { system_function, jacobian_function, state };
+
+    let new_param = engine::integrate(&mut system, odeparam, odesolver, tstops, output_tstops, &mut trajectory, &mut stats)
+        .unwrap_or_else(|erro| panic!("Not possible to store the data in memory. Error: {}", erro));
+
+    SolveResult { times : trajectory.times, states : trajectory.states, system : system.state, odeparam : new_param, stats }
+}
+
+
+/// Similar to `solve_ode_with_jacobian` but the output is given to an `OutputSink`, as in `solve_ode_to_sink`.
+pub fn solve_ode_with_jacobian_to_sink <SysFunc : FnMut (f64, &State) -> DState, JacFunc : FnMut (f64, &State) -> Vec<Vec<f64>>, Sink : OutputSink + ?Sized> (system_function: SysFunc, jacobian_function: JacFunc, odeparam : ODEParam, state : State, odesolver: ODESolver, sink : &mut Sink) -> Result<(State, ODEParam), Box<dyn Error>> {
+    solve_ode_with_jacobian_tstops_to_sink(system_function, jacobian_function, odeparam, state, odesolver, &[], false, sink)
+}
+
+
+/// Similar to `solve_ode_with_jacobian_tstops` but the output is given to an `OutputSink`, as in `solve_ode_tstops_to_sink`.
+#[allow(clippy::too_many_arguments)]
+pub fn solve_ode_with_jacobian_tstops_to_sink <SysFunc : FnMut (f64, &State) -> DState, JacFunc : FnMut (f64, &State) -> Vec<Vec<f64>>, Sink : OutputSink + ?Sized> (system_function: SysFunc, jacobian_function: JacFunc, odeparam : ODEParam, state : State, odesolver: ODESolver, tstops : &[f64], output_tstops : bool, sink : &mut Sink) -> Result<(State, ODEParam), Box<dyn Error>> {
+    let mut stats = SolveStats::default();
+    let mut system = WithJacobian { system_function, jacobian_function, state };
+    let new_param = engine::integrate(&mut system, odeparam, odesolver, tstops, output_tstops, sink, &mut stats)?;
+
+    match stats.termination {
+        Termination::Error(erro) => Err(erro.into()),
+        _ => Ok((system.state, new_param)),
     }
+}
+
 
-    (data, new_param)
+/// Similar to `solve_ode` but the system function writes the differential of the state in place, `FnMut (f64, &[f64], &mut [f64])`, and the output is stored in a contiguous `FlatData`. The stage buffers of the solvers are allocated once, so the integration does not allocate memory at each step or output row. Returns a `FlatSolveResult`.
+///
+///# Inputs
+///
+///`system_function: Closure FnMut (f64, &[f64], &mut [f64])` Also an closure Fn or an fn of the type `SystemFunctionInPlace` are accepted. The input is a closure or fn which receives `time : f64`, `state : &[f64]` and `dstate : &mut [f64]`, where it writes the differential of the state.
+///
+///`odeparam: ODEParam`. A value of type ODEParam.
+///
+///`state: State`. A vector with an initial state.
+///
+///`odesolver: ODESolver`. A choice of an ODE solver.
+pub fn solve_ode_in_place <SysFunc : FnMut (f64, &[f64], &mut [f64])> (system_function: SysFunc, odeparam : ODEParam, state : State, odesolver: ODESolver) -> FlatSolveResult {
+    solve_ode_in_place_tstops(system_function, odeparam, state, odesolver, &[], false)
 }
 
 
+/// Similar to `solve_ode_in_place` but the integrator lands exactly at each time of `tstops`, as in `solve_ode_tstops`.
+pub fn solve_ode_in_place_tstops <SysFunc : FnMut (f64, &[f64], &mut [f64])> (system_function: SysFunc, odeparam : ODEParam, state : State, odesolver: ODESolver, tstops : &[f64], output_tstops : bool) -> FlatSolveResult {
 
-fn integrator_closure<SysFunc : FnMut (f64, &State) -> DState> (odeparam : ODEParam, system_function : &mut SysFunc, state : State, solver : SolverClosure<SysFunc>) -> (Vec<f64>, ODEParam)
-{
-    let mut time = odeparam.time;
-    let tend = odeparam.tend;
-    let step = odeparam.step;
-    let ratio_step_output = odeparam.ratio_step_output;
-    // let solver = odeparam.solver;
-    let mut new_state: Vec<f64> = state;
-
-    for _i in  0 .. ratio_step_output {
-        if (time + step) - tend > 0.0  {
-            let new_step = tend - time;
-            // println!("{}",new_step);
-            new_state = (solver)(system_function, new_step, time, &new_state);
-            time = tend;
-            break;
-        } else {
-            new_state = (solver)(system_function, step, time, &new_state);
-            time += step;
-        }
+    let mut data = FlatData::with_capacity(state.len() + 1, capacity(&odeparam));
+    let mut stats = SolveStats::default();
+    let mut system = InPlace { system_function, state };
+
+    let new_param = engine::integrate(&mut system, odeparam, odesolver, tstops, output_tstops, &mut data, &mut stats)
+        .unwrap_or_else(|erro| panic!("Not possible to store the data in memory. Error: {}", erro));
+
+    FlatSolveResult { data, system : system.state, odeparam : new_param, stats }
+}
+
+
+/// Similar to `solve_ode_in_place` but the output is given to an `OutputSink`, as in `solve_ode_to_sink`.
+pub fn solve_ode_in_place_to_sink <SysFunc : FnMut (f64, &[f64], &mut [f64]), Sink : OutputSink + ?Sized> (system_function: SysFunc, odeparam : ODEParam, state : State, odesolver: ODESolver, sink : &mut Sink) -> Result<(State, ODEParam), Box<dyn Error>> {
+    solve_ode_in_place_tstops_to_sink(system_function, odeparam, state, odesolver, &[], false, sink)
+}
+
+
+/// Similar to `solve_ode_in_place_tstops` but the output is given to an `OutputSink`, as in `solve_ode_tstops_to_sink`.
+pub fn solve_ode_in_place_tstops_to_sink <SysFunc : FnMut (f64, &[f64], &mut [f64]), Sink : OutputSink + ?Sized> (system_function: SysFunc, odeparam : ODEParam, state : State, odesolver: ODESolver, tstops : &[f64], output_tstops : bool, sink : &mut Sink) -> Result<(State, ODEParam), Box<dyn Error>> {
+    let mut stats = SolveStats::default();
+    let mut system = InPlace { system_function, state };
+    let new_param = engine::integrate(&mut system, odeparam, odesolver, tstops, output_tstops, sink, &mut stats)?;
+
+    match stats.termination {
+        Termination::Error(erro) => Err(erro.into()),
+        _ => Ok((system.state, new_param)),
     }
+}
+
+
+
+
+
+/// Similar to `solve_ode` but the state and its differential can be of any type which implements `VectorSpace`: `[T;N]`, `Vec<T>`, `nalgebra::SVector<T,N>` and `nalgebra::DVector<T>` (feature `nalgebra`), `ndarray::Array1<T>` (feature `ndarray`) or a type of your own. So the system function can use them without converting from and to `Vec<f64>`. The components can be of any `Scalar` type `T`: `f64`, `f32`, `Complex<f64>` (feature `complex`)... Returns a `SolveResult` with the final state (as `system`) of the same type and the output states as `Vec<T>`.
+///
+///# Inputs
+///
+///`system_function: Closure FnMut (f64,&S) -> S` Also an closure Fn or an fn are accepted. The input is a closure or fn which receives `time : f64` and `state : &S` returning the differential of the state, of the same type.
+///
+///`odeparam: ODEParam`. A value of type ODEParam.
+///
+///`state: S`. An initial state.
+///
+///`odesolver: ODESolver`. A choice of an ODE solver.
+pub fn solve_ode_generic <T : Scalar, S : VectorSpace<T>, SysFunc : FnMut (f64, &S) -> S> (system_function: SysFunc, odeparam : ODEParam, state : S, odesolver: ODESolver) -> SolveResult<S, Vec<T>> {
+    solve_ode_generic_tstops(system_function, odeparam, state, odesolver, &[], false)
+}
+
+
+/// Similar to `solve_ode_generic` but the integrator lands exactly at each time of `tstops`, as in `solve_ode_tstops`.
+pub fn solve_ode_generic_tstops <T : Scalar, S : VectorSpace<T>, SysFunc : FnMut (f64, &S) -> S> (system_function: SysFunc, odeparam : ODEParam, state : S, odesolver: ODESolver, tstops : &[f64], output_tstops : bool) -> SolveResult<S, Vec<T>> {
 
-    let new_param = ODEParam {
-        time, tend, step, ratio_step_output};
-    
-    (new_state, new_param)
+    let mut trajectory = Trajectory::with_capacity(capacity(&odeparam));
+    let mut stats = SolveStats::default();
+    let mut system = Generic { system_function, state };
 
+    let new_param = engine::integrate(&mut system, odeparam, odesolver, tstops, output_tstops, &mut trajectory, &mut stats)
+        .unwrap_or_else(|erro| panic!("Not possible to store the data in memory. Error: {}", erro));
+
+    SolveResult { times : trajectory.times, states : trajectory.states, system : system.state, odeparam : new_param, stats }
 }
 
 
+/// Similar to `solve_ode_generic` but the output is given to an `OutputSink`, as in `solve_ode_to_sink`.
+pub fn solve_ode_generic_to_sink <T : Scalar, S : VectorSpace<T>, SysFunc : FnMut (f64, &S) -> S, Sink : OutputSink<T> + ?Sized> (system_function: SysFunc, odeparam : ODEParam, state : S, odesolver: ODESolver, sink : &mut Sink) -> Result<(S, ODEParam), Box<dyn Error>> {
+    solve_ode_generic_tstops_to_sink(system_function, odeparam, state, odesolver, &[], false, sink)
+}
+
 
+/// Similar to `solve_ode_generic_tstops` but the output is given to an `OutputSink`, as in `solve_ode_tstops_to_sink`.
+pub fn solve_ode_generic_tstops_to_sink <T : Scalar, S : VectorSpace<T>, SysFunc : FnMut (f64, &S) -> S, Sink : OutputSink<T> + ?Sized> (system_function: SysFunc, odeparam : ODEParam, state : S, odesolver: ODESolver, tstops : &[f64], output_tstops : bool, sink : &mut Sink) -> Result<(S, ODEParam), Box<dyn Error>> {
+    let mut stats = SolveStats::default();
+    let mut system = Generic { system_function, state };
+    let new_param = engine::integrate(&mut system, odeparam, odesolver, tstops, output_tstops, sink, &mut stats)?;
+
+    match stats.termination {
+        Termination::Error(erro) => Err(erro.into()),
+        _ => Ok((system.state, new_param)),
+    }
+}
 
 
 
@@ -207,42 +359,26 @@ fn integrator_closure<SysFunc : FnMut (f64, &State) -> DState> (odeparam : ODEPa
 ///
 /// The first column of data is the times, the second onwards are the values of the state at that particular time. It has an `header: Option<String>` that when given a Some(String) will add the string as a header in the data file.
 pub fn data_to_file(data:&Data,file_as_string:String, header : Option<String>) -> Result<(), Box<dyn Error>> {
-    let file = File::create(file_as_string);
-
-    let mut file = match file {
-        Ok(f) => f,
-        Err(erro) => {
-            eprintln!("Not possible to create/find the file for exporting the data. Error: {}", erro);
-            return Err(Box::new(erro) as Box<dyn Error>);},
-    };
-    
-    if let Some(headerstring) = header {
-        match writeln!(file,"{}", headerstring){
-            Ok(_) => (),
-            Err(erro) => {
-            eprintln!("Error in writing to file. Error: {}", erro);
-            return Err(Box::new(erro) as Box<dyn Error>);},
-        }
-    }
+    engine::rows_to_file(data, file_as_string, header)
+}
 
-    for datum in data {
-        let mut string = String::new();
-        for value in datum {
-             string += &format!("{:10.8e}\t",value).to_string();
-        }
-        match writeln!(file,"{}", string) {
-            Ok(_) => (),
-            Err(erro) => {
-            eprintln!("Error in writing to file. Error: {}", erro);
-            return Err(Box::new(erro) as Box<dyn Error>);},
-        }
+
+/// Saves the data to a given filename/filepath with labelled columns.
+///
+/// Similar to `data_to_file`, but the header is made of the `labels` of the columns (separated by tabs), which are checked against the width of the rows of data.
+pub fn data_to_file_labelled(data:&Data, file_as_string:String, labels : &[&str]) -> Result<(), Box<dyn Error>> {
+    if let Some(datum) = data.iter().find(|datum| datum.len() != labels.len()) {
+        let erro = std::io::Error::new(std::io::ErrorKind::InvalidInput,
+            format!("{} labels given for rows with {} columns", labels.len(), datum.len()));
+        eprintln!("Labels do not match the data. Error: {}", erro);
+        return Err(Box::new(erro) as Box<dyn Error>);
     }
 
-    Ok(())
+    data_to_file(data, file_as_string, Some(labels.join("\t")))
 }
 
 
-/// Solves the ODE but at each iteration exports the values to a file. So it does not keep a data vector in memory.
+/// Solves the ODE but at each iteration exports the values to a file. So it does not keep a data vector in memory. Returns the ODEParam with updated values, or the error in creating/writing the file.
 ///
 ///# Inputs
 ///
@@ -253,54 +389,10 @@ pub fn data_to_file(data:&Data,file_as_string:String, header : Option<String>) -
 ///`odesolver: ODESolver`. A choice of an ODE solver.
 ///
 ///`filestr: String`. String with a given filename/filepath to save the data
-pub fn solve_ode_to_file <SysFunc : FnMut (f64,&State) -> DState> (mut system_function: SysFunc, odeparam : ODEParam, state : State, odesolver : ODESolver, filestr : String ) -> () {
-
-    
-    let tini = odeparam.time;
-    let tend = odeparam.tend;
-    // let step = odeparam.step;
-    // let ratio_step_output = odeparam.ratio_step_output;
-
-
-    let mut file = File::create(filestr).unwrap();
-    
-    let mut datum: Vec<f64> = vec!(tini);
-    datum.append(&mut state.clone());
-    
-    
-    let mut string = String::new();
-    for value in datum {
-            string += &format!("{:10.8e}\t",value).to_string();
-    }
-    writeln!(file,"{}", string).unwrap();
-
-    let mut new_param : ODEParam = odeparam;
-    let mut new_state : State = state;
-    
-    let solver = match odesolver {
-        ODESolver::RK4 => rk4_closure,
-        ODESolver::Euler => euler_closure,
-    };
-    
-    loop {
-        (new_state, new_param) =
-            integrator_closure(new_param, &mut system_function, new_state, solver);
-
-        let new_time = new_param.time;
-
-        datum = vec!(new_time);
-        datum.append(&mut new_state.clone());
-        
-        string.clear();
-        for value in datum {
-                string += &format!("{:10.8e}\t",value).to_string();
-        }
-        writeln!(file,"{}", string).unwrap();
+pub fn solve_ode_to_file <SysFunc : FnMut (f64,&State) -> DState> (system_function: SysFunc, odeparam : ODEParam, state : State, odesolver : ODESolver, filestr : String ) -> Result<ODEParam, Box<dyn Error>> {
 
-        if (new_time - tend).abs() < 1.0e-10 {
-            break;
-        }
-    }
+    let mut sink = TextFileSink::new(filestr, None)?;
+    let (_, new_param) = solve_ode_to_sink(system_function, odeparam, state, odesolver, &mut sink)?;
 
+    Ok(new_param)
 }
-
@@ -1,5 +1,10 @@
 pub use super::*;
 use dyn_clone::DynClone;
+use crate::sink::OutputSink;
+pub use crate::result::{SolveResult, SolveStats, Termination};
+use crate::result::{Trajectory, capacity};
+use crate::engine::{System, Workspace};
+pub use crate::dual::{Dual, Real};
 
 /// Vector of the state of the system
 pub type State = Vec<f64>;
@@ -20,48 +25,114 @@ pub trait ODESystem : DynClone {
     ///Returns the differential of the state of the system.
     fn dstate (&self, time : f64) -> DState;
 
+    ///Returns the differential of the system at a given `state`, which is not the actual one. It is used by the solvers at the intermediate stages of a step.
+    ///
+    ///The default implementation clones the system, updates its state and calls `dstate`. Implement it to avoid the clone in systems with large parameters.
+    fn dstate_at (&self, time : f64, state : &State) -> DState {
+        let mut sist = dyn_clone::clone_box(self);
+        sist.update_state(state.clone());
+        sist.dstate(time)
+    }
+
     ///Updates the state of the system.
     fn update_state (&mut self, state : State);
+
+    ///Exact Jacobian of the differential at a given `state` (`jacobian[i][j]` is the derivative of the component `i` of the differential with respect to the component `j` of the state), used by the implicit solvers (`ODESolver::Lsoda`, `Ros3p`, `Rodas4`, `Radau5`, `GaussLegendre`, `BackwardEuler` and `Trapezoidal`).
+    ///
+    ///The default implementation returns `None` and the solvers compute it by finite differences. A `GenericODESystem` solved with `solve_ode_generic_system` uses its `GenericODESystem::jacobian_at` instead, without implementing this method.
+    fn exact_jacobian (&self, _time : f64, _state : &State) -> Option<Vec<Vec<f64>>> {
+        None
+    }
 }
 
 dyn_clone::clone_trait_object!(ODESystem);
 
 
-type Solver<Sist> = fn (sist: &Sist, step : f64, time : f64) -> State;
+///Opt-in generic variant of `ODESystem`: the differential is written once for any `T : Real`, so it can be evaluated with `f64` and with dual numbers, which give the exact Jacobian of the system (forward mode automatic differentiation, see the module `dual`).
+///
+///`ODESystem::dstate` can just call `dstate_generic` with the actual state, as `self.dstate_generic(time, self.state())`. Solved with `solve_ode_generic_system`, the implicit solvers use the exact Jacobian of `jacobian_at`.
+pub trait GenericODESystem : ODESystem {
 
+    ///Returns the differential of the system at a given `state`, for any real scalar `T`. The parameters of the system are converted with `T::from_f64`.
+    fn dstate_generic<T : Real> (&self, time : f64, state : &[T]) -> Vec<T>;
 
-fn integrator<Sist: ODESystem> (sist : Sist, odeparam : ODEParam, solver : Solver<Sist>) -> (Sist, ODEParam)
-{
-    let mut time = odeparam.time;
-    let tend = odeparam.tend;
-    let step = odeparam.step;
-    let ratio_step_output = odeparam.ratio_step_output;
-    // let solver = odeparam.solver;
-    let mut new_state;  // = sist.state().clone();
-    let mut new_sist = sist; 
-
-    for _i in  0 .. ratio_step_output {
-        if (time + step) - tend > 0.0  {
-            let new_step = tend - time;
-            new_state = (solver)(&new_sist, new_step, time);
-            new_sist.update_state(new_state);
-            time = tend;
-            break;
-        } else {
-            new_state = (solver)(&new_sist, step, time);
-            new_sist.update_state(new_state);
-            time += step;
+    ///Exact Jacobian of the differential at the actual state: `jacobian[i][j]` is the derivative of the component `i` of the differential with respect to the component `j` of the state.
+    fn jacobian (&self, time : f64) -> Vec<Vec<f64>> {
+        self.jacobian_at(time, self.state())
+    }
+
+    ///Exact Jacobian of the differential at a given `state`.
+    fn jacobian_at (&self, time : f64, state : &State) -> Vec<Vec<f64>> {
+        dual::jacobian(|duals : &[Dual]| self.dstate_generic(time, duals), state)
+    }
+}
+
+
+// System adapted to the engine
+struct Adapter<'a, Sist>(&'a mut Sist);
+
+impl<Sist : ODESystem> System for Adapter<'_, Sist> {
+    type State = State;
+
+    fn state (&self) -> &State {
+        self.0.state()
+    }
+
+    fn dstate (&mut self, time : f64, dstate : &mut State) {
+        *dstate = self.0.dstate(time);
+    }
+
+    fn dstate_at (&mut self, time : f64, state : &State, dstate : &mut State) {
+        *dstate = self.0.dstate_at(time, state);
+    }
+
+    fn update_state (&mut self, state : &State) {
+        self.0.update_state(state.clone());
+    }
+
+    fn jacobian_at (&mut self, time : f64, state : &State, jacobian : &mut [State]) -> bool {
+        match self.0.exact_jacobian(time, state) {
+            Some(exact) => {
+                jacobian.clone_from_slice(&exact);
+                true
+            },
+            None => false,
         }
     }
+}
 
-    let new_param = ODEParam {
-        time, .. odeparam};
-    
-    (new_sist, new_param)
+
+// System adapted to the engine, with the exact Jacobian of the dual numbers
+struct GenericAdapter<'a, Sist>(&'a mut Sist);
+
+impl<Sist : GenericODESystem> System for GenericAdapter<'_, Sist> {
+    type State = State;
+
+    fn state (&self) -> &State {
+        self.0.state()
+    }
+
+    fn dstate (&mut self, time : f64, dstate : &mut State) {
+        *dstate = self.0.dstate(time);
+    }
+
+    fn dstate_at (&mut self, time : f64, state : &State, dstate : &mut State) {
+        *dstate = self.0.dstate_at(time, state);
+    }
+
+    fn update_state (&mut self, state : &State) {
+        self.0.update_state(state.clone());
+    }
+
+    fn jacobian_at (&mut self, time : f64, state : &State, jacobian : &mut [State]) -> bool {
+        let exact = self.0.exact_jacobian(time, state).unwrap_or_else(|| GenericODESystem::jacobian_at(self.0, time, state));
+        jacobian.clone_from_slice(&exact);
+        true
+    }
 }
 
-              
-/// Main function for solving ODEs, returns an tuple with (Data, Sist, ODEParam) where Sist and ODEParam have updated values.
+
+/// Main function for solving ODEs, returns a `SolveResult` with the output times and states, the updated Sist and ODEParam and the statistics of the integration.
 ///
 ///# inputs
 ///
@@ -70,263 +141,140 @@ fn integrator<Sist: ODESystem> (sist : Sist, odeparam : ODEParam, solver : Solve
 ///`odeparam : ODEParam`. An value of ODEParam.
 ///
 ///`odesolver : ODESolver`. A choice of an ODE solver.
-pub fn solve_ode<Sist: ODESystem + Clone> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver) -> (Data, Sist, ODEParam) {
-    
-    let tini = odeparam.time;
-    let tend = odeparam.tend;
-    let step = odeparam.step;
-    let ratio_step_output = odeparam.ratio_step_output;
+pub fn solve_ode<Sist: ODESystem + Clone> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver) -> SolveResult<Sist> {
+    solve_ode_tstops(sist, odeparam, odesolver, &[], false)
+}
 
-    let capacity = ((tend-tini)/(step*(ratio_step_output as f64)) + 10.0) as usize;
-    
-    let mut data : Vec<Vec<f64>> = Vec::<Vec<f64>>::with_capacity(capacity);
 
-    let mut datum: Vec<f64> = vec!(tini);
-    datum.append(&mut sist.state().clone());
-    
-    data.push(datum);
-    
-    let mut new_param  = odeparam;
-    let mut new_sist  = sist;
-    
-    let solver = match odesolver {
-        ODESolver::RK4 => rk4,
-        ODESolver::Euler => euler,
-    };
+/// Similar to `solve_ode` but the integrator lands exactly at each time of `tstops` (input steps, dosing events, scheduled switches...), shortening the step before it without changing `ODEParam.step`. The step after it ends at the next time of the grid of `ODEParam.step`, so the output times are not shifted, and the adaptive solvers start again from the tstop. If `output_tstops` is true the points at the `tstops` are also added to the data.
+pub fn solve_ode_tstops<Sist: ODESystem + Clone> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver, tstops : &[f64], output_tstops : bool) -> SolveResult<Sist> {
     
-    loop {
-        (new_sist, new_param) =
-            integrator::<Sist>(new_sist,new_param,solver);
+    let mut trajectory = Trajectory::with_capacity(capacity(&odeparam));
+    let mut stats = SolveStats::default();
 
-        let new_time = new_param.time;
+    let mut new_sist = sist;
 
-        datum = vec!(new_time);
-        datum.append(&mut new_sist.state().clone());
-        
-        data.push(datum);
+    let new_param = engine::integrate(&mut Adapter(&mut new_sist), odeparam, odesolver, tstops, output_tstops, &mut trajectory, &mut stats)
+        .unwrap_or_else(|erro| panic!("Not possible to store the data in memory. Error: {}", erro));
 
-        if (new_time - tend).abs() < 1.0e-10 {
-            break;
-        }
-    }
-
-    (data, new_sist, new_param)
+    SolveResult { times : trajectory.times, states : trajectory.states, system : new_sist, odeparam : new_param, stats }
 }
 
 
-
-fn euler<Sist: ODESystem> (sist: &Sist, step : f64, time : f64) -> State {
-    let state = sist.state();
-    let mut xps = sist.dstate(time);// funcSist(time, &state);
-    for (x,xp) in state.iter().zip(xps.iter_mut()) {
-        *xp = (*x) + step*(*xp);
-    }
-
-    xps
+/// Similar to `solve_ode` but the output is given to an `OutputSink` (in memory data, files, channels...) instead of being returned. Returns a tuple with (Sist, ODEParam) with updated values, or the error of the sink.
+pub fn solve_ode_to_sink<Sist: ODESystem + Clone, Sink: OutputSink + ?Sized> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver, sink : &mut Sink) -> Result<(Sist, ODEParam), Box<dyn Error>> {
+    solve_ode_tstops_to_sink(sist, odeparam, odesolver, &[], false, sink)
 }
 
 
-fn rk4<Sist: ODESystem + Clone> (sist: &Sist, step : f64, time : f64) -> State {    
-    
-    let state = sist.state();
-    let k1 = sist.dstate(time);
-    
-    
-    let time2 = time + 0.5*step;
-    let xs2 : Vec<f64> = state.iter().zip(&k1).map(|(x,k)|(*x) + 0.5 * k * step).collect();
+/// Similar to `solve_ode_tstops` but the output is given to an `OutputSink`, as in `solve_ode_to_sink`. If the state stops being finite the integration is stopped and an error is returned.
+pub fn solve_ode_tstops_to_sink<Sist: ODESystem + Clone, Sink: OutputSink + ?Sized> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver, tstops : &[f64], output_tstops : bool, sink : &mut Sink) -> Result<(Sist, ODEParam), Box<dyn Error>> {
+    let mut stats = SolveStats::default();
+    let mut new_sist = sist;
+    let new_param = engine::integrate(&mut Adapter(&mut new_sist), odeparam, odesolver, tstops, output_tstops, sink, &mut stats)?;
 
-    let mut sist2 = sist.clone();
-    sist2.update_state(xs2);
-    let k2 = sist2.dstate(time2);
-
-    let time3 = time2;
-    let xs3: Vec<f64> = state.iter().zip(&k2)
-        .map(|(x,kb)| (*x) + 0.5 * kb * step)
-        .collect();
-    
-    let mut sist3 = sist.clone();
-    sist3.update_state(xs3);
-    let k3 = sist3.dstate(time3);
-    
-    let time4 = time + step;
-    let xs4:Vec<f64> = state.iter().zip(&k3)
-        .map(|(x,kc)| (*x) + kc * step).collect();
-    
-    let mut sist4 = sist.clone();
-    sist4.update_state(xs4);
-    let k4 = sist4.dstate(time4); 
-    
-    let mut output = Vec::<f64>::new(); 
-    for (i,_ka) in k1.iter().enumerate(){
-        output.push(state[i] + step*(1.0/6.0)*(k1[i] + 2.0*k2[i] + 2.0*k3[i] + k4[i]));
+    match stats.termination {
+        Termination::Error(erro) => Err(erro.into()),
+        _ => Ok((new_sist, new_param)),
     }
-
-    output
 }
 
 
-pub use crate::solver_vector::data_to_file;
-
-
-///Similar to `solve_ode` but uses `sist` as `&mut Sist` in order to not consume the original, instead update the given one.
-///Also as it doesn't consume the caller `sist` it does not return an updated `Sist`
-pub fn solve_ode_sist_mut<Sist: ODESystem + Clone> (sist : &mut Sist, odeparam : ODEParam, odesolver: ODESolver) -> (Data, ODEParam) {
-    
-    let tini = odeparam.time;
-    let tend = odeparam.tend;
-    let step = odeparam.step;
-    let ratio_step_output = odeparam.ratio_step_output;
+/// Similar to `solve_ode` for a `GenericODESystem`: the implicit solvers use the exact Jacobian given by `GenericODESystem::jacobian_at` (dual numbers, unless `ODESystem::exact_jacobian` is implemented) instead of finite differences.
+pub fn solve_ode_generic_system<Sist: GenericODESystem + Clone> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver) -> SolveResult<Sist> {
+    solve_ode_generic_system_tstops(sist, odeparam, odesolver, &[], false)
+}
 
-    let capacity = ((tend-tini)/(step*(ratio_step_output as f64)) + 10.0) as usize;
-    
-    let mut data : Vec<Vec<f64>> = Vec::<Vec<f64>>::with_capacity(capacity);
 
-    let mut datum: Vec<f64> = vec!(tini);
-    datum.append(&mut sist.state().clone());
-    
-    data.push(datum);
-    
-    let mut new_param  = odeparam;
-    // let mut new_sist  = sist;
-    
-    let solver = match odesolver {
-        ODESolver::RK4 => rk4,
-        ODESolver::Euler => euler,
-    };
-    
-    loop {
-        new_param =
-            integrator_sist_mut::<Sist>(sist,new_param,solver);
+/// Similar to `solve_ode_generic_system` but the integrator lands exactly at each time of `tstops`, as in `solve_ode_tstops`.
+pub fn solve_ode_generic_system_tstops<Sist: GenericODESystem + Clone> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver, tstops : &[f64], output_tstops : bool) -> SolveResult<Sist> {
 
-        let new_time = new_param.time;
+    let mut trajectory = Trajectory::with_capacity(capacity(&odeparam));
+    let mut stats = SolveStats::default();
 
-        datum = vec!(new_time);
-        datum.append(&mut sist.state().clone());
-        
-        data.push(datum);
+    let mut new_sist = sist;
 
-        if (new_time - tend).abs() < 1.0e-10 {
-            break;
-        }
-    }
+    let new_param = engine::integrate(&mut GenericAdapter(&mut new_sist), odeparam, odesolver, tstops, output_tstops, &mut trajectory, &mut stats)
+        .unwrap_or_else(|erro| panic!("Not possible to store the data in memory. Error: {}", erro));
 
-    (data, new_param)
+    SolveResult { times : trajectory.times, states : trajectory.states, system : new_sist, odeparam : new_param, stats }
 }
 
 
+/// Similar to `solve_ode_generic_system` but the output is given to an `OutputSink`, as in `solve_ode_to_sink`.
+pub fn solve_ode_generic_system_to_sink<Sist: GenericODESystem + Clone, Sink: OutputSink + ?Sized> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver, sink : &mut Sink) -> Result<(Sist, ODEParam), Box<dyn Error>> {
+    solve_ode_generic_system_tstops_to_sink(sist, odeparam, odesolver, &[], false, sink)
+}
 
 
+/// Similar to `solve_ode_generic_system_tstops` but the output is given to an `OutputSink`, as in `solve_ode_tstops_to_sink`.
+pub fn solve_ode_generic_system_tstops_to_sink<Sist: GenericODESystem + Clone, Sink: OutputSink + ?Sized> (sist : Sist, odeparam : ODEParam, odesolver: ODESolver, tstops : &[f64], output_tstops : bool, sink : &mut Sink) -> Result<(Sist, ODEParam), Box<dyn Error>> {
+    let mut stats = SolveStats::default();
+    let mut new_sist = sist;
+    let new_param = engine::integrate(&mut GenericAdapter(&mut new_sist), odeparam, odesolver, tstops, output_tstops, sink, &mut stats)?;
 
-fn integrator_sist_mut<Sist: ODESystem> (sist : &mut Sist, odeparam : ODEParam, solver : Solver<Sist>) -> ODEParam
-{
-    let mut time = odeparam.time;
-    let tend = odeparam.tend;
-    let step = odeparam.step;
-    let ratio_step_output = odeparam.ratio_step_output;
-    // let solver = odeparam.solver;
-    let mut new_state;  // = sist.state().clone();
-    // let mut new_sist = sist; 
-
-    for _i in  0 .. ratio_step_output {
-        if (time + step) - tend > 0.0  {
-            let new_step = tend - time;
-            new_state = (solver)(&sist, new_step, time);
-            // new_sist.update_state(new_state);
-            sist.update_state(new_state);
-            time = tend;
-            break;
-        } else {
-            new_state = (solver)(&sist, step, time);
-            // new_sist.update_state(new_state);
-            sist.update_state(new_state);
-            time += step;
-        }
+    match stats.termination {
+        Termination::Error(erro) => Err(erro.into()),
+        _ => Ok((new_sist, new_param)),
     }
-
-    let new_param = ODEParam {
-        time, .. odeparam};
-    
-    new_param
 }
 
 
-fn integrator_sist_mut_no_data<Sist: ODESystem> (sist : &mut Sist, odeparam : ODEParam, solver : Solver<Sist>) -> ODEParam
-{
-    let mut time = odeparam.time;
-    let tend = odeparam.tend;
-    let step = odeparam.step;
-    // let ratio_step_output = odeparam.ratio_step_output;
-    // let solver = odeparam.solver;
-    let mut new_state;  // = sist.state().clone();
-    // let mut new_sist = sist; 
-
-    // for _i in  0 .. ratio_step_output {
-    loop {
-
-        if (time + step) - tend > 0.0  {
-            let new_step = tend - time;
-            new_state = (solver)(&sist, new_step, time);
-            // new_sist.update_state(new_state);
-            sist.update_state(new_state);
-            time = tend;
-            break;
-        } 
-
-        new_state = (solver)(&sist, step, time);
-        // new_sist.update_state(new_state);
-        sist.update_state(new_state);
-        time += step;
-    }
+pub use crate::solver_vector::data_to_file;
 
-    let new_param = ODEParam {
-        time, .. odeparam};
-    
-    new_param
+
+///Similar to `solve_ode` but uses `sist` as `&mut Sist` in order to not consume the original, instead update the given one.
+///Also as it doesn't consume the caller `sist` the `system` of the returned `SolveResult` is empty.
+pub fn solve_ode_sist_mut<Sist: ODESystem + Clone> (sist : &mut Sist, odeparam : ODEParam, odesolver: ODESolver) -> SolveResult<()> {
+    solve_ode_sist_mut_tstops(sist, odeparam, odesolver, &[], false)
 }
 
 
-///Similar to `solve_ode_sist_mut` but in that case you are only interested in how the system evolves, not in the generated data.
-pub fn solve_ode_sist_mut_no_data<Sist: ODESystem + Clone> (sist : &mut Sist, odeparam : ODEParam, odesolver: ODESolver) -> ODEParam {
+///Similar to `solve_ode_sist_mut` but the integrator lands exactly at each time of `tstops`, as in `solve_ode_tstops`.
+pub fn solve_ode_sist_mut_tstops<Sist: ODESystem + Clone> (sist : &mut Sist, odeparam : ODEParam, odesolver: ODESolver, tstops : &[f64], output_tstops : bool) -> SolveResult<()> {
     
-    let tini = odeparam.time;
-    let tend = odeparam.tend;
-    // let step = odeparam.step;
-    // let ratio_step_output = odeparam.ratio_step_output;
+    let mut trajectory = Trajectory::with_capacity(capacity(&odeparam));
+    let mut stats = SolveStats::default();
 
-    let mut new_param  = odeparam;
-    
-    let solver = match odesolver {
-        ODESolver::RK4 => rk4,
-        ODESolver::Euler => euler,
-    };
+    let new_param = engine::integrate(&mut Adapter(sist), odeparam, odesolver, tstops, output_tstops, &mut trajectory, &mut stats)
+        .unwrap_or_else(|erro| panic!("Not possible to store the data in memory. Error: {}", erro));
 
-    let mut new_time = tini;
-    
-    loop {
+    SolveResult { times : trajectory.times, states : trajectory.states, system : (), odeparam : new_param, stats }
+}
+
+
+///Similar to `solve_ode_sist_mut` but the output is given to an `OutputSink`, as in `solve_ode_to_sink`. Returns the ODEParam with updated values, or the error of the sink.
+pub fn solve_ode_sist_mut_to_sink<Sist: ODESystem + Clone, Sink: OutputSink + ?Sized> (sist : &mut Sist, odeparam : ODEParam, odesolver: ODESolver, sink : &mut Sink) -> Result<ODEParam, Box<dyn Error>> {
+    solve_ode_sist_mut_tstops_to_sink(sist, odeparam, odesolver, &[], false, sink)
+}
 
-        if (new_time - tend).abs() < 1.0e-10 {
-            break;
-        }
-        
-        new_param = integrator_sist_mut_no_data::<Sist>(sist,new_param,solver);
 
-        new_time = new_param.time;
+///Similar to `solve_ode_sist_mut_tstops` but the output is given to an `OutputSink`, as in `solve_ode_tstops_to_sink`.
+pub fn solve_ode_sist_mut_tstops_to_sink<Sist: ODESystem + Clone, Sink: OutputSink + ?Sized> (sist : &mut Sist, odeparam : ODEParam, odesolver: ODESolver, tstops : &[f64], output_tstops : bool, sink : &mut Sink) -> Result<ODEParam, Box<dyn Error>> {
+    let mut stats = SolveStats::default();
+    let new_param = engine::integrate(&mut Adapter(sist), odeparam, odesolver, tstops, output_tstops, sink, &mut stats)?;
 
+    match stats.termination {
+        Termination::Error(erro) => Err(erro.into()),
+        _ => Ok(new_param),
     }
+}
+
 
-    new_param
+///Similar to `solve_ode_sist_mut` but in that case you are only interested in how the system evolves, not in the generated data.
+pub fn solve_ode_sist_mut_no_data<Sist: ODESystem + Clone> (sist : &mut Sist, odeparam : ODEParam, odesolver: ODESolver) -> ODEParam {
+    engine::advance(&mut Adapter(sist), odeparam, odesolver)
 }
 
 ///Simplified version of `solve_ode_mut_no_data` where only one step of the integration is made.
 ///The system evolves from `time` to `time + step`
 pub fn solve_ode_step_sist_mut_no_data<Sist: ODESystem + Clone> (sist : &mut Sist, time: f64, step: f64 , odesolver: ODESolver) 
 {
-    let solver = match odesolver {
-        ODESolver::RK4 => rk4,
-        ODESolver::Euler => euler,
-    };
-
-    let new_state = (solver)(sist, step, time);
-    sist.update_state(new_state);
+    let mut workspace = Workspace::new(sist.state());
+    engine::step(&mut Adapter(sist), time, step, odesolver, &mut workspace);
 }
 
+// One step as `solve_ode_step_sist_mut_no_data`, with the `workspace` of the previous steps and counted in `stats`
+pub(crate) fn step_sist_mut<Sist: ODESystem + Clone> (sist : &mut Sist, time : f64, step : f64, odesolver : ODESolver, workspace : &mut Workspace<State>, stats : &mut SolveStats) {
+    engine::counted_step(&mut Adapter(sist), time, step, odesolver, workspace, stats);
+}
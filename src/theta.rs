@@ -0,0 +1,66 @@
+//! One-step theta methods with the fixed step `h`, used by `ODESolver::BackwardEuler` (theta = 1, L-stable, order 1) and `ODESolver::Trapezoidal` (theta = 1/2, the Crank–Nicolson scheme, A-stable, order 2).
+//!
+//! The new state solves `y1 = y + h*(1 - theta)*f(t, y) + h*theta*f(t + h, y1)`, by simplified Newton iterations from `y1 = y` with the Jacobian J at the start of the step: the corrections solve `(I/(h*theta) - J)*delta = f(t + h, y1) + (y + h*(1 - theta)*f(t, y) - y1)/(h*theta)`.
+
+use crate::engine::{Scalar, SolveStats, System, VectorSpace, Workspace};
+use crate::implicit::{self, factorize, solve_in_place};
+use crate::{ODESolver, Tolerance};
+
+
+// The Newton iterations go down almost to the rounding errors, the error of the method being given by the step
+const NEWTON_TOLERANCE : Tolerance = Tolerance { rtol : 1.0e-12, atol : 1.0e-12 };
+const NEWTON_ITERATIONS : usize = 10;
+
+
+// Makes one step of `odesolver` (`BackwardEuler` or `Trapezoidal`) from `time`, with a new Jacobian. If the Newton iterations do not converge the state is set to NaN, which stops the integration.
+pub(crate) fn step<T : Scalar, Sys : System<T>> (system : &mut Sys, time : f64, step : f64, odesolver : ODESolver, workspace : &mut Workspace<Sys::State>, stats : &mut SolveStats) {
+    let theta = match odesolver {
+        ODESolver::BackwardEuler => 1.0,
+        ODESolver::Trapezoidal => 0.5,
+        _ => unreachable!("{:?} is not a theta method", odesolver),
+    };
+    let new_time = time + step;
+
+    let Workspace { k1, k2, k3, k4, stage, jacobian, .. } = workspace;
+    system.dstate(time, k4);
+    stats.fevals += 1;
+    stats.fevals += implicit::jacobian(system, time, k4, jacobian, stage, k1);
+    let lu = factorize(1.0/(step*theta), jacobian);
+
+    // explicit part `y + h*(1 - theta)*f(t, y)` and the iterate `y1`
+    k2.assign_axpy(system.state(), step*(1.0 - theta), k4);
+    k3.assign(system.state());
+
+    let mut converged = false;
+    if let Some(lu) = lu {
+        let mut previous = 0.0;
+        for iteration in 0 .. NEWTON_ITERATIONS {
+            system.dstate_at(new_time, k3, k1);
+            stats.fevals += 1;
+            k1.axpy(1.0/(step*theta), k2);
+            k1.axpy(-1.0/(step*theta), k3);
+            solve_in_place(&lu, k1);
+            k3.axpy(1.0, k1);
+
+            let mut sum = 0.0;
+            for (d, y) in k1.components().iter().zip(k3.components()) {
+                sum += (d.magnitude()/(NEWTON_TOLERANCE.atol + NEWTON_TOLERANCE.rtol*y.magnitude())).powi(2);
+            }
+            let norm = (sum/k1.components().len().max(1) as f64).sqrt();
+
+            if !norm.is_finite() || (iteration > 0 && norm >= 0.99*previous) {
+                break;
+            }
+            if norm <= 1.0 {
+                converged = true;
+                break;
+            }
+            previous = norm;
+        }
+    }
+
+    if !converged {
+        k3.components_mut().fill(T::from_f64(f64::NAN));
+    }
+    system.update_state(k3);
+}